@@ -1,6 +1,8 @@
 use std::fmt::{Display, Formatter};
 
-use crate::token::{Span, Token};
+use unicode_width::UnicodeWidthStr;
+
+use crate::token::{KEYWORDS, Span, Token};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -23,6 +25,11 @@ impl Error {
         Err(error)
     }
 
+    /// The line the error starts on, counting from 1
+    pub(crate) fn line(&self) -> u16 {
+        self.start.line
+    }
+
     // -----------------------------------------------------------------------------
     //   - Lex errors -
     // -----------------------------------------------------------------------------
@@ -30,20 +37,70 @@ impl Error {
         Self::err(ErrorKind::InvalidInteger, (span, span), source)
     }
 
+    pub(crate) fn int_overflow<T>(span: Span, source: impl Into<String>) -> Result<T> {
+        Self::err(ErrorKind::IntegerOverflow, (span, span), source)
+    }
+
+    pub(crate) fn invalid_duration<T>(span: Span, source: impl Into<String>) -> Result<T> {
+        Self::err(ErrorKind::InvalidDuration, (span, span), source)
+    }
+
     pub(crate) fn unterminated_string<T>(span: Span, source: impl Into<String>) -> Result<T> {
         Self::err(ErrorKind::UnterminatedString, (span, span), source)
     }
 
+    pub(crate) fn unterminated_regex<T>(span: Span, source: impl Into<String>) -> Result<T> {
+        Self::err(ErrorKind::UnterminatedRegex, (span, span), source)
+    }
+
+    pub(crate) fn invalid_escape<T>(span: Span, source: impl Into<String>) -> Result<T> {
+        Self::err(ErrorKind::InvalidEscape, (span, span), source)
+    }
+
+    pub(crate) fn unknown_escape<T>(c: char, span: Span, source: impl Into<String>) -> Result<T> {
+        Self::err(ErrorKind::UnknownEscape(c), (span, span), source)
+    }
+
+    pub(crate) fn unsupported_version<T>(version: &str, span: Span, source: impl Into<String>) -> Result<T> {
+        Self::err(ErrorKind::UnsupportedVersion(version.into()), (span, span), source)
+    }
+
+    pub(crate) fn invalid_version<T>(version: &str, span: Span, source: impl Into<String>) -> Result<T> {
+        Self::err(ErrorKind::InvalidVersion(version.into()), (span, span), source)
+    }
+
+    // -----------------------------------------------------------------------------
+    //   - Json errors -
+    // -----------------------------------------------------------------------------
+    #[cfg(feature = "serde")]
+    pub(crate) fn invalid_json<T>(err: serde_json::Error, source: impl Into<String>) -> Result<T> {
+        let span = Span {
+            token: 0,
+            line: err.line() as u16,
+            col: err.column() as u16,
+        };
+        Self::err(ErrorKind::InvalidJson(err.to_string()), (span, span), source)
+    }
+
     // -----------------------------------------------------------------------------
     //   - Parse errors -
     // -----------------------------------------------------------------------------
-    pub(crate) fn invalid_instruction<T>(token: Token, spans: (Span, Span), source: impl Into<String>) -> Result<T> {
-        Self::err(ErrorKind::InvalidInstruction(token), spans, source)
+    pub(crate) fn invalid_instruction<T>(
+        token: Token<'_>,
+        spans: (Span, Span),
+        source: impl Into<String>,
+    ) -> Result<T> {
+        let suggestion = match token {
+            Token::Ident(ident) => closest_instruction(ident),
+            _ => None,
+        };
+        let kind = ErrorKind::InvalidInstruction(token.to_string(), suggestion);
+        Self::err(kind, spans, source)
     }
 
     pub(crate) fn invalid_arg<T>(
         expected: &'static str,
-        token: Token,
+        token: Token<'_>,
         spans: (Span, Span),
         source: impl Into<String>,
     ) -> Result<T> {
@@ -57,9 +114,17 @@ impl Error {
         )
     }
 
+    pub(crate) fn unclosed_block<T>(spans: (Span, Span), source: impl Into<String>) -> Result<T> {
+        Self::err(ErrorKind::UnclosedBlock, spans, source)
+    }
+
+    pub(crate) fn unbalanced_brace<T>(spans: (Span, Span), source: impl Into<String>) -> Result<T> {
+        Self::err(ErrorKind::UnbalancedBrace, spans, source)
+    }
+
     pub(crate) fn unexpected_token<T>(
         expected: &'static str,
-        token: Token,
+        token: Token<'_>,
         spans: (Span, Span),
         source: impl Into<String>,
     ) -> Result<T> {
@@ -84,19 +149,85 @@ impl Display for Error {
 
         let row_width = (from + to - 1).to_string().len();
 
-        writeln!(f)?;
+        writeln!(f, "error at {}:{}: {}", self.start.line, self.start.col, self.kind)?;
         for (no, line) in lines {
             let gutter = format!("{:>row_width$}: ", no + 1);
-            writeln!(f, "{gutter}{line}")?;
+            writeln!(f, "{gutter}{}", expand_tabs(line))?;
             if no + 1 == self.start.line as usize {
-                let indent = " ".repeat(gutter.len());
-                writeln!(f, "{indent}^-- {}", self.kind)?;
+                let (offset, width) = self.caret(line);
+                let indent = " ".repeat(gutter.len() + offset);
+                writeln!(f, "{indent}^{} {}", "~".repeat(width - 1), self.kind)?;
             }
         }
         Ok(())
     }
 }
 
+impl Error {
+    // The display column of the start of the span in the line with expanded tabs,
+    // and how many columns the span takes up to the end of the line
+    fn caret(&self, line: &str) -> (usize, usize) {
+        let start = self.start.col.saturating_sub(1) as usize;
+        let len =
+            if self.end.line == self.start.line { self.end.col.saturating_sub(self.start.col) as usize } else { 0 };
+
+        let before = line.chars().take(start).collect::<String>();
+        let spanned = line.chars().take(start + len).collect::<String>();
+        let offset = expand_tabs(&before).width();
+        let width = expand_tabs(&spanned).width() - offset;
+        (offset, width.max(1))
+    }
+}
+
+// The instruction keyword closest to a misspelled one, if it is only a couple of edits away
+fn closest_instruction(ident: &str) -> Option<&'static str> {
+    const MAX_DISTANCE: usize = 2;
+
+    KEYWORDS
+        .iter()
+        .filter(|(_, token)| token.starts_instruction())
+        .map(|(keyword, _)| (edit_distance(ident, keyword), *keyword))
+        .filter(|(distance, _)| *distance <= MAX_DISTANCE)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, keyword)| keyword)
+}
+
+// The Levenshtein distance: the number of characters to insert, remove or replace
+// to turn one string into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a != *b);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+// Replace every tab with spaces up to the next multiple of `TAB_WIDTH` columns,
+// so the caret lines up with the source whatever the width of a tab in the terminal
+fn expand_tabs(line: &str) -> String {
+    const TAB_WIDTH: usize = 4;
+
+    let mut output = String::with_capacity(line.len());
+    for c in line.chars() {
+        match c {
+            '\t' => {
+                let spaces = TAB_WIDTH - output.width() % TAB_WIDTH;
+                output.extend(std::iter::repeat_n(' ', spaces));
+            }
+            c => output.push(c),
+        }
+    }
+    output
+}
+
 impl std::error::Error for Error {
 }
 
@@ -104,11 +235,25 @@ impl std::error::Error for Error {
 pub enum ErrorKind {
     // Lex errors
     UnterminatedString,
+    UnterminatedRegex,
     InvalidInteger,
+    IntegerOverflow,
+    InvalidDuration,
+    InvalidEscape,
+    UnknownEscape(char),
+    InvalidVersion(String),
+    UnsupportedVersion(String),
+
+    // Json errors
+    #[cfg(feature = "serde")]
+    InvalidJson(String),
 
     // Parse errors
     InvalidArg { expected: &'static str, found: String },
-    InvalidInstruction(Token),
+    /// The token, and the instruction it is probably a misspelling of
+    InvalidInstruction(String, Option<&'static str>),
+    UnclosedBlock,
+    UnbalancedBrace,
     UnexpectedToken { expected: &'static str, found: String },
 }
 
@@ -116,9 +261,25 @@ impl Display for ErrorKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             ErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            ErrorKind::UnterminatedRegex => write!(f, "unterminated regex"),
             ErrorKind::InvalidInteger => write!(f, "invalid integer"),
+            ErrorKind::IntegerOverflow => write!(f, "integer out of range"),
+            ErrorKind::InvalidDuration => write!(f, "invalid duration, expected a number followed by `ms`, `s` or `m`"),
+            ErrorKind::InvalidEscape => write!(f, "invalid escape, expected `\\x` followed by two hex digits (00-7f)"),
+            ErrorKind::UnknownEscape(c) => write!(f, "unknown escape `\\{c}`"),
+            ErrorKind::InvalidVersion(version) => write!(f, "invalid version: `{version}`"),
+            ErrorKind::UnsupportedVersion(version) => {
+                write!(f, "requires version {version} but this is version {}", env!("CARGO_PKG_VERSION"))
+            }
+            #[cfg(feature = "serde")]
+            ErrorKind::InvalidJson(err) => write!(f, "invalid json: {err}"),
             ErrorKind::InvalidArg { expected, found } => write!(f, "expected `{expected}`, found `{found}`"),
-            ErrorKind::InvalidInstruction(token) => write!(f, "invalid instruction: `{token}`"),
+            ErrorKind::InvalidInstruction(token, None) => write!(f, "invalid instruction: `{token}`"),
+            ErrorKind::InvalidInstruction(token, Some(keyword)) => {
+                write!(f, "invalid instruction: `{token}`, did you mean `{keyword}`?")
+            }
+            ErrorKind::UnbalancedBrace => write!(f, "`}}` without a block to close"),
+            ErrorKind::UnclosedBlock => write!(f, "missing `}}` at the end of the block"),
             ErrorKind::UnexpectedToken { expected, found } => {
                 write!(f, "unexpected token, `{expected}`, found `{found}`")
             }
@@ -1,9 +1,25 @@
-use std::path::PathBuf;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Dest {
     Relative { row: i32, col: i32 },
     Marker(String),
+    /// An absolute line number, starting at one
+    Line(u16),
+    /// An absolute line number stored in a variable
+    LineVariable(String),
+    /// A number of screen pages up (negative) or down (positive)
+    PageRelative(i32),
+    /// The start of a capture group in the first match of a regex
+    Capture { pattern: String, group: usize },
+    /// An absolute byte offset in the buffer
+    Offset(usize),
+    /// Where the cursor was once the most recent `type` finished
+    LastType,
 }
 
 impl From<(i32, i32)> for Dest {
@@ -18,52 +34,250 @@ impl From<&str> for Dest {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Source {
     Str(String),
     Ident(String),
+    /// A string with `${name}` replaced by the value of the variable, written `= <string>`
+    Interpolated(String),
 }
 
-#[derive(Debug, PartialEq)]
+/// What `select` selects
+#[derive(Debug, Clone, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Select {
+    /// A rectangle starting at the cursor
+    Rect { width: u16, height: u16 },
+    /// The lines between the marker and the marker with the same name ending in `_end`
+    Region(String),
+}
+
+/// What ends a `repeat until` loop
+#[derive(Debug, Clone, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Condition {
+    /// The buffer contains the text
+    BufferContains(String),
+    /// The variable holds the value
+    VarEq(String, String),
+}
+
+/// How to handle an instruction that fails
+#[derive(Debug, Default, Copy, Clone, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OnError {
+    Skip,
+    #[default]
+    Halt,
+    Retry,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
     Load(PathBuf, String),
+    /// Play the instructions of another script in place of this one,
+    /// relative to the directory of the including script
+    Include(PathBuf),
+    /// Replace the text with the content of a file and move the cursor to the start,
+    /// relative to the directory of the script
+    OpenFile(PathBuf),
     Find(String),
-    Goto(Dest),
+    /// Move the cursor a number of matches of the last `find` forward,
+    /// through the whole buffer
+    SearchNext(u16),
+    /// Move the cursor a number of matches of the last `find` backward
+    SearchPrev(u16),
+    Goto {
+        dest: Dest,
+        /// Scroll the viewport to put the cursor line in the middle
+        center: bool,
+    },
     Type {
         source: Source,
         trim_trailing_newline: bool,
         prefix_newline: bool,
+        /// Type the text from the last character to the first
+        reverse: bool,
+        /// Expand tabs to the next multiple of this many columns
+        align: Option<u16>,
+        /// Replace the common indentation of the text with the indentation
+        /// of the line the cursor is on
+        reindent: bool,
+        /// Once typed, accept this as an autocomplete suggestion
+        /// replacing the typed text
+        complete: Option<Source>,
+        /// Type a word at a time rather than a character at a time
+        by_word: bool,
+        /// Once typed, erase the end of the text and type this correction instead
+        fix: Option<Source>,
+        /// Pause after punctuation and newlines
+        natural: bool,
+        /// Milliseconds to pause before typing
+        think: u64,
+        /// Indent every line by the depth of the `{` and `}` before it
+        autoindent: bool,
+        /// Pause at every newline of the text, rather than for the `linepause`
+        line_pause: Option<Duration>,
+        /// Close brackets and quotes as they are opened, typing over the closers
+        autopair: bool,
+        /// Only type these lines of the variable, counting from 1, the end included
+        lines: Option<(usize, usize)>,
+    },
+    /// Type the lines appended to a file for a number of seconds
+    Tail {
+        path: PathBuf,
+        timeout: u64,
     },
     Insert(Source),
+    /// Insert the source in front of every occurrence of the needle at once
+    InsertEach {
+        needle: String,
+        source: Source,
+    },
     Delete,
+    /// Erase a number of characters before the cursor, one at a time
+    Backspace(u16),
+    /// Remove all text and move the cursor to the start, keeping the variables
+    Clear,
+    Flush,
+    Breakpoint,
+    /// Stop playback until a key is pressed
+    Pause,
+    /// Print the variables to stderr while compiling, in verbose mode
+    ContextDump,
+    /// Check the value of a variable while compiling, in validate mode
+    AssertVar {
+        key: String,
+        value: String,
+    },
+    /// Set a variable to a string or the value of another variable
+    Set {
+        key: String,
+        value: Source,
+    },
+    /// Set a variable to the result of arithmetic on numeric variables, e.g. `"${i} + 1"`
+    SetExpr {
+        key: String,
+        expr: String,
+    },
+    /// Copy the selection, into the named register if one is given
+    Copy(Option<String>),
+    /// Paste the content of the named register, or the default register
+    Paste(Option<String>),
+    /// Insert the strings before and after the selection
+    Surround {
+        left: String,
+        right: String,
+    },
 
-    /// This instructions requires that the cursor is placed on the
-    /// same line as the src.
+    /// Replace the first match of the src in the whole document,
+    /// wherever the cursor is, and move the cursor there.
     ///
     /// ```text
-    /// replace "hello", "good bye"
+    /// replace "hello" "good bye"
     ///
     /// text:
-    /// hello
-    /// hello <- cursor pos -> good bye
-    /// hello
+    /// hi            -> hi
+    /// hello         -> good bye
+    /// hello         -> hello
     /// ```
     Replace {
         src: String,
         replacement: Source,
+        /// Store the replaced text under this name
+        capture: Option<String>,
+        /// Replace the first match on the visible lines rather than in the whole document
+        viewport: bool,
     },
-    Select {
-        width: u16,
-        height: u16,
+    Select(Select),
+    /// Record the cursor position to select from
+    Anchor,
+    /// Play the body a number of times
+    Repeat {
+        count: u32,
+        body: Vec<Instruction>,
     },
+    /// Play the body until the condition holds, checking it before every iteration
+    RepeatUntil {
+        condition: Condition,
+        body: Vec<Instruction>,
+    },
+    /// A point in the script that `jump` can continue from
+    Label(String),
+    /// Continue playback from a label, before or after the jump
+    JumpToLabel(String),
+    /// Move the cursor and select from the anchor to the new position
+    SelectTo(Dest),
+    /// Select the word under the cursor and the given number of words after it
+    SelectWords(u16),
+    /// Select whole lines, starting at one and including the end
+    SelectLines {
+        start: usize,
+        end: usize,
+    },
+    /// Save the current selection
+    PushSelection,
+    /// Restore the most recently saved selection
+    PopSelection,
     SetTitle(String),
+    /// Write the text to the terminal as is, e.g. an escape sequence
+    EchoRaw(String),
+    /// Load a color scheme for the syntax highlighting
+    ThemeLoad(PathBuf),
+    /// Replay the keystrokes of a recording with the recorded delays
+    TypeRecording(PathBuf),
     ShowLineNumbers(bool),
-    LinePause(u64),
-    Speed(u64),
-    Wait(u64),
+    LinePause(Duration),
+    /// Milliseconds per frame, each randomly up to `jitter` milliseconds longer or shorter
+    Speed {
+        base: u64,
+        jitter: u64,
+    },
+    Wait(Duration),
+    /// Wait one of the durations, picked at random
+    WaitOneOf(Vec<Duration>),
+    /// Wait for a file to exist, with an optional timeout in seconds
+    WaitFile {
+        path: PathBuf,
+        timeout: Option<u64>,
+    },
+    OnError(OnError),
+}
+
+/// A file outside of the script that playback depends on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resource {
+    /// Read while compiling, with `load`, `include`, `edit`, `theme load` or `type_recording`
+    Read(PathBuf),
+    /// Read during playback with `type tail`
+    Tail(PathBuf),
+    /// Waited for with `wait_file`
+    Wait(PathBuf),
+}
+
+impl Display for Resource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Resource::Read(path) => write!(f, "read {}", path.display()),
+            Resource::Tail(path) => write!(f, "tail {}", path.display()),
+            Resource::Wait(path) => write!(f, "wait {}", path.display()),
+        }
+    }
 }
 
-#[derive(Debug)]
+/// Resolve a path against the directory of the script using it.
+/// Without a script, e.g. when reading from stdin, the path is used as is.
+pub fn relative_to_script(path: &Path, script: Option<&Path>) -> PathBuf {
+    match script.and_then(Path::parent) {
+        Some(dir) => dir.join(path),
+        None => path.into(),
+    }
+}
+
+#[derive(Debug, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
 pub struct Instructions {
     inner: Vec<Instruction>,
 }
@@ -73,12 +287,103 @@ impl Instructions {
         Self { inner }
     }
 
+    pub fn iter(&self) -> std::slice::Iter<'_, Instruction> {
+        self.inner.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Instruction> {
+        self.inner.get(index)
+    }
+
+    /// A hash of the instructions, for use as a cache key.
+    /// Comments and formatting of the source don't change the hash,
+    /// and neither does the version of Rust or the platform parrot is built with.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Every file the instructions read or wait for, in order, without touching any of them.
+    /// Included and edited files are resolved against the `script`, the way compiling does
+    pub fn resources(&self, script: Option<&Path>) -> Vec<Resource> {
+        let mut resources = vec![];
+        collect_resources(&self.inner, script, &mut resources);
+        resources
+    }
+
     #[cfg(test)]
     pub fn take_instructions(self) -> Vec<Instruction> {
         self.inner
     }
 }
 
+fn collect_resources(instructions: &[Instruction], script: Option<&Path>, resources: &mut Vec<Resource>) {
+    for instruction in instructions {
+        let resource = match instruction {
+            Instruction::Include(path) | Instruction::OpenFile(path) => {
+                Resource::Read(relative_to_script(path, script))
+            }
+            Instruction::Load(path, _) | Instruction::ThemeLoad(path) | Instruction::TypeRecording(path) => {
+                Resource::Read(path.clone())
+            }
+            Instruction::Tail { path, .. } => Resource::Tail(path.clone()),
+            Instruction::WaitFile { path, .. } => Resource::Wait(path.clone()),
+            Instruction::Repeat { body, .. } | Instruction::RepeatUntil { body, .. } => {
+                collect_resources(body, script, resources);
+                continue;
+            }
+            _ => continue,
+        };
+        resources.push(resource);
+    }
+}
+
+// The FNV-1a hash. The std hashers may change between Rust versions
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    // Lengths hash the same on 32 and 64 bit platforms
+    fn write_usize(&mut self, i: usize) {
+        self.write(&(i as u64).to_le_bytes());
+    }
+}
+
+/// One instruction per line, as it would be written in a script
+impl Display for Instructions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for instruction in &self.inner {
+            writeln!(f, "{instruction}")?;
+        }
+        Ok(())
+    }
+}
+
 impl IntoIterator for Instructions {
     type IntoIter = <Vec<Instruction> as IntoIterator>::IntoIter;
     type Item = Instruction;
@@ -87,3 +392,450 @@ impl IntoIterator for Instructions {
         self.inner.into_iter()
     }
 }
+
+impl<'a> IntoIterator for &'a Instructions {
+    type IntoIter = std::slice::Iter<'a, Instruction>;
+    type Item = &'a Instruction;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// A string as it would be written in a script
+struct Quoted<'a>(&'a str);
+
+impl Display for Quoted<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"")?;
+        for c in self.0.chars() {
+            match c {
+                '\n' => write!(f, "\\n")?,
+                '\t' => write!(f, "\\t")?,
+                '\0' => write!(f, "\\0")?,
+                '\\' | '"' => write!(f, "\\{c}")?,
+                c if c.is_ascii_control() => write!(f, "\\x{:02x}", c as u8)?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+// A duration in the largest unit that keeps it whole,
+// or in seconds with a fraction if it isn't a whole number of milliseconds
+struct Dur(Duration);
+
+impl Display for Dur {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let nanos = self.0.as_nanos();
+        match nanos {
+            0 => write!(f, "0ms"),
+            _ if nanos.is_multiple_of(60_000_000_000) => write!(f, "{}m", nanos / 60_000_000_000),
+            _ if nanos.is_multiple_of(1_000_000_000) => write!(f, "{}s", nanos / 1_000_000_000),
+            _ if nanos.is_multiple_of(1_000_000) => write!(f, "{}ms", nanos / 1_000_000),
+            _ => {
+                let fraction = format!("{:09}", self.0.subsec_nanos());
+                write!(f, "{}.{}s", self.0.as_secs(), fraction.trim_end_matches('0'))
+            }
+        }
+    }
+}
+
+fn quoted_path(path: &Path) -> String {
+    Quoted(&path.to_string_lossy()).to_string()
+}
+
+impl Display for Dest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dest::Relative { row, col } => write!(f, "{row} {col}"),
+            Dest::Marker(marker) => write!(f, "{marker}"),
+            Dest::Line(line) => write!(f, "line {line}"),
+            Dest::LineVariable(key) => write!(f, "line {key}"),
+            Dest::PageRelative(pages) => write!(f, "page {pages}"),
+            Dest::Capture { pattern, group } => write!(f, "capture /{pattern}/ {group}"),
+            Dest::Offset(offset) => write!(f, "offset {offset}"),
+            Dest::LastType => write!(f, "last_type"),
+        }
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Str(s) => write!(f, "{}", Quoted(s)),
+            Source::Ident(ident) => write!(f, "{ident}"),
+            Source::Interpolated(s) => write!(f, "= {}", Quoted(s)),
+        }
+    }
+}
+
+/// The instruction as it would be written in a script, with blocks spanning several lines.
+/// Parsing the output gives the same instruction back.
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::Load(path, key) => write!(f, "load {} as {key}", quoted_path(path)),
+            Instruction::OpenFile(path) => write!(f, "edit {}", quoted_path(path)),
+            Instruction::Include(path) => write!(f, "include {}", quoted_path(path)),
+            Instruction::Find(needle) => write!(f, "find {}", Quoted(needle)),
+            Instruction::SearchNext(count) => write!(f, "search_next {count}"),
+            Instruction::SearchPrev(count) => write!(f, "search_prev {count}"),
+            Instruction::Goto { dest, center } => {
+                write!(f, "goto {dest}")?;
+                if *center {
+                    write!(f, " center")?;
+                }
+                Ok(())
+            }
+            Instruction::Type {
+                source,
+                trim_trailing_newline,
+                prefix_newline,
+                reverse,
+                align,
+                reindent,
+                complete,
+                by_word,
+                fix,
+                natural,
+                think,
+                autoindent,
+                line_pause,
+                autopair,
+                lines,
+            } => {
+                let keyword = if *prefix_newline { "typenl" } else { "type" };
+                write!(f, "{keyword} {source}")?;
+                if let Some((start, end)) = lines {
+                    write!(f, " {start}..{end}")?;
+                }
+                if *trim_trailing_newline {
+                    write!(f, " nonl")?;
+                }
+                if *reverse {
+                    write!(f, " reverse")?;
+                }
+                if let Some(width) = align {
+                    write!(f, " align {width}")?;
+                }
+                if *reindent {
+                    write!(f, " reindent")?;
+                }
+                if let Some(complete) = complete {
+                    write!(f, " complete {complete}")?;
+                }
+                if *by_word {
+                    write!(f, " by word")?;
+                }
+                if let Some(fix) = fix {
+                    write!(f, " fix {fix}")?;
+                }
+                if *natural {
+                    write!(f, " natural")?;
+                }
+                if *think > 0 {
+                    write!(f, " think {think}ms")?;
+                }
+                if *autoindent {
+                    write!(f, " autoindent")?;
+                }
+                if let Some(pause) = line_pause {
+                    write!(f, " line_pause {}", Dur(*pause))?;
+                }
+                if *autopair {
+                    write!(f, " autopair")?;
+                }
+                Ok(())
+            }
+            Instruction::Tail { path, timeout } => write!(f, "type tail {} {timeout}", quoted_path(path)),
+            Instruction::Insert(source) => write!(f, "insert {source}"),
+            Instruction::InsertEach { needle, source } => write!(f, "insert_each {} {source}", Quoted(needle)),
+            Instruction::Delete => write!(f, "delete"),
+            Instruction::Backspace(count) => write!(f, "backspace {count}"),
+            Instruction::Clear => write!(f, "clear"),
+            Instruction::Flush => write!(f, "flush"),
+            Instruction::Breakpoint => write!(f, "breakpoint"),
+            Instruction::Pause => write!(f, "pause"),
+            Instruction::ContextDump => write!(f, "context_dump"),
+            Instruction::AssertVar { key, value } => write!(f, "assert_var {key} {}", Quoted(value)),
+            Instruction::Set { key, value } => write!(f, "set {key} {value}"),
+            Instruction::SetExpr { key, expr } => write!(f, "set {key} = {}", Quoted(expr)),
+            Instruction::Copy(None) => write!(f, "copy"),
+            Instruction::Copy(Some(register)) => write!(f, "copy to {register}"),
+            Instruction::Paste(None) => write!(f, "paste"),
+            Instruction::Paste(Some(register)) => write!(f, "paste {register}"),
+            Instruction::Surround { left, right } => write!(f, "surround {} {}", Quoted(left), Quoted(right)),
+            Instruction::Replace {
+                src,
+                replacement,
+                capture,
+                viewport,
+            } => {
+                write!(f, "replace ")?;
+                if *viewport {
+                    write!(f, "viewport ")?;
+                }
+                write!(f, "{} {replacement}", Quoted(src))?;
+                if let Some(capture) = capture {
+                    write!(f, " as {capture}")?;
+                }
+                Ok(())
+            }
+            Instruction::Select(Select::Rect { width, height }) => write!(f, "select {width} {height}"),
+            Instruction::Select(Select::Region(marker)) => write!(f, "select {marker}"),
+            Instruction::Anchor => write!(f, "anchor"),
+            Instruction::Repeat { count, body } => {
+                write!(f, "repeat {count} ")?;
+                write_block(f, body)
+            }
+            Instruction::RepeatUntil { condition, body } => {
+                match condition {
+                    Condition::BufferContains(text) => write!(f, "repeat until buffer_contains {} ", Quoted(text))?,
+                    Condition::VarEq(key, value) => write!(f, "repeat until var_eq {key} {} ", Quoted(value))?,
+                }
+                write_block(f, body)
+            }
+            Instruction::Label(label) => write!(f, "label {label}"),
+            Instruction::JumpToLabel(label) => write!(f, "jump {label}"),
+            Instruction::SelectTo(dest) => write!(f, "select to {dest}"),
+            Instruction::SelectWords(count) => match count.saturating_sub(1) {
+                0 => write!(f, "select word"),
+                extra => write!(f, "select word {extra}"),
+            },
+            Instruction::SelectLines { start, end } => write!(f, "select lines {start}..{end}"),
+            Instruction::PushSelection => write!(f, "push_selection"),
+            Instruction::PopSelection => write!(f, "pop_selection"),
+            Instruction::SetTitle(title) => write!(f, "title {}", Quoted(title)),
+            Instruction::EchoRaw(text) => write!(f, "echo_raw {}", Quoted(text)),
+            Instruction::ThemeLoad(path) => write!(f, "theme load {}", quoted_path(path)),
+            Instruction::TypeRecording(path) => write!(f, "type_recording {}", quoted_path(path)),
+            Instruction::ShowLineNumbers(show) => write!(f, "numbers {show}"),
+            Instruction::LinePause(duration) => write!(f, "linepause {}", Dur(*duration)),
+            Instruction::Speed { base, jitter: 0 } => write!(f, "speed {base}"),
+            Instruction::Speed { base, jitter } => write!(f, "speed {base} +- {jitter}"),
+            Instruction::Wait(duration) => write!(f, "wait {}", Dur(*duration)),
+            Instruction::WaitOneOf(durations) => {
+                write!(f, "wait one_of")?;
+                for duration in durations {
+                    write!(f, " {}", Dur(*duration))?;
+                }
+                Ok(())
+            }
+            Instruction::WaitFile { path, timeout } => {
+                write!(f, "wait_file {}", quoted_path(path))?;
+                if let Some(timeout) = timeout {
+                    write!(f, " {timeout}")?;
+                }
+                Ok(())
+            }
+            Instruction::OnError(OnError::Skip) => write!(f, "on_error skip"),
+            Instruction::OnError(OnError::Halt) => write!(f, "on_error halt"),
+            Instruction::OnError(OnError::Retry) => write!(f, "on_error retry"),
+        }
+    }
+}
+
+// The body of a block indented by four spaces
+fn write_block(f: &mut Formatter<'_>, body: &[Instruction]) -> std::fmt::Result {
+    writeln!(f, "{{")?;
+    for instruction in body {
+        for line in instruction.to_string().lines() {
+            writeln!(f, "    {line}")?;
+        }
+    }
+    write!(f, "}}")
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use super::{Instruction, Resource};
+    use crate::parse;
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let code = "
+load \"src/main.rs\" as main
+goto 1 2
+type main reverse align 4
+repeat 2 {
+    select word +2
+    delete
+}
+on_error skip
+";
+        let instructions = parse(code).unwrap();
+        let json = crate::to_json(&instructions);
+        assert_eq!(crate::from_json(&json).unwrap(), instructions);
+
+        assert!(json.contains("\"src/main.rs\""));
+        assert!(crate::from_json("[{\"Wait\": \"one\"}]").is_err());
+    }
+
+    #[test]
+    fn inspect_without_taking() {
+        let instructions = parse("load \"a.rs\" as a\n\n// type it\ntype a\nwait 1\n").unwrap();
+        assert_eq!(instructions.len(), 3);
+        assert!(!instructions.is_empty());
+        assert_eq!(instructions.get(2), Some(&Instruction::Wait(Duration::from_secs(1))));
+        assert_eq!(instructions.get(3), None);
+
+        let waits = instructions.iter().filter(|inst| matches!(inst, Instruction::Wait(_)));
+        assert_eq!(waits.count(), 1);
+        assert_eq!((&instructions).into_iter().count(), instructions.len());
+
+        assert!(parse("// nothing to play\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn hash_ignores_formatting() {
+        let a = parse("load \"a.rs\" as a\ntype a\nwait 1").unwrap();
+        let b = parse("// setup\nload   \"a.rs\" as a\n\n  type a\n// type it\nwait 1\n").unwrap();
+        let c = parse("load \"a.rs\" as a\ntype a\nwait 2").unwrap();
+
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert_ne!(a.content_hash(), c.content_hash());
+
+        // The hash never changes, so it can be stored
+        assert_eq!(parse("").unwrap().content_hash(), 12161962213042174405);
+    }
+
+    #[test]
+    fn list_resources() {
+        let code = "
+load \"src/main.rs\" as main
+theme load \"dark.toml\"
+type main
+wait_file \"target/done\" 10
+repeat 2 {
+    type tail \"app.log\" 5
+}
+repeat until buffer_contains \"}\" {
+    include \"intro.echo\"
+}
+edit \"lib.rs\"
+";
+        let instructions = parse(code).unwrap();
+        let resources = instructions.resources(Some(Path::new("demos/demo.echo")));
+        let expected = vec![
+            Resource::Read("src/main.rs".into()),
+            Resource::Read("dark.toml".into()),
+            Resource::Wait("target/done".into()),
+            Resource::Tail("app.log".into()),
+            Resource::Read("demos/intro.echo".into()),
+            Resource::Read("demos/lib.rs".into()),
+        ];
+        assert_eq!(resources, expected);
+
+        assert_eq!(resources[0].to_string(), "read src/main.rs");
+        assert_eq!(resources[2].to_string(), "wait target/done");
+        assert_eq!(resources[3].to_string(), "tail app.log");
+
+        // Without a script the paths are used as is
+        let resources = instructions.resources(None);
+        assert_eq!(resources[4], Resource::Read("intro.echo".into()));
+    }
+
+    #[test]
+    fn display_parses_back() {
+        let code = r#"
+load "src/main.rs" as main
+include "intro.echo"
+theme load "dark.toml"
+type_recording "keys.json"
+goto -1 2 center
+goto line 3
+goto line row
+goto page -2
+goto capture /fn (\w+)/ 1
+goto offset 10
+type "a\tb\n\"c\"\\" nonl reverse align 4 reindent complete "x" by word fix main natural think 1500 autoindent line_pause 300 autopair
+typenl main
+type tail "app.log" 5
+insert "x"
+insert_each "fn" main
+replace viewport "a" "b" as old
+select 3 4
+select main
+select to here
+select word 2
+select lines 1..3
+copy to reg
+paste reg
+copy
+paste
+search_next 2
+search_prev 1
+repeat 2 {
+    repeat until var_eq i "3" {
+        set i = "${i} + 1"
+    }
+}
+repeat until buffer_contains "done" {
+    backspace 1
+}
+label top
+jump top
+wait 1500ms
+wait one_of 1 2m
+wait_file "done" 5
+linepause 250ms
+numbers false
+title "demo"
+speed 30
+speed 40 +- 15
+on_error retry
+assert_var i "3"
+set j i
+goto last_type center
+edit "src/main.rs"
+surround "(" ")"
+type foo 10..20 nonl
+insert = "${a}$$"
+echo_raw "\x1b[2J"
+"#;
+        let instructions = parse(code).unwrap();
+        let dump = instructions.to_string();
+        assert_eq!(parse(&dump).unwrap(), instructions);
+
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert_eq!(lines[8], "goto capture /fn (\\w+)/ 1");
+        assert_eq!(
+            lines[10],
+            r#"type "a\tb\n\"c\"\\" nonl reverse align 4 reindent complete "x" by word fix main natural think 1500ms autoindent line_pause 300ms autopair"#
+        );
+        let block = [
+            "repeat 2 {",
+            "    repeat until var_eq i \"3\" {",
+            "        set i = \"${i} + 1\"",
+            "    }",
+            "}",
+        ];
+        assert_eq!(lines[27..32], block);
+        assert_eq!(lines[37..39], ["wait 1500ms", "wait one_of 1s 2m"]);
+        assert_eq!(lines.last(), Some(&r#"echo_raw "\x1b[2J""#));
+    }
+
+    #[test]
+    fn display_durations_parse_back() {
+        let cases = [
+            ("wait 90s", "wait 90s"),
+            ("wait 1.5s", "wait 1500ms"),
+            ("wait 1.0005s", "wait 1.0005s"),
+            ("wait 0.25ms", "wait 0.00025s"),
+            ("wait 2m", "wait 2m"),
+        ];
+
+        for (code, expected) in cases {
+            let instructions = parse(code).unwrap();
+            let dump = instructions.to_string();
+            assert_eq!(dump.trim_end(), expected);
+            assert_eq!(parse(&dump).unwrap(), instructions, "{code}");
+        }
+    }
+}
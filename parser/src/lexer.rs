@@ -1,33 +1,109 @@
+use std::borrow::Cow;
 use std::iter::Peekable;
+use std::num::{IntErrorKind, ParseIntError};
 use std::str::Chars;
-
-use unicode_width::UnicodeWidthChar;
+use std::time::Duration;
 
 use crate::error::{Error, Result};
-use crate::token::{Span, Token, Tokens};
+use crate::token::{KEYWORDS, Span, Token, Tokens};
+
+static VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The comment prefix used unless another one is given
+pub const DEFAULT_COMMENT_PREFIX: &str = "//";
 
 pub fn lex<'a>(code: &'a str) -> Result<Tokens<'a>> {
-    Lexer::new(code).lex()
+    lex_with_comment_prefixes(code, &[DEFAULT_COMMENT_PREFIX])
+}
+
+pub fn lex_with_comment_prefixes<'a>(code: &'a str, comment_prefixes: &[&str]) -> Result<Tokens<'a>> {
+    Lexer::new(code, comment_prefixes).lex()
+}
+
+/// Lex the code like [`lex_with_comment_prefixes`], leaving out the lines that fail to lex
+/// and collecting the errors rather than stopping at the first one.
+pub fn lex_recoverable<'a>(code: &'a str, comment_prefixes: &[&str]) -> (Tokens<'a>, Vec<Error>) {
+    Lexer::new(code, comment_prefixes).lex_recoverable()
 }
 
-struct Lexer<'src> {
+/// The one-based line and column of a byte offset in the source.
+/// A column is a character, however many bytes it takes.
+pub(crate) fn line_col(source: &str, offset: usize) -> (u16, u16) {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let line = before.matches('\n').count() + 1;
+    let col = before[line_start..].chars().count() + 1;
+    (line as u16, col as u16)
+}
+
+// Parse `major.minor.patch` where minor and patch are optional
+fn parse_version(version: &str) -> Option<[u64; 3]> {
+    let mut output = [0; 3];
+    let mut parts = version.split('.');
+
+    output[0] = parts.next()?.parse().ok()?;
+    for (value, part) in output[1..].iter_mut().zip(parts.by_ref()) {
+        *value = part.parse().ok()?;
+    }
+
+    // There is no fourth component
+    match parts.next() {
+        Some(_) => None,
+        None => Some(output),
+    }
+}
+
+fn is_overflow(err: &ParseIntError) -> bool {
+    matches!(err.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow)
+}
+
+struct Lexer<'src, 'prefix> {
     source: &'src str,
+    comment_prefixes: &'prefix [&'prefix str],
     input: Peekable<Chars<'src>>,
-    tokens: Vec<Token>,
+    // Byte offset of the next character in the source
+    offset: usize,
+    // Byte offset of the first character of the token being lexed
+    token_start: usize,
+    // The byte offset, line and column of the most recent span
+    position: (usize, u16, u16),
+    tokens: Vec<Token<'src>>,
     spans: Vec<Span>,
-    current_span: Span,
-    next_span: Span,
 }
 
-impl<'src> Lexer<'src> {
-    pub fn new(source: &'src str) -> Self {
+impl<'src, 'prefix> Lexer<'src, 'prefix> {
+    pub fn new(source: &'src str, comment_prefixes: &'prefix [&'prefix str]) -> Self {
         Self {
             source,
+            comment_prefixes,
             input: source.chars().peekable(),
+            offset: 0,
+            token_start: 0,
+            position: (0, Span::INITIAL.line, Span::INITIAL.col),
             tokens: vec![],
             spans: vec![],
-            current_span: Span::INITIAL,
-            next_span: Span::INITIAL,
+        }
+    }
+
+    // The span of a byte offset, counting on from the most recent span
+    // rather than from the start of the source
+    fn span(&mut self, offset: usize) -> Span {
+        if offset < self.position.0 {
+            self.position = (0, Span::INITIAL.line, Span::INITIAL.col);
+        }
+
+        let (from, line, col) = self.position;
+        let (lines, end_col) = line_col(&self.source[from..], offset - from);
+        let (line, col) = match lines {
+            1 => (line, col + end_col - 1),
+            _ => (line + lines - 1, end_col),
+        };
+
+        self.position = (offset, line, col);
+        Span {
+            token: self.tokens.len() as u32,
+            line,
+            col,
         }
     }
 
@@ -37,130 +113,218 @@ impl<'src> Lexer<'src> {
             .next()
             .expect("every character has to be checked before consume is called");
 
-        if c == '\n' {
-            self.next_span.line += 1;
-            self.next_span.col = 1;
-        } else {
-            self.next_span.col += c.width().unwrap_or(0) as u16;
-        }
+        self.offset += c.len_utf8();
     }
 
-    fn single_char_token(&mut self, token: Token) {
-        if token == Token::Newline {
-            self.next_span.line += 1;
-            self.next_span.col = 1;
-        } else {
-            self.next_span.col += 1;
-        }
+    fn single_char_token(&mut self, token: Token<'src>) {
         self.push_token(token);
     }
 
     fn lex(mut self) -> Result<Tokens<'src>> {
-        loop {
-            let Some(c) = self.input.next() else { break };
-
-            match c {
-                // -----------------------------------------------------------------------------
-                //   - Multi char tokens-
-                // -----------------------------------------------------------------------------
-                '/' if Some('/') == self.input.peek().copied() => self.comment(),
-
-                // -----------------------------------------------------------------------------
-                //   - Single char tokens -
-                // -----------------------------------------------------------------------------
-                '\n' => self.single_char_token(Token::Newline),
-                '=' => self.single_char_token(Token::Equal),
-                '@' => self.single_char_token(Token::At),
-                '!' => self.single_char_token(Token::Bang),
-
-                '-' | '0'..='9' => self.int(c)?,
-                'a'..='z' | 'A'..='Z' => self.ident(c)?,
-                '"' | '\'' => self.string(c)?,
-                _ => self.whitespace(),
+        while let Some(c) = self.next_char() {
+            self.token(c)?;
+        }
+
+        Ok(self.finish())
+    }
+
+    // Lex every line that can be lexed, leaving out the rest of a line from the token that fails
+    fn lex_recoverable(mut self) -> (Tokens<'src>, Vec<Error>) {
+        let mut errors = vec![];
+        while let Some(c) = self.next_char() {
+            if let Err(err) = self.token(c) {
+                errors.push(err);
+                self.skip_failed_line();
             }
         }
 
+        (self.finish(), errors)
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        self.token_start = self.offset;
+        let c = self.input.next()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    // Drop the tokens on the line of the failed token, and carry on from the end of that line
+    fn skip_failed_line(&mut self) {
+        let line = self.span(self.token_start).line;
+        while self.spans.last().is_some_and(|span| span.line >= line) {
+            self.spans.pop();
+            self.tokens.pop();
+        }
+
+        let rest = &self.source[self.token_start..];
+        self.offset = self.token_start + rest.find('\n').unwrap_or(rest.len());
+        self.input = self.source[self.offset..].chars().peekable();
+    }
+
+    fn finish(mut self) -> Tokens<'src> {
+        self.token_start = self.offset;
         self.push_token(Token::Eof);
 
-        let tokens = Tokens::new(self.source, self.tokens, self.spans);
-        Ok(tokens)
+        Tokens::new(self.source, self.tokens, self.spans)
+    }
+
+    fn token(&mut self, c: char) -> Result<()> {
+        match c {
+            // -----------------------------------------------------------------------------
+            //   - Multi char tokens-
+            // -----------------------------------------------------------------------------
+            '#' if Some('!') == self.input.peek().copied() => self.pragma()?,
+            _ if self.comment_prefix(c).is_some() => self.comment(c),
+            '.' if Some('.') == self.input.peek().copied() => {
+                self.consume_char();
+                self.push_token(Token::DotDot);
+            }
+            '+' if Some('-') == self.input.peek().copied() => {
+                self.consume_char();
+                self.push_token(Token::PlusMinus);
+            }
+
+            // -----------------------------------------------------------------------------
+            //   - Single char tokens -
+            // -----------------------------------------------------------------------------
+            '\n' => self.single_char_token(Token::Newline),
+            '=' => self.single_char_token(Token::Equal),
+            '@' => self.single_char_token(Token::At),
+            '!' => self.single_char_token(Token::Bang),
+            '{' => self.single_char_token(Token::LBrace),
+            '}' => self.single_char_token(Token::RBrace),
+
+            '+' if matches!(self.input.peek(), Some('0'..='9')) => self.int(c)?,
+            '-' | '0'..='9' => self.int(c)?,
+            'a'..='z' | 'A'..='Z' => self.ident(c)?,
+            '"' | '\'' => self.string(c)?,
+            '/' if self.after_capture() => self.regex()?,
+            _ => self.whitespace(),
+        }
+
+        Ok(())
     }
 
     fn string(&mut self, quote: char) -> Result<()> {
-        let mut buffer = String::new();
+        let start = self.offset;
+
+        // The string is borrowed from the source unless
+        // it contains an escape sequence
+        let mut buffer: Option<String> = None;
         let mut escaping = false;
+        // Where the backslash of the current escape is, for errors
+        let mut escape_offset = self.offset;
 
-        loop {
+        let end = loop {
             match self.input.peek() {
                 Some('\\') if !escaping => {
-                    if let Some('"' | '\\') = self.input.peek() {
-                        escaping = true;
-                    } else {
-                        escaping = false;
-                    }
+                    escaping = true;
+                    escape_offset = self.offset;
+                    buffer.get_or_insert_with(|| self.source[start..self.offset].to_string());
                 }
-                Some('n') if escaping => {
-                    buffer.push('\n');
-                    escaping = false;
-                }
-                Some(c @ '\\') if escaping => {
-                    buffer.push(*c);
+                Some('x') if escaping => {
+                    self.consume_char();
+                    let byte = self.hex_escape()?;
+                    buffer.as_mut().expect("escaping requires a buffer").push(byte);
                     escaping = false;
+                    continue;
                 }
-                Some(c) if escaping && *c == quote => {
-                    buffer.push(*c);
+                Some(c) if escaping => {
+                    let c = match c {
+                        'n' => '\n',
+                        't' => '\t',
+                        '0' => '\0',
+                        '\\' | '"' => *c,
+                        c if *c == quote => *c,
+                        &c => return Error::unknown_escape(c, self.span(escape_offset), self.source),
+                    };
+                    buffer.as_mut().expect("escaping requires a buffer").push(c);
                     escaping = false;
                 }
                 // Closing quote
                 Some(c) if *c == quote => {
+                    let end = self.offset;
                     self.consume_char();
-                    break;
+                    break end;
                 }
-                Some(c) => buffer.push(*c),
-                None => return Error::unterminated_string(self.next_span, self.source),
+                Some(c) => {
+                    if let Some(buffer) = buffer.as_mut() {
+                        buffer.push(*c);
+                    }
+                }
+                None => return Error::unterminated_string(self.span(self.token_start), self.source),
             }
 
+            self.consume_char();
+        };
+
+        let string = match buffer {
+            Some(buffer) => Cow::Owned(buffer),
+            None => Cow::Borrowed(&self.source[start..end]),
+        };
+
+        self.push_token(Token::Str(string));
+        Ok(())
+    }
+
+    // A regex can only follow `goto capture`, anywhere else a slash is not a token
+    fn after_capture(&self) -> bool {
+        let mut tokens = self.tokens.iter().rev().filter(|token| **token != Token::Whitespace);
+        tokens.next() == Some(&Token::Ident("capture")) && tokens.next() == Some(&Token::Goto)
+    }
+
+    // The pattern is kept as written, so `\/` is left for the regex to unescape
+    fn regex(&mut self) -> Result<()> {
+        let start = self.offset;
+        let mut escaping = false;
+
+        loop {
+            match self.input.peek() {
+                Some('/') if !escaping => break,
+                Some('\n') | None => return Error::unterminated_regex(self.span(self.token_start), self.source),
+                Some(c) => escaping = *c == '\\' && !escaping,
+            }
             self.consume_char();
         }
 
-        self.push_token(Token::Str(buffer));
+        let end = self.offset;
+        self.consume_char();
+        self.push_token(Token::Regex(&self.source[start..end]));
         Ok(())
     }
 
+    // The two hex digits of a `\xNN` escape, limited to ASCII
+    fn hex_escape(&mut self) -> Result<char> {
+        let mut byte = 0;
+        for _ in 0..2 {
+            let Some(digit) = self.input.peek().and_then(|c| c.to_digit(16)) else {
+                return Error::invalid_escape(self.span(self.offset), self.source);
+            };
+            byte = byte * 16 + digit;
+            self.consume_char();
+        }
+
+        match char::from_u32(byte) {
+            Some(c) if c.is_ascii() => Ok(c),
+            _ => Error::invalid_escape(self.span(self.offset), self.source),
+        }
+    }
+
     fn ident(&mut self, initial: char) -> Result<()> {
-        let mut buffer = String::from(initial);
+        let start = self.offset - initial.len_utf8();
 
         loop {
             match self.input.peek() {
-                Some(c @ ('a'..='z' | 'A'..='Z' | '0'..'9' | '_' | '-')) => {
-                    buffer.push(*c);
-                    self.consume_char();
-                }
+                Some('a'..='z' | 'A'..='Z' | '0'..'9' | '_' | '-') => self.consume_char(),
                 Some(_) | None => break,
             }
         }
 
-        let token = match buffer.as_str() {
-            "as" => Token::As,
-            "delete" => Token::Delete,
-            "find" => Token::Find,
-            "goto" => Token::Goto,
-            "insert" => Token::Insert,
-            "linepause" => Token::LinePause,
-            "load" => Token::Load,
-            "nonl" => Token::NoNewline,
-            "numbers" => Token::ShowLineNumbers,
-            "replace" => Token::Replace,
-            "select" => Token::Select,
-            "speed" => Token::Speed,
-            "title" => Token::SetTitle,
-            "type" => Token::Type,
-            "typenl" => Token::TypeNl,
-            "true" => Token::Bool(true),
-            "false" => Token::Bool(false),
-            "wait" | "sleep" => Token::Wait,
-            _ => Token::Ident(buffer),
-        };
+        let ident = &self.source[start..self.offset];
+        let token = KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == ident)
+            .map_or(Token::Ident(ident), |(_, token)| token.clone());
         self.push_token(token);
         Ok(())
     }
@@ -173,13 +337,46 @@ impl<'src> Lexer<'src> {
                     buffer.push(*c);
                     self.consume_char();
                 }
+                // A digit separator, as in `1_000`
+                Some('_') => self.consume_char(),
                 Some(_) | None => break,
             }
         }
 
-        let int = match buffer.parse() {
+        if let Some(radix) = self.radix_prefix(&buffer) {
+            return self.radix_int(buffer, radix);
+        }
+
+        // A fraction is only allowed in a duration, and `1..3` is a range
+        let rest = &self.source[self.offset..];
+        let fraction = rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit());
+        if fraction {
+            buffer.push('.');
+            self.consume_char();
+            while let Some(c @ '0'..='9') = self.input.peek() {
+                buffer.push(*c);
+                self.consume_char();
+            }
+        }
+
+        if let Some(seconds) = self.duration_unit() {
+            // Negative and too large durations are rejected along with unparsable ones
+            let duration = match buffer.parse::<f64>() {
+                Ok(value) => Duration::try_from_secs_f64(value * seconds).ok(),
+                Err(_) => None,
+            };
+            let Some(duration) = duration else {
+                return Error::invalid_duration(self.span(self.token_start), self.source);
+            };
+            self.push_token(Token::Duration(duration));
+            return Ok(());
+        }
+
+        let int = match buffer.parse::<i64>() {
             Ok(int) => int,
-            Err(_) => return Error::invalid_int(self.next_span, self.source),
+            Err(_) if fraction => return Error::invalid_duration(self.span(self.token_start), self.source),
+            Err(err) if is_overflow(&err) => return Error::int_overflow(self.span(self.token_start), self.source),
+            Err(_) => return Error::invalid_int(self.span(self.token_start), self.source),
         };
 
         let token = Token::Int(int);
@@ -187,16 +384,91 @@ impl<'src> Lexer<'src> {
         Ok(())
     }
 
-    fn push_token(&mut self, token: Token) {
-        self.current_span.token = self.tokens.len() as u32;
-        self.spans.push(self.current_span);
-        self.current_span = self.next_span;
+    // Consume the `x` of `0x` or the `b` of `0b` when followed by a digit of that radix
+    fn radix_prefix(&mut self, buffer: &str) -> Option<u32> {
+        if buffer.trim_start_matches(['-', '+']) != "0" {
+            return None;
+        }
+
+        let mut rest = self.source[self.offset..].chars();
+        let radix = match rest.next() {
+            Some('x') => 16,
+            Some('b') => 2,
+            _ => return None,
+        };
+
+        let digit = rest.next()?;
+        if !digit.is_digit(radix) {
+            return None;
+        }
+
+        self.consume_char();
+        Some(radix)
+    }
+
+    // The digits of a hexadecimal or binary int, with the sign already in the buffer
+    fn radix_int(&mut self, mut buffer: String, radix: u32) -> Result<()> {
+        // Drop the leading zero of the prefix, keeping the sign
+        buffer.pop();
+
+        loop {
+            match self.input.peek() {
+                Some(c) if c.is_digit(radix) => {
+                    buffer.push(*c);
+                    self.consume_char();
+                }
+                Some('_') => self.consume_char(),
+                Some(_) | None => break,
+            }
+        }
+
+        match i64::from_str_radix(&buffer, radix) {
+            Ok(int) => {
+                self.push_token(Token::Int(int));
+                Ok(())
+            }
+            Err(err) if is_overflow(&err) => Error::int_overflow(self.span(self.token_start), self.source),
+            Err(_) => Error::invalid_int(self.span(self.token_start), self.source),
+        }
+    }
+
+    // Consume the unit of a duration directly after a number, returning the number of seconds in the unit
+    fn duration_unit(&mut self) -> Option<f64> {
+        static UNITS: &[(&str, f64)] = &[("ms", 0.001), ("s", 1.0), ("m", 60.0)];
+
+        let rest = &self.source[self.offset..];
+        let (unit, seconds) = UNITS.iter().find(|(unit, _)| {
+            // `5min` is not a number of minutes
+            rest.starts_with(unit) && !rest[unit.len()..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+        })?;
+
+        unit.chars().for_each(|_| self.consume_char());
+        Some(*seconds)
+    }
+
+    fn push_token(&mut self, token: Token<'src>) {
+        let span = self.span(self.token_start);
+        self.spans.push(span);
         self.tokens.push(token);
     }
 
-    fn comment(&mut self) {
-        // Consume the last '\'
-        self.consume_char();
+    // The longest comment prefix at the start of `initial`, if any.
+    // The longest one wins so `--` and `---` can both be prefixes.
+    fn comment_prefix(&self, initial: char) -> Option<&'prefix str> {
+        let start = self.offset - initial.len_utf8();
+        self.comment_prefixes
+            .iter()
+            .copied()
+            .filter(|prefix| !prefix.is_empty() && self.source[start..].starts_with(prefix))
+            .max_by_key(|prefix| prefix.len())
+    }
+
+    fn comment(&mut self, initial: char) {
+        // Consume the remainder of the comment prefix
+        let prefix = self.comment_prefix(initial).unwrap_or_default();
+        for _ in prefix.chars().skip(1) {
+            self.consume_char();
+        }
 
         while let Some(&c) = self.input.peek() {
             self.consume_char();
@@ -208,6 +480,41 @@ impl<'src> Lexer<'src> {
         self.push_token(Token::Comment);
     }
 
+    // A line starting with `#!`.
+    // `#!version <version>` requires the version of the parser to be
+    // equal to or greater than the given version.
+    // Anything else (like a shebang) is treated as a comment.
+    fn pragma(&mut self) -> Result<()> {
+        let span = self.span(self.token_start);
+
+        // Consume the '!'
+        self.consume_char();
+
+        let mut buffer = String::new();
+        while let Some(&c) = self.input.peek() {
+            if c == '\n' {
+                break;
+            }
+            buffer.push(c);
+            self.consume_char();
+        }
+
+        let mut words = buffer.split_whitespace();
+        if let Some("version") = words.next() {
+            let required = words.next().unwrap_or_default();
+            match parse_version(required) {
+                Some(version) if version > parse_version(VERSION).expect("crate version is valid") => {
+                    return Error::unsupported_version(required, span, self.source);
+                }
+                Some(_) => {}
+                None => return Error::invalid_version(required, span, self.source),
+            }
+        }
+
+        self.push_token(Token::Comment);
+        Ok(())
+    }
+
     fn whitespace(&mut self) {
         loop {
             match self.input.peek() {
@@ -226,21 +533,21 @@ impl<'src> Lexer<'src> {
 mod test {
     use super::*;
 
-    fn ident(s: &str) -> Token {
-        Token::Ident(s.into())
+    fn ident(s: &str) -> Token<'_> {
+        Token::Ident(s)
     }
 
-    fn string(s: &str) -> Token {
+    fn string(s: &str) -> Token<'_> {
         Token::Str(s.into())
     }
 
-    fn int(i: i64) -> Token {
+    fn int(i: i64) -> Token<'static> {
         Token::Int(i)
     }
 
     macro_rules! token_fn {
         ($name:ident, $variant:ident) => {
-            fn $name() -> Token {
+            fn $name() -> Token<'static> {
                 Token::$variant
             }
         };
@@ -254,7 +561,7 @@ mod test {
     token_fn!(nl, Newline);
     token_fn!(eof, Eof);
 
-    fn lex_tokens(input: &str) -> Vec<Token> {
+    fn lex_tokens(input: &str) -> Vec<Token<'_>> {
         lex(input).unwrap().take_tokens()
     }
 
@@ -294,6 +601,82 @@ mod test {
         assert_eq!(tokens, expected);
     }
 
+    #[test]
+    fn lex_positive_int() {
+        let input = "+2";
+        let tokens = lex_tokens(input);
+
+        let expected = vec![int(2), eof()];
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn lex_int_literals() {
+        let tokens = lex_tokens("0xFF 0x1f 1_000 0b1010 -0x10 +0b1 1_000ms 0");
+        let expected = vec![
+            int(255),
+            whitespace(),
+            int(31),
+            whitespace(),
+            int(1000),
+            whitespace(),
+            int(10),
+            whitespace(),
+            int(-16),
+            whitespace(),
+            int(1),
+            whitespace(),
+            Token::Duration(Duration::from_secs(1)),
+            whitespace(),
+            int(0),
+            eof(),
+        ];
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn lex_int_overflow() {
+        let tokens = lex_tokens("0x7FFF_FFFF_FFFF_FFFF -0x8000000000000000");
+        assert_eq!(tokens, vec![int(i64::MAX), whitespace(), int(i64::MIN), eof()]);
+
+        let err = lex("goto 0x8000000000000000 1").unwrap_err();
+        assert!(err.to_string().starts_with("error at 1:6: integer out of range"));
+
+        let err = lex("speed 99_999_999_999_999_999_999").unwrap_err();
+        assert!(err.to_string().starts_with("error at 1:7: integer out of range"));
+    }
+
+    #[test]
+    fn lex_durations() {
+        let tokens = lex_tokens("500ms 1.5s 2m 1..3");
+        let expected = vec![
+            Token::Duration(Duration::from_millis(500)),
+            whitespace(),
+            Token::Duration(Duration::from_millis(1500)),
+            whitespace(),
+            Token::Duration(Duration::from_secs(120)),
+            whitespace(),
+            int(1),
+            Token::DotDot,
+            int(3),
+            eof(),
+        ];
+        assert_eq!(tokens, expected);
+
+        assert!(lex("1.5").is_err());
+        assert!(lex("-2s").is_err());
+        assert!(lex("99999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn lex_range() {
+        let input = "10..15";
+        let tokens = lex_tokens(input);
+
+        let expected = vec![int(10), Token::DotDot, int(15), eof()];
+        assert_eq!(tokens, expected);
+    }
+
     #[test]
     fn lex_ident() {
         let input = "change1";
@@ -312,6 +695,128 @@ mod test {
         assert_eq!(tokens, expected);
     }
 
+    #[test]
+    fn lex_custom_comment_prefix() {
+        let input = "# comment\nload";
+        let tokens = lex_with_comment_prefixes(input, &["#"]).unwrap().take_tokens();
+        assert_eq!(tokens, vec![comment(), load(), eof()]);
+
+        let tokens = lex_with_comment_prefixes("-- comment", &["--"]).unwrap().take_tokens();
+        assert_eq!(tokens, vec![comment(), eof()]);
+    }
+
+    #[test]
+    fn lex_multiple_comment_prefixes() {
+        let input = "# comment\nload\n// comment\n#// comment\nload";
+        let tokens = lex_with_comment_prefixes(input, &["#", "//"]).unwrap().take_tokens();
+        let expected = vec![comment(), load(), nl(), comment(), comment(), load(), eof()];
+        assert_eq!(tokens, expected);
+
+        // The longest prefix is consumed, so nothing of it is left over
+        let input = "-- comment\n--- comment\nload";
+        let tokens = lex_with_comment_prefixes(input, &["--", "---"]).unwrap().take_tokens();
+        assert_eq!(tokens, vec![comment(), comment(), load(), eof()]);
+    }
+
+    #[test]
+    fn lex_version_pragma() {
+        let expected = vec![comment(), nl(), load(), eof()];
+
+        let tokens = lex_tokens("#!version 0.1\nload");
+        assert_eq!(tokens, expected);
+
+        let input = format!("#!version {VERSION}\nload");
+        let tokens = lex_tokens(&input);
+        assert_eq!(tokens, expected);
+
+        let tokens = lex_tokens("#!/usr/bin/env parrot\nload");
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn lex_version_pragma_too_new() {
+        assert!(lex("#!version 99.0").is_err());
+        assert!(lex("#!version 0.99.0").is_err());
+        assert!(lex("#!version banana").is_err());
+        assert!(lex("#!version 0.1.0.0").is_err());
+    }
+
+    #[test]
+    fn lex_strings_and_idents_borrow_from_source() {
+        let input = "load \"src/main.rs\" as code\ntype code\n".repeat(1000);
+        let tokens = lex_tokens(&input);
+
+        let allocations = tokens
+            .iter()
+            .filter(|token| matches!(token, Token::Str(Cow::Owned(_))))
+            .count();
+        assert_eq!(allocations, 0);
+        assert_eq!(tokens[2], string("src/main.rs"));
+        assert_eq!(tokens[6], ident("code"));
+    }
+
+    #[test]
+    fn lex_string_with_escape_allocates() {
+        let tokens = lex_tokens("\"a\\\"b\"");
+        assert_eq!(tokens[0], Token::Str(Cow::Owned("a\"b".into())));
+    }
+
+    #[test]
+    fn lex_string_with_escapes() {
+        let escapes = [
+            ("\"a\\nb\"", "a\nb"),
+            ("\"a\\tb\"", "a\tb"),
+            ("\"a\\\"b\"", "a\"b"),
+            ("\"a\\\\b\"", "a\\b"),
+            ("\"a\\0b\"", "a\0b"),
+            ("'a\\'b'", "a'b"),
+        ];
+
+        for (input, expected) in escapes {
+            assert_eq!(lex_tokens(input)[0], string(expected));
+        }
+    }
+
+    #[test]
+    fn lex_string_with_unknown_escape() {
+        let err = lex("type \"a\\nb\"\ntype \"ab\\qc\"\nwait 1").unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("2: type \"ab\\qc\"\n           ^ unknown escape `\\q`"));
+    }
+
+    #[test]
+    fn lex_string_with_hex_escape() {
+        let tokens = lex_tokens("\"\\x1b[2J\"");
+        assert_eq!(tokens[0], string("\x1b[2J"));
+        assert!(lex("\"\\x1\"").is_err());
+        assert!(lex("\"\\xff\"").is_err());
+    }
+
+    #[test]
+    fn lex_regex() {
+        let tokens = lex_tokens("goto capture /let (\\w+)\\/x/ 1");
+        assert_eq!(tokens[4], Token::Regex("let (\\w+)\\/x"));
+        assert_eq!(tokens[6], int(1));
+
+        assert!(lex("goto capture /let (\\w+)\n/").is_err());
+
+        // Anywhere else a slash is not the start of a regex
+        let tokens = lex_tokens("wait 1 / 2");
+        assert!(!tokens.iter().any(|token| matches!(token, Token::Regex(_))));
+    }
+
+    #[test]
+    fn byte_offset_to_line_and_column() {
+        let source = "wait 1\ntype \"héllo\" nonl\n";
+        assert_eq!(line_col(source, 0), (1, 1));
+        assert_eq!(line_col(source, 6), (1, 7));
+        assert_eq!(line_col(source, 7), (2, 1));
+        // `é` is two bytes but one column
+        let nonl = source.find("nonl").unwrap();
+        assert_eq!(line_col(source, nonl), (2, 14));
+        assert_eq!(line_col(source, source.len()), (3, 1));
+    }
+
     #[test]
     fn span_for_comments() {
         let input = "// comment";
@@ -329,7 +834,7 @@ mod test {
             Span {
                 token: 1,
                 line: 1,
-                col: 10,
+                col: 11,
             },
             end
         );
@@ -1,4 +1,8 @@
-pub use instruction::{Dest, Instruction, Instructions, Source};
+pub use error::Error;
+pub use instruction::{
+    Condition, Dest, Instruction, Instructions, OnError, Resource, Select, Source, relative_to_script,
+};
+pub use lexer::DEFAULT_COMMENT_PREFIX;
 
 mod error;
 mod instruction;
@@ -10,3 +14,46 @@ pub fn parse<'a>(input: &'a str) -> error::Result<Instructions> {
     let tokens = lexer::lex(input)?;
     parse::parse(tokens)
 }
+
+/// Parse the input where comments start with any of `comment_prefixes` rather than `//`
+pub fn parse_with_comment_prefixes(input: &str, comment_prefixes: &[&str]) -> error::Result<Instructions> {
+    let tokens = lexer::lex_with_comment_prefixes(input, comment_prefixes)?;
+    parse::parse(tokens)
+}
+
+/// Parse exactly one instruction, e.g. a line read from a prompt.
+/// A blank or comment-only line is `None`, anything after the instruction is an error.
+pub fn parse_line(input: &str, comment_prefix: &str) -> error::Result<Option<Instruction>> {
+    let tokens = lexer::lex_with_comment_prefixes(input, &[comment_prefix])?;
+    parse::parse_line(tokens)
+}
+
+/// The instructions as JSON, to be read back with [`from_json`]
+#[cfg(feature = "serde")]
+pub fn to_json(instructions: &Instructions) -> String {
+    serde_json::to_string(instructions).expect("instructions are always valid json")
+}
+
+/// Read instructions serialized with [`to_json`]
+#[cfg(feature = "serde")]
+pub fn from_json(input: &str) -> error::Result<Instructions> {
+    serde_json::from_str(input).or_else(|err| Error::invalid_json(err, input))
+}
+
+/// Parse the input, skipping any line that fails to parse
+/// and collecting the errors rather than stopping at the first one.
+pub fn parse_recoverable(input: &str) -> (Instructions, Vec<Error>) {
+    parse_recoverable_with_comment_prefixes(input, &[DEFAULT_COMMENT_PREFIX])
+}
+
+/// Parse the input like [`parse_recoverable`], where comments start with any of
+/// `comment_prefixes` rather than `//`
+pub fn parse_recoverable_with_comment_prefixes(input: &str, comment_prefixes: &[&str]) -> (Instructions, Vec<Error>) {
+    let (tokens, mut errors) = lexer::lex_recoverable(input, comment_prefixes);
+    let (instructions, parse_errors) = parse::parse_recoverable(tokens);
+
+    // A line that fails to lex is reported in between the lines that fail to parse
+    errors.extend(parse_errors);
+    errors.sort_by_key(|err| err.line());
+    (instructions, errors)
+}
@@ -1,5 +1,7 @@
+use std::time::Duration;
+
 use crate::error::{Error, Result};
-use crate::instruction::{Dest, Instruction, Instructions, Source};
+use crate::instruction::{Condition, Dest, Instruction, Instructions, OnError, Select, Source};
 use crate::token::{Token, Tokens};
 
 struct Parser<'src> {
@@ -15,35 +17,166 @@ impl<'src> Parser<'src> {
         let mut instructions = vec![];
 
         loop {
-            match self.tokens.current() {
-                Token::Newline | Token::Comment | Token::Whitespace => {
-                    self.tokens.consume();
-                    continue;
-                }
-                Token::Eof => break,
-                _ => (),
+            self.skip_empty();
+            if self.tokens.at_end() {
+                break;
             }
 
-            let inst = self.load()?;
+            let inst = self.instruction()?;
             instructions.push(inst);
+        }
+
+        Ok(Instructions::new(instructions))
+    }
+
+    fn parse_recoverable(&mut self) -> (Instructions, Vec<Error>) {
+        let mut instructions = vec![];
+        let mut errors = vec![];
+
+        loop {
+            self.skip_empty();
+            if self.tokens.at_end() {
+                break;
+            }
+
+            let start = self.tokens.current_span();
+            match self.instruction() {
+                Ok(inst) => instructions.push(inst),
+                Err(err) => {
+                    // Resynchronise at the start of the next line
+                    errors.push(err);
+                    self.tokens.skip_line(start.line);
+                }
+            }
+        }
+
+        (Instructions::new(instructions), errors)
+    }
+
+    // Only one instruction, followed by nothing but comments
+    fn parse_line(&mut self) -> Result<Option<Instruction>> {
+        self.skip_empty();
+        if self.tokens.at_end() {
+            return Ok(None);
+        }
+
+        let inst = self.repeat()?;
+        self.skip_empty();
+        if self.tokens.at_end() {
+            return Ok(Some(inst));
+        }
+
+        let token = self.tokens.take();
+        Error::unexpected_token("end of line", token, self.tokens.spans(), self.tokens.source)
+    }
+
+    fn skip_empty(&mut self) {
+        while let Token::Newline | Token::Comment | Token::Whitespace = self.tokens.current() {
+            self.tokens.consume();
+        }
+    }
+
+    /// Nothing but the end of the instruction follows, so a word like `line` is a plain ident
+    fn at_instruction_end(&self) -> bool {
+        matches!(self.tokens.peek(0), Token::Newline | Token::RBrace | Token::Eof)
+    }
+
+    fn instruction(&mut self) -> Result<Instruction> {
+        let inst = self.repeat()?;
+
+        // The end of a block can follow the last instruction of the block on the same line
+        if let Token::RBrace = self.tokens.peek(0) {
+            return Ok(inst);
+        }
 
+        // there has to be either newline OR eof here
+        match self.tokens.take() {
+            Token::Newline | Token::Comment | Token::Whitespace | Token::Eof => Ok(inst),
+            token => Error::unexpected_token("newline or end of file", token, self.tokens.spans(), self.tokens.source),
+        }
+    }
+
+    fn repeat(&mut self) -> Result<Instruction> {
+        // repeat <int> { <instructions> }
+        // repeat until <condition> { <instructions> }
+        if self.tokens.consume_if(Token::Repeat) {
             match self.tokens.take() {
-                Token::Newline | Token::Comment | Token::Whitespace => continue,
-                Token::Eof => break,
-                token => {
-                    return Error::unexpected_token(
-                        "newline or end of file",
-                        token,
-                        self.tokens.spans(),
-                        self.tokens.source,
-                    );
+                Token::Int(count @ 0..=0xFFFF_FFFF) => {
+                    let body = self.block()?;
+                    Ok(Instruction::Repeat {
+                        count: count as u32,
+                        body,
+                    })
+                }
+                Token::Until => {
+                    let condition = self.condition()?;
+                    let body = self.block()?;
+                    Ok(Instruction::RepeatUntil { condition, body })
                 }
+                token => Error::invalid_arg("count or until", token, self.tokens.spans(), self.tokens.source),
             }
+        } else {
+            self.include()
+        }
+    }
 
-            // there has to be either newline OR eof here
+    fn include(&mut self) -> Result<Instruction> {
+        // include <string>
+        if self.tokens.consume_if(Token::Include) {
+            match self.tokens.take() {
+                Token::Str(path) => Ok(Instruction::Include(path.as_ref().into())),
+                token => Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+            }
+        } else {
+            self.load()
         }
+    }
 
-        Ok(Instructions::new(instructions))
+    fn condition(&mut self) -> Result<Condition> {
+        // buffer_contains <string>
+        // var_eq <ident> <string>
+        match self.tokens.take() {
+            Token::Ident("buffer_contains") => match self.tokens.take() {
+                Token::Str(text) => Ok(Condition::BufferContains(text.into())),
+                token => Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+            },
+            Token::Ident("var_eq") => {
+                let key = match self.tokens.take() {
+                    Token::Ident(key) => key,
+                    token => return Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
+                };
+
+                match self.tokens.take() {
+                    Token::Str(value) => Ok(Condition::VarEq(key.into(), value.into())),
+                    token => Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+                }
+            }
+            token => Error::invalid_arg("condition", token, self.tokens.spans(), self.tokens.source),
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Instruction>> {
+        // { <instructions> }
+        let token = self.tokens.take();
+        let spans = self.tokens.spans();
+        match token {
+            Token::LBrace => {}
+            token => return Error::invalid_arg("{", token, spans, self.tokens.source),
+        }
+
+        let mut body = vec![];
+        loop {
+            self.skip_empty();
+            if self.tokens.consume_if(Token::RBrace) {
+                return Ok(body);
+            }
+
+            if self.tokens.at_end() {
+                return Error::unclosed_block(spans, self.tokens.source);
+            }
+
+            body.push(self.instruction()?);
+        }
     }
 
     fn load(&mut self) -> Result<Instruction> {
@@ -51,111 +184,322 @@ impl<'src> Parser<'src> {
             match self.tokens.take() {
                 Token::Str(path) => match self.tokens.take() {
                     Token::As => match self.tokens.take() {
-                        Token::Ident(key) => Ok(Instruction::Load(path.into(), key)),
+                        Token::Ident(key) => Ok(Instruction::Load(path.as_ref().into(), key.into())),
                         token => return Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
                     },
                     token => return Error::invalid_arg("as", token, self.tokens.spans(), self.tokens.source),
                 },
                 token => Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
             }
+        } else {
+            self.edit()
+        }
+    }
+
+    fn edit(&mut self) -> Result<Instruction> {
+        // edit <string>
+        if self.tokens.consume_if(Token::Edit) {
+            match self.tokens.take() {
+                Token::Str(path) => Ok(Instruction::OpenFile(path.as_ref().into())),
+                token => Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+            }
         } else {
             self.goto()
         }
     }
 
     fn goto(&mut self) -> Result<Instruction> {
-        // goto <ident>|<int> <int>
+        // goto <ident>|<int> <int>|line <int>|line <ident>|page <int>|capture <regex> <int>|offset <int>|last_type
+        //      <center>?
         if self.tokens.consume_if(Token::Goto) {
             // <ident>
-            let instr = match self.tokens.take() {
-                Token::Ident(ident) => Instruction::Goto(Dest::Marker(ident)),
+            let dest = match self.tokens.take() {
+                Token::Ident("capture") if matches!(self.tokens.peek(0), Token::Regex(_)) => {
+                    match (self.tokens.take(), self.tokens.take()) {
+                        (Token::Regex(pattern), Token::Int(group @ 0..)) => Dest::Capture {
+                            pattern: pattern.into(),
+                            group: group as usize,
+                        },
+                        (_, token) => {
+                            return Error::invalid_arg("group", token, self.tokens.spans(), self.tokens.source);
+                        }
+                    }
+                }
+                Token::Ident("offset") if matches!(self.tokens.peek(0), Token::Int(_)) => match self.tokens.take() {
+                    Token::Int(offset @ 0..) => Dest::Offset(offset as usize),
+                    token => return Error::invalid_arg("offset", token, self.tokens.spans(), self.tokens.source),
+                },
+                Token::Ident("last_type") => Dest::LastType,
+                // A marker can be called `line` too, as in `goto line`
+                Token::Ident("line")
+                    if !self.at_instruction_end() && *self.tokens.peek(0) != Token::Ident("center") =>
+                {
+                    match self.tokens.take() {
+                        Token::Int(line @ 1..=0xFFFF) => Dest::Line(line as u16),
+                        Token::Ident(ident) => Dest::LineVariable(ident.into()),
+                        token => {
+                            return Error::invalid_arg("line number", token, self.tokens.spans(), self.tokens.source);
+                        }
+                    }
+                }
+                Token::Ident("page") if matches!(self.tokens.peek(0), Token::Int(_)) => {
+                    let token = self.tokens.take();
+                    match int::<i32>(&token) {
+                        Some(pages) if pages != 0 => Dest::PageRelative(pages),
+                        _ => return Error::invalid_arg("pages", token, self.tokens.spans(), self.tokens.source),
+                    }
+                }
+                Token::Ident(ident) => Dest::Marker(ident.into()),
                 Token::Int(row) => match self.tokens.take() {
-                    Token::Int(col) => Instruction::Goto(Dest::Relative {
+                    Token::Int(col) => Dest::Relative {
                         row: row as i32,
                         col: col as i32,
-                    }),
+                    },
                     token => return Error::invalid_arg("number", token, self.tokens.spans(), self.tokens.source),
                 },
                 token => return Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
             };
 
-            Ok(instr)
+            let center = self.tokens.consume_if(Token::Ident("center"));
+            Ok(Instruction::Goto { dest, center })
         } else {
             self.print()
         }
     }
 
     fn print(&mut self) -> Result<Instruction> {
-        // print <string>
+        // print <string> <modifiers>
+        // print tail <string> <int>
         if self.tokens.consume_if(Token::Type) {
-            let source = match self.tokens.take() {
-                Token::Str(s) => Source::Str(s),
-                Token::Ident(ident) => Source::Ident(ident),
-                token => return Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
-            };
-
-            let trim_trailing_newline = self.tokens.consume_if(Token::NoNewline);
-            Ok(Instruction::Type {
-                source,
-                trim_trailing_newline,
-                prefix_newline: false,
-            })
+            // Without a path after it `tail` is the name of a variable
+            if matches!(self.tokens.peek(1), Token::Str(_)) && self.tokens.consume_if(Token::Ident("tail")) {
+                self.tail()
+            } else {
+                self.type_args(false)
+            }
         } else {
             self.printnl()
         }
     }
 
+    fn tail(&mut self) -> Result<Instruction> {
+        let path = match self.tokens.take() {
+            Token::Str(path) => path.as_ref().into(),
+            token => return Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+        };
+
+        match self.tokens.take() {
+            Token::Int(timeout @ 0..) => Ok(Instruction::Tail {
+                path,
+                timeout: timeout as u64,
+            }),
+            token => Error::invalid_arg("seconds", token, self.tokens.spans(), self.tokens.source),
+        }
+    }
+
     fn printnl(&mut self) -> Result<Instruction> {
-        // printnl <string> <nonl>?
+        // printnl <string> <modifiers>
         if self.tokens.consume_if(Token::TypeNl) {
-            let source = match self.tokens.take() {
-                Token::Str(s) => Source::Str(s),
-                Token::Ident(ident) => Source::Ident(ident),
-                token => return Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
-            };
-
-            let trim_trailing_newline = self.tokens.consume_if(Token::NoNewline);
+            self.type_args(true)
+        } else {
+            self.type_recording()
+        }
+    }
 
-            Ok(Instruction::Type {
-                source,
-                trim_trailing_newline,
-                prefix_newline: true,
-            })
+    fn type_recording(&mut self) -> Result<Instruction> {
+        // type_recording <string>
+        if self.tokens.consume_if(Token::TypeRecording) {
+            match self.tokens.take() {
+                Token::Str(path) => Ok(Instruction::TypeRecording(path.as_ref().into())),
+                token => Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+            }
         } else {
             self.insert()
         }
     }
 
+    fn type_args(&mut self, prefix_newline: bool) -> Result<Instruction> {
+        let source = match self.tokens.take() {
+            Token::Str(s) => Source::Str(s.into()),
+            Token::Ident(ident) => Source::Ident(ident.into()),
+            Token::Equal => self.interpolated()?,
+            token => return Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
+        };
+
+        // <ident> <int>..<int>
+        let mut lines = None;
+        if matches!(source, Source::Ident(_)) && matches!(self.tokens.peek(0), Token::Int(_)) {
+            lines = Some(self.line_range()?);
+        }
+
+        // modifiers: <nonl>? <reverse>? <align int>? <reindent>? <complete string|ident>? <by word>?
+        //            <fix string|ident>? <natural>? <think int ms?>? <autoindent>? <line_pause duration>?
+        //            <autopair>?
+        //            in any order
+        let mut trim_trailing_newline = false;
+        let mut reverse = false;
+        let mut align = None;
+        let mut reindent = false;
+        let mut complete = None;
+        let mut by_word = false;
+        let mut fix = None;
+        let mut natural = false;
+        let mut think = 0;
+        let mut autoindent = false;
+        let mut line_pause = None;
+        let mut autopair = false;
+
+        loop {
+            if self.tokens.consume_if(Token::NoNewline) {
+                trim_trailing_newline = true;
+            } else if self.tokens.consume_if(Token::Ident("reverse")) {
+                reverse = true;
+            } else if self.tokens.consume_if(Token::Reindent) {
+                reindent = true;
+            } else if self.tokens.consume_if(Token::Ident("natural")) {
+                natural = true;
+            } else if self.tokens.consume_if(Token::Autoindent) {
+                autoindent = true;
+            } else if self.tokens.consume_if(Token::Ident("think")) {
+                // The unit is optional: `think 800` or `think 800ms`
+                let token = self.tokens.take();
+                match duration(&token, Duration::from_millis) {
+                    Some(duration) => think = duration.as_millis() as u64,
+                    None => return Error::invalid_arg("milliseconds", token, self.tokens.spans(), self.tokens.source),
+                }
+                // The unit can also be written apart, as in `think 800 ms`
+                if let Token::Int(_) = token {
+                    _ = self.tokens.consume_if(Token::Ident("ms"));
+                }
+            } else if self.tokens.consume_if(Token::Ident("autopair")) {
+                autopair = true;
+            } else if self.tokens.consume_if(Token::Ident("line_pause")) {
+                // A plain int is a number of milliseconds
+                let token = self.tokens.take();
+                match duration(&token, Duration::from_millis) {
+                    Some(duration) => line_pause = Some(duration),
+                    None => return Error::invalid_arg("duration", token, self.tokens.spans(), self.tokens.source),
+                }
+            } else if self.tokens.consume_if(Token::Align) {
+                let token = self.tokens.take();
+                match int::<u16>(&token) {
+                    Some(width @ 1..) => align = Some(width),
+                    _ => return Error::invalid_arg("tab width", token, self.tokens.spans(), self.tokens.source),
+                }
+            } else if self.tokens.consume_if(Token::Complete) {
+                match self.tokens.take() {
+                    Token::Str(s) => complete = Some(Source::Str(s.into())),
+                    Token::Ident(ident) => complete = Some(Source::Ident(ident.into())),
+                    token => return Error::invalid_arg("completion", token, self.tokens.spans(), self.tokens.source),
+                }
+            } else if self.tokens.consume_if(Token::Ident("by")) {
+                match self.tokens.take() {
+                    Token::Ident("word") => by_word = true,
+                    token => return Error::invalid_arg("word", token, self.tokens.spans(), self.tokens.source),
+                }
+            } else if self.tokens.consume_if(Token::Ident("fix")) {
+                match self.tokens.take() {
+                    Token::Str(s) => fix = Some(Source::Str(s.into())),
+                    Token::Ident(ident) => fix = Some(Source::Ident(ident.into())),
+                    token => return Error::invalid_arg("correction", token, self.tokens.spans(), self.tokens.source),
+                }
+            } else {
+                break;
+            }
+        }
+
+        Ok(Instruction::Type {
+            source,
+            trim_trailing_newline,
+            prefix_newline,
+            reverse,
+            align,
+            reindent,
+            complete,
+            by_word,
+            fix,
+            natural,
+            think,
+            autoindent,
+            line_pause,
+            autopair,
+            lines,
+        })
+    }
+
+    // = <string>
+    // Variables are only interpolated into a string when asked to, like `set <ident> = <string>`
+    fn interpolated(&mut self) -> Result<Source> {
+        match self.tokens.take() {
+            Token::Str(s) => Ok(Source::Interpolated(s.into())),
+            token => Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+        }
+    }
+
     fn insert(&mut self) -> Result<Instruction> {
         // insert <string>
         if self.tokens.consume_if(Token::Insert) {
             match self.tokens.take() {
-                Token::Str(s) => return Ok(Instruction::Insert(Source::Str(s))),
-                Token::Ident(ident) => return Ok(Instruction::Insert(Source::Ident(ident))),
-                token => return Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
+                Token::Str(s) => Ok(Instruction::Insert(Source::Str(s.into()))),
+                Token::Ident(ident) => Ok(Instruction::Insert(Source::Ident(ident.into()))),
+                Token::Equal => Ok(Instruction::Insert(self.interpolated()?)),
+                token => Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
             }
+        } else if self.tokens.consume_if(Token::InsertEach) {
+            // insert_each <string> <string|ident>
+            let needle = match self.tokens.take() {
+                Token::Str(needle) => needle.into(),
+                token => return Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+            };
+
+            let source = match self.tokens.take() {
+                Token::Str(s) => Source::Str(s.into()),
+                Token::Ident(ident) => Source::Ident(ident.into()),
+                Token::Equal => self.interpolated()?,
+                token => return Error::invalid_arg("ident or string", token, self.tokens.spans(), self.tokens.source),
+            };
+
+            Ok(Instruction::InsertEach { needle, source })
         } else {
             self.change()
         }
     }
 
     fn change(&mut self) -> Result<Instruction> {
-        // change <string> <string|ident>
+        // change <viewport>? <string> <string|ident> <as ident>?
         if self.tokens.consume_if(Token::Replace) {
+            // <viewport>?
+            let viewport = self.tokens.consume_if(Token::Ident("viewport"));
+
             // <string>
             let src = match self.tokens.take() {
-                Token::Str(string) => string,
+                Token::Str(string) => string.into(),
                 token => return Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
             };
 
             // <string|ident>
             let replacement = match self.tokens.take() {
-                Token::Str(string) => Source::Str(string),
-                Token::Ident(ident) => Source::Ident(ident),
+                Token::Str(string) => Source::Str(string.into()),
+                Token::Ident(ident) => Source::Ident(ident.into()),
+                Token::Equal => self.interpolated()?,
                 token => return Error::invalid_arg("string or ident", token, self.tokens.spans(), self.tokens.source),
             };
 
-            let instr = Instruction::Replace { src, replacement };
+            // <as ident>?
+            let mut capture = None;
+            if self.tokens.consume_if(Token::As) {
+                match self.tokens.take() {
+                    Token::Ident(ident) => capture = Some(ident.into()),
+                    token => return Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
+                }
+            }
+
+            let instr = Instruction::Replace {
+                src,
+                replacement,
+                capture,
+                viewport,
+            };
             Ok(instr)
         } else {
             self.delete()
@@ -163,40 +507,243 @@ impl<'src> Parser<'src> {
     }
 
     fn delete(&mut self) -> Result<Instruction> {
-        if self.tokens.consume_if(Token::Delete) { Ok(Instruction::Delete) } else { self.speed() }
+        if self.tokens.consume_if(Token::Delete) { Ok(Instruction::Delete) } else { self.backspace() }
+    }
+
+    fn backspace(&mut self) -> Result<Instruction> {
+        // backspace <int>
+        if self.tokens.consume_if(Token::Backspace) {
+            match self.tokens.take() {
+                Token::Int(count @ 0..=0xFFFF) => Ok(Instruction::Backspace(count as u16)),
+                token => Error::invalid_arg("int", token, self.tokens.spans(), self.tokens.source),
+            }
+        } else {
+            self.clear()
+        }
+    }
+
+    fn clear(&mut self) -> Result<Instruction> {
+        if self.tokens.consume_if(Token::Clear) { Ok(Instruction::Clear) } else { self.flush() }
+    }
+
+    fn flush(&mut self) -> Result<Instruction> {
+        if self.tokens.consume_if(Token::Flush) { Ok(Instruction::Flush) } else { self.breakpoint() }
+    }
+
+    fn breakpoint(&mut self) -> Result<Instruction> {
+        if self.tokens.consume_if(Token::Breakpoint) { Ok(Instruction::Breakpoint) } else { self.pause() }
+    }
+
+    fn pause(&mut self) -> Result<Instruction> {
+        if self.tokens.consume_if(Token::Pause) { Ok(Instruction::Pause) } else { self.context_dump() }
+    }
+
+    fn context_dump(&mut self) -> Result<Instruction> {
+        if self.tokens.consume_if(Token::ContextDump) { Ok(Instruction::ContextDump) } else { self.assert_var() }
+    }
+
+    fn assert_var(&mut self) -> Result<Instruction> {
+        // assert_var <ident> <string>
+        if self.tokens.consume_if(Token::AssertVar) {
+            let key = match self.tokens.take() {
+                Token::Ident(key) => key.into(),
+                token => return Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
+            };
+
+            let value = match self.tokens.take() {
+                Token::Str(value) => value.into(),
+                token => return Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+            };
+
+            Ok(Instruction::AssertVar { key, value })
+        } else {
+            self.set()
+        }
+    }
+
+    fn set(&mut self) -> Result<Instruction> {
+        // set <ident> <string|ident>
+        // set <ident> = <string>
+        if self.tokens.consume_if(Token::Set) {
+            let key = match self.tokens.take() {
+                Token::Ident(key) => key.into(),
+                token => return Error::invalid_arg("ident", token, self.tokens.spans(), self.tokens.source),
+            };
+
+            if self.tokens.consume_if(Token::Equal) {
+                return match self.tokens.take() {
+                    Token::Str(expr) => Ok(Instruction::SetExpr { key, expr: expr.into() }),
+                    token => Error::invalid_arg("expression", token, self.tokens.spans(), self.tokens.source),
+                };
+            }
+
+            let value = match self.tokens.take() {
+                Token::Str(value) => Source::Str(value.into()),
+                Token::Ident(ident) => Source::Ident(ident.into()),
+                token => return Error::invalid_arg("string or ident", token, self.tokens.spans(), self.tokens.source),
+            };
+
+            Ok(Instruction::Set { key, value })
+        } else {
+            self.anchor()
+        }
+    }
+
+    fn anchor(&mut self) -> Result<Instruction> {
+        if self.tokens.consume_if(Token::Ident("anchor")) { Ok(Instruction::Anchor) } else { self.copy() }
+    }
+
+    fn copy(&mut self) -> Result<Instruction> {
+        // copy <to ident>?
+        if self.tokens.consume_if(Token::Copy) {
+            if !self.tokens.consume_if(Token::Ident("to")) {
+                return Ok(Instruction::Copy(None));
+            }
+
+            match self.tokens.take() {
+                Token::Ident(register) => Ok(Instruction::Copy(Some(register.into()))),
+                token => Error::invalid_arg("register", token, self.tokens.spans(), self.tokens.source),
+            }
+        } else {
+            self.paste()
+        }
+    }
+
+    fn paste(&mut self) -> Result<Instruction> {
+        // paste <ident>?
+        if self.tokens.consume_if(Token::Paste) {
+            match *self.tokens.peek(0) {
+                Token::Ident(register) => {
+                    self.tokens.consume();
+                    Ok(Instruction::Paste(Some(register.into())))
+                }
+                _ => Ok(Instruction::Paste(None)),
+            }
+        } else {
+            self.surround()
+        }
+    }
+
+    fn surround(&mut self) -> Result<Instruction> {
+        // surround <string> <string>
+        if self.tokens.consume_if(Token::Surround) {
+            let left = match self.tokens.take() {
+                Token::Str(left) => left.into(),
+                token => return Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+            };
+
+            match self.tokens.take() {
+                Token::Str(right) => Ok(Instruction::Surround {
+                    left,
+                    right: right.into(),
+                }),
+                token => Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+            }
+        } else {
+            self.speed()
+        }
     }
 
     fn speed(&mut self) -> Result<Instruction> {
-        // speed <int>
+        // speed <int> <+- int>?
         if self.tokens.consume_if(Token::Speed) {
             // <int>
-            let instr = match self.tokens.take() {
-                Token::Int(speed) => Instruction::Speed(speed as u64),
+            let base = match self.tokens.take() {
+                Token::Int(speed) => speed as u64,
                 token => return Error::invalid_arg("int", token, self.tokens.spans(), self.tokens.source),
             };
 
-            Ok(instr)
+            // <+- int>?
+            let mut jitter = 0;
+            if self.tokens.consume_if(Token::PlusMinus) {
+                match self.tokens.take() {
+                    Token::Int(ms @ 0..) => jitter = ms as u64,
+                    token => return Error::invalid_arg("jitter", token, self.tokens.spans(), self.tokens.source),
+                }
+            }
+
+            Ok(Instruction::Speed { base, jitter })
         } else {
             self.select()
         }
     }
 
+    fn line_range(&mut self) -> Result<(usize, usize)> {
+        // <int>..<int> where the end is not before the start
+        let start = match self.tokens.take() {
+            Token::Int(start @ 1..) => start as usize,
+            token => return Error::invalid_arg("line number", token, self.tokens.spans(), self.tokens.source),
+        };
+
+        if !self.tokens.consume_if(Token::DotDot) {
+            let token = self.tokens.take();
+            return Error::invalid_arg("..", token, self.tokens.spans(), self.tokens.source);
+        }
+
+        match self.tokens.take() {
+            Token::Int(end @ 1..) if end as usize >= start => Ok((start, end as usize)),
+            token => Error::invalid_arg("end of range", token, self.tokens.spans(), self.tokens.source),
+        }
+    }
+
     fn select(&mut self) -> Result<Instruction> {
         // select <ident>|<int> <int>
+        // select word <+int>?
+        // select lines <int>..<int>
+        // select to here|<ident>|<int> <int>
         if self.tokens.consume_if(Token::Select) {
             let instr = match self.tokens.take() {
-                // Token::Ident(ident) => Instruction::Select(Dest::Marker(ident)),
+                Token::Ident("to") if !self.at_instruction_end() => match self.tokens.take() {
+                    Token::Ident("here") => Instruction::SelectTo(Dest::Relative { row: 0, col: 0 }),
+                    Token::Ident(ident) => Instruction::SelectTo(Dest::Marker(ident.into())),
+                    Token::Int(row) => match self.tokens.take() {
+                        Token::Int(col) => Instruction::SelectTo(Dest::Relative {
+                            row: row as i32,
+                            col: col as i32,
+                        }),
+                        token => return Error::invalid_arg("number", token, self.tokens.spans(), self.tokens.source),
+                    },
+                    token => return Error::invalid_arg("destination", token, self.tokens.spans(), self.tokens.source),
+                },
+                Token::Lines => {
+                    let (start, end) = self.line_range()?;
+                    Instruction::SelectLines { start, end }
+                }
+                Token::Ident("word") => {
+                    if !matches!(self.tokens.peek(0), Token::Int(_)) {
+                        return Ok(Instruction::SelectWords(1));
+                    }
+
+                    let token = self.tokens.take();
+                    match int::<u16>(&token).and_then(|extra| extra.checked_add(1)) {
+                        Some(count) => Instruction::SelectWords(count),
+                        None => return Error::invalid_arg("word count", token, self.tokens.spans(), self.tokens.source),
+                    }
+                }
+                Token::Ident(ident) => Instruction::Select(Select::Region(ident.into())),
                 Token::Int(width) => match self.tokens.take() {
-                    Token::Int(height) => Instruction::Select {
+                    Token::Int(height) => Instruction::Select(Select::Rect {
                         width: width as u16,
                         height: height as u16,
-                    },
+                    }),
                     token => return Error::invalid_arg("number", token, self.tokens.spans(), self.tokens.source),
                 },
                 token => return Error::invalid_arg("ident or row", token, self.tokens.spans(), self.tokens.source),
             };
 
             Ok(instr)
+        } else {
+            self.selection_stack()
+        }
+    }
+
+    fn selection_stack(&mut self) -> Result<Instruction> {
+        // push_selection
+        // pop_selection
+        if self.tokens.consume_if(Token::PushSelection) {
+            Ok(Instruction::PushSelection)
+        } else if self.tokens.consume_if(Token::PopSelection) {
+            Ok(Instruction::PopSelection)
         } else {
             self.find()
         }
@@ -206,24 +753,49 @@ impl<'src> Parser<'src> {
         // find <string>
         if self.tokens.consume_if(Token::Find) {
             let instr = match self.tokens.take() {
-                Token::Str(needle) => Instruction::Find(needle),
+                Token::Str(needle) => Instruction::Find(needle.into()),
                 token => return Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
             };
 
             Ok(instr)
+        } else {
+            self.search()
+        }
+    }
+
+    fn search(&mut self) -> Result<Instruction> {
+        // search_next <int>?
+        // search_prev <int>?
+        if self.tokens.consume_if(Token::SearchNext) {
+            Ok(Instruction::SearchNext(self.search_count()?))
+        } else if self.tokens.consume_if(Token::SearchPrev) {
+            Ok(Instruction::SearchPrev(self.search_count()?))
         } else {
             self.linepause()
         }
     }
 
+    // The number of matches to move, one unless given
+    fn search_count(&mut self) -> Result<u16> {
+        if !matches!(self.tokens.peek(0), Token::Int(_)) {
+            return Ok(1);
+        }
+
+        let token = self.tokens.take();
+        match int::<u16>(&token) {
+            Some(count @ 1..) => Ok(count),
+            _ => Error::invalid_arg("search count", token, self.tokens.spans(), self.tokens.source),
+        }
+    }
+
     fn linepause(&mut self) -> Result<Instruction> {
         if self.tokens.consume_if(Token::LinePause) {
-            let instr = match self.tokens.take() {
-                Token::Int(ms) => Instruction::LinePause(ms as u64),
-                token => return Error::invalid_arg("int", token, self.tokens.spans(), self.tokens.source),
-            };
-
-            Ok(instr)
+            // A plain int is a number of milliseconds
+            let token = self.tokens.take();
+            match duration(&token, Duration::from_millis) {
+                Some(duration) => Ok(Instruction::LinePause(duration)),
+                None => Error::invalid_arg("duration", token, self.tokens.spans(), self.tokens.source),
+            }
         } else {
             self.set_title()
         }
@@ -232,11 +804,40 @@ impl<'src> Parser<'src> {
     fn set_title(&mut self) -> Result<Instruction> {
         if self.tokens.consume_if(Token::SetTitle) {
             let instr = match self.tokens.take() {
-                Token::Str(title) => Instruction::SetTitle(title),
+                Token::Str(title) => Instruction::SetTitle(title.into()),
                 token => return Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
             };
 
             Ok(instr)
+        } else {
+            self.echo_raw()
+        }
+    }
+
+    fn echo_raw(&mut self) -> Result<Instruction> {
+        // echo_raw <string>
+        if self.tokens.consume_if(Token::EchoRaw) {
+            match self.tokens.take() {
+                Token::Str(text) => Ok(Instruction::EchoRaw(text.into())),
+                token => Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+            }
+        } else {
+            self.theme()
+        }
+    }
+
+    fn theme(&mut self) -> Result<Instruction> {
+        // theme load <string>
+        if self.tokens.consume_if(Token::Theme) {
+            if !self.tokens.consume_if(Token::Load) {
+                let token = self.tokens.take();
+                return Error::invalid_arg("load", token, self.tokens.spans(), self.tokens.source);
+            }
+
+            match self.tokens.take() {
+                Token::Str(path) => Ok(Instruction::ThemeLoad(path.as_ref().into())),
+                token => Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+            }
         } else {
             self.numbers()
         }
@@ -251,31 +852,128 @@ impl<'src> Parser<'src> {
 
             Ok(instr)
         } else {
-            self.wait()
+            self.on_error()
         }
     }
 
-    fn wait(&mut self) -> Result<Instruction> {
+    fn on_error(&mut self) -> Result<Instruction> {
+        // on_error skip|halt|retry
+        if self.tokens.consume_if(Token::OnError) {
+            let mode = match self.tokens.take() {
+                Token::Ident("skip") => OnError::Skip,
+                Token::Ident("halt") => OnError::Halt,
+                Token::Ident("retry") => OnError::Retry,
+                token => {
+                    return Error::invalid_arg("skip, halt or retry", token, self.tokens.spans(), self.tokens.source);
+                }
+            };
+
+            Ok(Instruction::OnError(mode))
+        } else {
+            self.label()
+        }
+    }
+
+    fn label(&mut self) -> Result<Instruction> {
+        // label <ident>
+        // jump <ident>
+        // Labels are checked by the vm, once included scripts are part of the script
+        if self.tokens.consume_if(Token::Label) {
+            match self.tokens.take() {
+                Token::Ident(label) => Ok(Instruction::Label(label.into())),
+                token => Error::invalid_arg("label", token, self.tokens.spans(), self.tokens.source),
+            }
+        } else if self.tokens.consume_if(Token::Jump) {
+            match self.tokens.take() {
+                Token::Ident(label) => Ok(Instruction::JumpToLabel(label.into())),
+                token => Error::invalid_arg("label", token, self.tokens.spans(), self.tokens.source),
+            }
+        } else {
+            self.wait()
+        }
+    }
+
+    fn wait(&mut self) -> Result<Instruction> {
         // if not wait then error
 
         match self.tokens.take() {
             Token::Wait => {
+                // A plain int is a number of seconds
                 let instr = match self.tokens.take() {
-                    Token::Int(seconds) => Instruction::Wait(seconds as u64),
-                    token => return Error::invalid_arg("seconds", token, self.tokens.spans(), self.tokens.source),
+                    // wait one_of <duration>+
+                    Token::Ident("one_of") => {
+                        let mut choices = vec![];
+                        while let Some(choice) = duration(self.tokens.peek(0), Duration::from_secs) {
+                            self.tokens.consume();
+                            choices.push(choice);
+                        }
+
+                        if choices.is_empty() {
+                            let token = self.tokens.take();
+                            return Error::invalid_arg("duration", token, self.tokens.spans(), self.tokens.source);
+                        }
+                        Instruction::WaitOneOf(choices)
+                    }
+                    token => match duration(&token, Duration::from_secs) {
+                        Some(duration) => Instruction::Wait(duration),
+                        None => return Error::invalid_arg("duration", token, self.tokens.spans(), self.tokens.source),
+                    },
                 };
 
                 Ok(instr)
             }
+            // wait_file <string> <int>?
+            Token::WaitFile => {
+                let path = match self.tokens.take() {
+                    Token::Str(path) => path.as_ref().into(),
+                    token => return Error::invalid_arg("string", token, self.tokens.spans(), self.tokens.source),
+                };
+
+                let timeout = match *self.tokens.peek(0) {
+                    Token::Int(seconds @ 0..) => {
+                        self.tokens.consume();
+                        Some(seconds as u64)
+                    }
+                    _ => None,
+                };
+
+                Ok(Instruction::WaitFile { path, timeout })
+            }
+            Token::RBrace => Error::unbalanced_brace(self.tokens.spans(), self.tokens.source),
             token => Error::invalid_instruction(token, self.tokens.spans(), self.tokens.source),
         }
     }
 }
 
+// A duration with a unit, or a whole number of the unit given by `from_int`
+fn duration(token: &Token<'_>, from_int: fn(u64) -> Duration) -> Option<Duration> {
+    match *token {
+        Token::Duration(duration) => Some(duration),
+        Token::Int(count @ 0..) => Some(from_int(count as u64)),
+        _ => None,
+    }
+}
+
+// An int that fits in `T`
+fn int<T: TryFrom<i64>>(token: &Token<'_>) -> Option<T> {
+    match *token {
+        Token::Int(int) => T::try_from(int).ok(),
+        _ => None,
+    }
+}
+
 pub fn parse(tokens: Tokens<'_>) -> Result<Instructions> {
     Parser::new(tokens).parse()
 }
 
+pub fn parse_recoverable(tokens: Tokens<'_>) -> (Instructions, Vec<Error>) {
+    Parser::new(tokens).parse_recoverable()
+}
+
+pub fn parse_line(tokens: Tokens<'_>) -> Result<Option<Instruction>> {
+    Parser::new(tokens).parse_line()
+}
+
 #[cfg(test)]
 mod test {
     use std::path::PathBuf;
@@ -302,7 +1000,10 @@ mod test {
     }
 
     fn goto(dest: impl Into<Dest>) -> Instruction {
-        Instruction::Goto(dest.into())
+        Instruction::Goto {
+            dest: dest.into(),
+            center: false,
+        }
     }
 
     fn print_str(s: &str) -> Instruction {
@@ -310,6 +1011,18 @@ mod test {
             source: Source::Str(s.into()),
             trim_trailing_newline: false,
             prefix_newline: false,
+            reverse: false,
+            align: None,
+            reindent: false,
+            complete: None,
+            by_word: false,
+            fix: None,
+            natural: false,
+            think: 0,
+            autoindent: false,
+            line_pause: None,
+            autopair: false,
+            lines: None,
         }
     }
 
@@ -318,6 +1031,18 @@ mod test {
             source: Source::Ident(s.into()),
             trim_trailing_newline: false,
             prefix_newline: false,
+            reverse: false,
+            align: None,
+            reindent: false,
+            complete: None,
+            by_word: false,
+            fix: None,
+            natural: false,
+            think: 0,
+            autoindent: false,
+            line_pause: None,
+            autopair: false,
+            lines: None,
         }
     }
 
@@ -326,6 +1051,8 @@ mod test {
         Instruction::Replace {
             src,
             replacement: Source::Str(s.into()),
+            capture: None,
+            viewport: false,
         }
     }
 
@@ -334,11 +1061,13 @@ mod test {
         Instruction::Replace {
             src,
             replacement: Source::Ident(s.into()),
+            capture: None,
+            viewport: false,
         }
     }
 
     fn wait(secs: u64) -> Instruction {
-        Instruction::Wait(secs)
+        Instruction::Wait(Duration::from_secs(secs))
     }
 
     #[test]
@@ -348,6 +1077,22 @@ mod test {
         assert_eq!(output, expected);
     }
 
+    #[test]
+    fn parse_include() {
+        let output = parse_ok("include \"intro.echo\"");
+        assert_eq!(output, vec![Instruction::Include("intro.echo".into())]);
+
+        assert!(parse("include intro").is_err());
+    }
+
+    #[test]
+    fn parse_edit() {
+        let output = parse_ok("edit \"src/main.rs\"");
+        assert_eq!(output, vec![Instruction::OpenFile("src/main.rs".into())]);
+
+        assert!(parse("edit main").is_err());
+    }
+
     #[test]
     fn parse_goto() {
         let output = parse_ok("goto aaa");
@@ -370,6 +1115,420 @@ mod test {
         assert_eq!(output, expected);
     }
 
+    #[test]
+    fn parse_type_line_range() {
+        let output = parse_ok("type foo 10..20\ntype foo reverse");
+        let Instruction::Type { source, lines, .. } = &output[0] else { panic!() };
+        assert_eq!(*source, Source::Ident("foo".into()));
+        assert_eq!(*lines, Some((10, 20)));
+        assert!(matches!(output[1], Instruction::Type { lines: None, .. }));
+        assert!(matches!(output[1], Instruction::Type { reverse: true, .. }));
+
+        // Line numbers start at 1, and the range can't be backwards
+        assert!(parse("type foo 0..2").is_err());
+        assert!(parse("type foo 5..2").is_err());
+        assert!(parse("type foo 3..-1").is_err());
+        assert!(parse("type foo 5").is_err());
+        assert!(parse("type \"foo\" 1..2").is_err());
+    }
+
+    #[test]
+    fn parse_type_reverse() {
+        let output = parse_ok("type \"a string\" reverse");
+        let expected = vec![Instruction::Type {
+            source: Source::Str("a string".into()),
+            trim_trailing_newline: false,
+            prefix_newline: false,
+            reverse: true,
+            align: None,
+            reindent: false,
+            complete: None,
+            by_word: false,
+            fix: None,
+            natural: false,
+            think: 0,
+            autoindent: false,
+            line_pause: None,
+            autopair: false,
+            lines: None,
+        }];
+        assert_eq!(output, expected);
+
+        let output = parse_ok("typenl aaa nonl reverse");
+        let expected = vec![Instruction::Type {
+            source: Source::Ident("aaa".into()),
+            trim_trailing_newline: true,
+            prefix_newline: true,
+            reverse: true,
+            align: None,
+            reindent: false,
+            complete: None,
+            by_word: false,
+            fix: None,
+            natural: false,
+            think: 0,
+            autoindent: false,
+            line_pause: None,
+            autopair: false,
+            lines: None,
+        }];
+        assert_eq!(output, expected);
+
+        let output = parse_ok("load \"a\" as reverse\ngoto reverse");
+        assert_eq!(output, vec![load("a", "reverse"), goto("reverse")]);
+    }
+
+    #[test]
+    fn parse_type_align() {
+        let output = parse_ok("type \"a\tb\" align 8");
+        let expected = vec![Instruction::Type {
+            source: Source::Str("a\tb".into()),
+            trim_trailing_newline: false,
+            prefix_newline: false,
+            reverse: false,
+            align: Some(8),
+            reindent: false,
+            complete: None,
+            by_word: false,
+            fix: None,
+            natural: false,
+            think: 0,
+            autoindent: false,
+            line_pause: None,
+            autopair: false,
+            lines: None,
+        }];
+        assert_eq!(output, expected);
+
+        assert!(parse("type \"a\tb\" align 0").is_err());
+        assert!(parse("type \"a\tb\" align 65536").is_err());
+        assert!(parse("type \"a\tb\" align -4").is_err());
+        assert!(parse("type \"a\tb\" align").is_err());
+    }
+
+    #[test]
+    fn parse_type_reindent() {
+        let output = parse_ok("type snippet reindent nonl");
+        let expected = vec![Instruction::Type {
+            source: Source::Ident("snippet".into()),
+            trim_trailing_newline: true,
+            prefix_newline: false,
+            reverse: false,
+            align: None,
+            reindent: true,
+            complete: None,
+            by_word: false,
+            fix: None,
+            natural: false,
+            think: 0,
+            autoindent: false,
+            line_pause: None,
+            autopair: false,
+            lines: None,
+        }];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parse_type_complete() {
+        let output = parse_ok("type \"prin\" complete \"println!()\"");
+        let expected = vec![Instruction::Type {
+            source: Source::Str("prin".into()),
+            trim_trailing_newline: false,
+            prefix_newline: false,
+            reverse: false,
+            align: None,
+            reindent: false,
+            complete: Some(Source::Str("println!()".into())),
+            by_word: false,
+            fix: None,
+            natural: false,
+            think: 0,
+            autoindent: false,
+            line_pause: None,
+            autopair: false,
+            lines: None,
+        }];
+        assert_eq!(output, expected);
+
+        let output = parse_ok("type \"prin\" nonl complete macro");
+        let Instruction::Type { complete, .. } = &output[0] else { panic!() };
+        assert_eq!(complete, &Some(Source::Ident("macro".into())));
+
+        assert!(parse("type \"prin\" complete").is_err());
+    }
+
+    #[test]
+    fn parse_type_by_word() {
+        let output = parse_ok("type \"let a = 1;\" by word");
+        let expected = vec![Instruction::Type {
+            source: Source::Str("let a = 1;".into()),
+            trim_trailing_newline: false,
+            prefix_newline: false,
+            reverse: false,
+            align: None,
+            reindent: false,
+            complete: None,
+            by_word: true,
+            fix: None,
+            natural: false,
+            think: 0,
+            autoindent: false,
+            line_pause: None,
+            autopair: false,
+            lines: None,
+        }];
+        assert_eq!(output, expected);
+
+        assert!(parse("type \"a\" by").is_err());
+        assert!(parse("type \"a\" by line").is_err());
+
+        let output = parse_ok("load \"a\" as by\ngoto by");
+        assert_eq!(output, vec![load("a", "by"), goto("by")]);
+    }
+
+    #[test]
+    fn parse_type_fix() {
+        let output = parse_ok("type \"fn mian\" fix \"main\"");
+        let expected = vec![Instruction::Type {
+            source: Source::Str("fn mian".into()),
+            trim_trailing_newline: false,
+            prefix_newline: false,
+            reverse: false,
+            align: None,
+            reindent: false,
+            complete: None,
+            by_word: false,
+            fix: Some(Source::Str("main".into())),
+            natural: false,
+            think: 0,
+            autoindent: false,
+            line_pause: None,
+            autopair: false,
+            lines: None,
+        }];
+        assert_eq!(output, expected);
+
+        assert!(parse("type \"fn mian\" fix").is_err());
+
+        let output = parse_ok("load \"a\" as fix\ngoto fix");
+        assert_eq!(output, vec![load("a", "fix"), goto("fix")]);
+    }
+
+    #[test]
+    fn parse_type_natural() {
+        let output = parse_ok("type \"a, b.\" natural nonl");
+        assert!(matches!(
+            output[0],
+            Instruction::Type {
+                natural: true,
+                trim_trailing_newline: true,
+                ..
+            }
+        ));
+
+        let output = parse_ok("type \"a, b.\"");
+        assert!(matches!(output[0], Instruction::Type { natural: false, .. }));
+
+        let output = parse_ok("load \"a\" as natural\ngoto natural");
+        assert_eq!(output, vec![load("a", "natural"), goto("natural")]);
+    }
+
+    #[test]
+    fn parse_type_think() {
+        let output = parse_ok("type \"a\" think 800ms");
+        assert!(matches!(output[0], Instruction::Type { think: 800, .. }));
+
+        let output = parse_ok("type \"a\" think 800 reverse");
+        assert!(matches!(
+            output[0],
+            Instruction::Type {
+                think: 800,
+                reverse: true,
+                ..
+            }
+        ));
+
+        let output = parse_ok("type \"a\" think 800 ms");
+        assert!(matches!(output[0], Instruction::Type { think: 800, .. }));
+
+        let output = parse_ok("type \"a\"");
+        assert!(matches!(output[0], Instruction::Type { think: 0, .. }));
+
+        assert!(parse("type \"a\" think").is_err());
+        assert!(parse("type \"a\" think -1").is_err());
+
+        let output = parse_ok("load \"a\" as think\ngoto think");
+        assert_eq!(output, vec![load("a", "think"), goto("think")]);
+    }
+
+    #[test]
+    fn parse_type_autoindent() {
+        let output = parse_ok("type \"fn a() {\nb\n}\" autoindent");
+        assert!(matches!(output[0], Instruction::Type { autoindent: true, .. }));
+
+        let output = parse_ok("type \"a\"");
+        assert!(matches!(output[0], Instruction::Type { autoindent: false, .. }));
+    }
+
+    #[test]
+    fn parse_type_line_pause() {
+        let output = parse_ok("type \"a\nb\" line_pause 300ms natural\ntype \"c\" line_pause 50\ntype \"d\"");
+        let pauses = output
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Type { line_pause, .. } => *line_pause,
+                _ => panic!("{instruction:?} is not a type"),
+            })
+            .collect::<Vec<_>>();
+        let expected = [Some(Duration::from_millis(300)), Some(Duration::from_millis(50)), None];
+        assert_eq!(pauses, expected);
+        assert!(matches!(output[0], Instruction::Type { natural: true, .. }));
+
+        assert!(parse("type \"a\" line_pause").is_err());
+        assert!(parse("type \"a\" line_pause \"300ms\"").is_err());
+    }
+
+    #[test]
+    fn parse_type_autopair() {
+        let output = parse_ok("type \"f(a)\" autopair natural\ntype \"b\"");
+        assert!(matches!(output[0], Instruction::Type { autopair: true, .. }));
+        assert!(matches!(output[0], Instruction::Type { natural: true, .. }));
+        assert!(matches!(output[1], Instruction::Type { autopair: false, .. }));
+    }
+
+    #[test]
+    fn parse_type_tail() {
+        let output = parse_ok("type tail \"app.log\" 10");
+        let expected = vec![Instruction::Tail {
+            path: "app.log".into(),
+            timeout: 10,
+        }];
+        assert_eq!(output, expected);
+
+        assert!(parse("type tail \"app.log\"").is_err());
+        assert!(parse("type tail log 10").is_err());
+
+        let output = parse_ok("load \"a\" as tail\ntype tail");
+        assert_eq!(output, vec![load("a", "tail"), print_ident("tail")]);
+    }
+
+    #[test]
+    fn parse_insert_each() {
+        let output = parse_ok("insert_each \"fn\" \"pub \"\ninsert_each \"fn\" vis");
+        let expected = vec![
+            Instruction::InsertEach {
+                needle: "fn".into(),
+                source: Source::Str("pub ".into()),
+            },
+            Instruction::InsertEach {
+                needle: "fn".into(),
+                source: Source::Ident("vis".into()),
+            },
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("insert_each fn \"pub \"").is_err());
+        assert!(parse("insert_each \"fn\"").is_err());
+    }
+
+    #[test]
+    fn parse_select() {
+        let output = parse_ok("select 3 4\nselect main");
+        let expected = vec![
+            Instruction::Select(Select::Rect { width: 3, height: 4 }),
+            Instruction::Select(Select::Region("main".into())),
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("select 3").is_err());
+        assert!(parse("select \"main\"").is_err());
+    }
+
+    #[test]
+    fn parse_select_word() {
+        let output = parse_ok("select word");
+        assert_eq!(output, vec![Instruction::SelectWords(1)]);
+
+        let output = parse_ok("select word +2");
+        assert_eq!(output, vec![Instruction::SelectWords(3)]);
+
+        assert!(parse("select word -2").is_err());
+        assert!(parse("select word 65535").is_err());
+        assert!(parse("select word 65536").is_err());
+
+        // Anywhere else `word` is a plain ident
+        let output = parse_ok("load \"a\" as word\ngoto word");
+        assert_eq!(output, vec![load("a", "word"), goto("word")]);
+    }
+
+    #[test]
+    fn parse_search_next_and_prev() {
+        let output = parse_ok("find \"fn\"\nsearch_next\nsearch_next 3\nsearch_prev 2");
+        let expected = vec![
+            Instruction::Find("fn".into()),
+            Instruction::SearchNext(1),
+            Instruction::SearchNext(3),
+            Instruction::SearchPrev(2),
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("search_next 0").is_err());
+        assert!(parse("search_next 65536").is_err());
+        assert!(parse("search_prev -1").is_err());
+        assert!(parse("search_prev \"fn\"").is_err());
+    }
+
+    #[test]
+    fn parse_anchor_and_select_to() {
+        let output = parse_ok("anchor\nselect to here\nselect to end\nselect to 2 -1");
+        let expected = vec![
+            Instruction::Anchor,
+            Instruction::SelectTo((0, 0).into()),
+            Instruction::SelectTo("end".into()),
+            Instruction::SelectTo((2, -1).into()),
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("select to 2").is_err());
+
+        // Without a destination `to` is the name of a region, and `here` and `anchor` are markers elsewhere
+        let output = parse_ok("select to\ngoto to\nload \"a\" as anchor\ngoto anchor\ngoto here");
+        let expected = vec![
+            Instruction::Select(Select::Region("to".into())),
+            goto("to"),
+            load("a", "anchor"),
+            goto("anchor"),
+            goto("here"),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parse_select_lines() {
+        let output = parse_ok("select lines 10..15");
+        assert_eq!(output, vec![Instruction::SelectLines { start: 10, end: 15 }]);
+
+        let output = parse_ok("select lines 3..3");
+        assert_eq!(output, vec![Instruction::SelectLines { start: 3, end: 3 }]);
+
+        assert!(parse("select lines 15..10").is_err());
+        assert!(parse("select lines 0..10").is_err());
+        assert!(parse("select lines 10 15").is_err());
+        assert!(parse("select lines 3..-1").is_err());
+    }
+
+    #[test]
+    fn parse_push_and_pop_selection() {
+        let output = parse_ok("push_selection\nselect word\npop_selection");
+        let expected = vec![
+            Instruction::PushSelection,
+            Instruction::SelectWords(1),
+            Instruction::PopSelection,
+        ];
+        assert_eq!(output, expected);
+    }
+
     #[test]
     fn parse_replace() {
         let output = parse_ok("replace \"a\" \"b\"");
@@ -381,6 +1540,39 @@ mod test {
         assert_eq!(output, expected);
     }
 
+    #[test]
+    fn parse_replace_capture() {
+        let output = parse_ok("replace \"old\" \"new\" as prev");
+        let expected = vec![Instruction::Replace {
+            src: "old".into(),
+            replacement: Source::Str("new".into()),
+            capture: Some("prev".into()),
+            viewport: false,
+        }];
+        assert_eq!(output, expected);
+
+        assert!(parse("replace \"old\" \"new\" as").is_err());
+        assert!(parse("replace \"old\" \"new\" as \"prev\"").is_err());
+    }
+
+    #[test]
+    fn parse_replace_viewport() {
+        let output = parse_ok("replace viewport \"old\" new");
+        let expected = vec![Instruction::Replace {
+            src: "old".into(),
+            replacement: Source::Ident("new".into()),
+            capture: None,
+            viewport: true,
+        }];
+        assert_eq!(output, expected);
+
+        // A variable named viewport is still a replacement
+        let output = parse_ok("replace \"old\" viewport");
+        assert_eq!(output, vec![replace_ident("old", "viewport")]);
+
+        assert!(parse("replace viewport viewport \"old\" \"new\"").is_err());
+    }
+
     #[test]
     fn parse_wait() {
         let output = parse_ok("wait 123");
@@ -388,6 +1580,582 @@ mod test {
         assert_eq!(output, expected);
     }
 
+    #[test]
+    fn parse_wait_duration() {
+        let output = parse_ok("wait 500ms\nwait 1.5s\nwait 2m\nwait 3");
+        let expected = vec![
+            Instruction::Wait(Duration::from_millis(500)),
+            Instruction::Wait(Duration::from_millis(1500)),
+            Instruction::Wait(Duration::from_secs(120)),
+            wait(3),
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("wait 1.5").is_err());
+        assert!(parse("wait -1s").is_err());
+        assert!(parse("wait -1").is_err());
+        assert!(parse("wait 5min").is_err());
+        assert!(parse("wait 99999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn parse_linepause_duration() {
+        let output = parse_ok("linepause 200\nlinepause 0.5s");
+        let expected = vec![
+            Instruction::LinePause(Duration::from_millis(200)),
+            Instruction::LinePause(Duration::from_millis(500)),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parse_speed_jitter() {
+        let output = parse_ok("speed 40\nspeed 40 +- 15\nspeed 40 +-15");
+        let expected = vec![
+            Instruction::Speed { base: 40, jitter: 0 },
+            Instruction::Speed { base: 40, jitter: 15 },
+            Instruction::Speed { base: 40, jitter: 15 },
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("speed 40 +-").is_err());
+        assert!(parse("speed 40 +- -5").is_err());
+    }
+
+    #[test]
+    fn parse_wait_one_of() {
+        let output = parse_ok("wait one_of 1 2 500ms\nwait one_of 3");
+        let secs = Duration::from_secs;
+        let expected = vec![
+            Instruction::WaitOneOf(vec![secs(1), secs(2), Duration::from_millis(500)]),
+            Instruction::WaitOneOf(vec![secs(3)]),
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("wait one_of").is_err());
+        assert!(parse("wait one_of \"1\"").is_err());
+        assert!(parse("wait one_of 1 -2").is_err());
+    }
+
+    #[test]
+    fn parse_wait_file() {
+        let output = parse_ok("wait_file \"target/done\"");
+        let expected = vec![Instruction::WaitFile {
+            path: "target/done".into(),
+            timeout: None,
+        }];
+        assert_eq!(output, expected);
+
+        let output = parse_ok("wait_file \"target/done\" 10");
+        let expected = vec![Instruction::WaitFile {
+            path: "target/done".into(),
+            timeout: Some(10),
+        }];
+        assert_eq!(output, expected);
+
+        assert!(parse("wait_file done").is_err());
+        assert!(parse("wait_file \"target/done\" -1").is_err());
+    }
+
+    #[test]
+    fn parse_flush() {
+        let output = parse_ok("flush\nwait 1");
+        let expected = vec![Instruction::Flush, wait(1)];
+        assert_eq!(output, expected);
+
+        assert!(parse("flush 1").is_err());
+    }
+
+    #[test]
+    fn parse_copy_and_paste() {
+        let output = parse_ok("copy\ncopy to a\npaste\npaste a");
+        let expected = vec![
+            Instruction::Copy(None),
+            Instruction::Copy(Some("a".into())),
+            Instruction::Paste(None),
+            Instruction::Paste(Some("a".into())),
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("copy a").is_err());
+        assert!(parse("copy to").is_err());
+        assert!(parse("paste \"a\"").is_err());
+
+        let output = parse_ok("load \"a\" as to\ncopy to to");
+        assert_eq!(output, vec![load("a", "to"), Instruction::Copy(Some("to".into()))]);
+    }
+
+    #[test]
+    fn parse_surround() {
+        let output = parse_ok("surround \"(\" \")\"\nsurround '\"' '\"'");
+        let expected = vec![
+            Instruction::Surround {
+                left: "(".into(),
+                right: ")".into(),
+            },
+            Instruction::Surround {
+                left: "\"".into(),
+                right: "\"".into(),
+            },
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("surround \"(\"").is_err());
+        assert!(parse("surround \"(\" close").is_err());
+    }
+
+    #[test]
+    fn parse_type_recording() {
+        let output = parse_ok("type_recording \"keys.json\"");
+        assert_eq!(output, vec![Instruction::TypeRecording("keys.json".into())]);
+
+        assert!(parse("type_recording keys").is_err());
+        assert!(parse("type_recording").is_err());
+    }
+
+    #[test]
+    fn parse_theme_load() {
+        let output = parse_ok("theme load \"dark.toml\"");
+        assert_eq!(output, vec![Instruction::ThemeLoad("dark.toml".into())]);
+
+        assert!(parse("theme \"dark.toml\"").is_err());
+        assert!(parse("theme load dark").is_err());
+    }
+
+    #[test]
+    fn parse_echo_raw() {
+        // Escapes are decoded, so the control characters are written as they are
+        let output = parse_ok("echo_raw \"\\x1b[2J\\x1b[H\"");
+        assert_eq!(output, vec![Instruction::EchoRaw("\x1b[2J\x1b[H".into())]);
+
+        assert!(parse("echo_raw").is_err());
+        assert!(parse("echo_raw 27").is_err());
+    }
+
+    #[test]
+    fn parse_backspace() {
+        let output = parse_ok("backspace 5");
+        assert_eq!(output, vec![Instruction::Backspace(5)]);
+
+        let err = parse("backspace \"foo\"").unwrap_err();
+        assert!(err.to_string().contains("expected `int`, found `\"foo\"`"));
+    }
+
+    #[test]
+    fn parse_clear() {
+        let output = parse_ok("wait 1\nclear\nwait 2");
+        let expected = vec![wait(1), Instruction::Clear, wait(2)];
+        assert_eq!(output, expected);
+
+        assert!(parse("clear 1").is_err());
+    }
+
+    #[test]
+    fn parse_pause() {
+        let output = parse_ok("wait 1\npause\nwait 2");
+        let expected = vec![wait(1), Instruction::Pause, wait(2)];
+        assert_eq!(output, expected);
+
+        let msg = parse("pause 1").unwrap_err().to_string();
+        assert!(msg.contains("unexpected token, `newline or end of file`, found `1`"));
+    }
+
+    #[test]
+    fn parse_breakpoint() {
+        let output = parse_ok("wait 1\nbreakpoint\nwait 2");
+        let expected = vec![wait(1), Instruction::Breakpoint, wait(2)];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parse_context_dump() {
+        let output = parse_ok("load \"a.rs\" as a\ncontext_dump");
+        let expected = vec![Instruction::Load("a.rs".into(), "a".into()), Instruction::ContextDump];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parse_assert_var() {
+        let output = parse_ok("assert_var a \"fn a() {}\"");
+        let expected = vec![Instruction::AssertVar {
+            key: "a".into(),
+            value: "fn a() {}".into(),
+        }];
+        assert_eq!(output, expected);
+
+        assert!(parse("assert_var \"a\" \"b\"").is_err());
+        assert!(parse("assert_var a").is_err());
+    }
+
+    #[test]
+    fn parse_set() {
+        let output = parse_ok("set total = \"${a} + ${b}\"\nset greeting \"hello {name}\"\nset a b");
+        let expected = vec![
+            Instruction::SetExpr {
+                key: "total".into(),
+                expr: "${a} + ${b}".into(),
+            },
+            Instruction::Set {
+                key: "greeting".into(),
+                value: Source::Str("hello {name}".into()),
+            },
+            Instruction::Set {
+                key: "a".into(),
+                value: Source::Ident("b".into()),
+            },
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("set \"i\" \"1\"").is_err());
+        assert!(parse("set i 1").is_err());
+        assert!(parse("set i = a").is_err());
+        assert!(parse("set i").is_err());
+    }
+
+    #[test]
+    fn parse_interpolated_strings() {
+        let output = parse_ok("insert = \"${name}\"\ninsert_each \"x\" = \"${a}\"\ninsert \"${name}\"");
+        let expected = vec![
+            Instruction::Insert(Source::Interpolated("${name}".into())),
+            Instruction::InsertEach {
+                needle: "x".into(),
+                source: Source::Interpolated("${a}".into()),
+            },
+            Instruction::Insert(Source::Str("${name}".into())),
+        ];
+        assert_eq!(output, expected);
+
+        let output = parse_ok("type = \"Hello ${name}\"");
+        let Instruction::Type { source, .. } = &output[0] else { panic!() };
+        assert_eq!(*source, Source::Interpolated("Hello ${name}".into()));
+
+        assert!(parse("insert = name").is_err());
+        assert!(parse("type =").is_err());
+    }
+
+    #[test]
+    fn parse_on_error() {
+        let output = parse_ok("on_error skip\non_error halt\non_error retry");
+        let expected = vec![
+            Instruction::OnError(OnError::Skip),
+            Instruction::OnError(OnError::Halt),
+            Instruction::OnError(OnError::Retry),
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("on_error ignore").is_err());
+    }
+
+    #[test]
+    fn parse_repeat() {
+        let output = parse_ok("repeat 3 { wait 1 }");
+        let expected = vec![Instruction::Repeat {
+            count: 3,
+            body: vec![wait(1)],
+        }];
+        assert_eq!(output, expected);
+
+        let output = parse_ok("repeat 2 {\n    type \"a\"\n    repeat 2 {\n        wait 1\n    }\n}\nwait 2");
+        let expected = vec![
+            Instruction::Repeat {
+                count: 2,
+                body: vec![
+                    print_str("a"),
+                    Instruction::Repeat {
+                        count: 2,
+                        body: vec![wait(1)],
+                    },
+                ],
+            },
+            wait(2),
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("repeat -1 { wait 1 }").is_err());
+    }
+
+    #[test]
+    fn parse_repeat_missing_brace() {
+        let err = parse("repeat 3 {\n    wait 1\n").unwrap_err();
+        assert!(err.to_string().contains("missing `}` at the end of the block"));
+
+        let err = parse("wait 1\n}").unwrap_err();
+        assert!(err.to_string().contains("`}` without a block to close"));
+
+        let err = parse("repeat 3 { wait 1 }}").unwrap_err();
+        assert!(err.to_string().contains("`}` without a block to close"));
+    }
+
+    #[test]
+    fn parse_repeat_until() {
+        let output = parse_ok("repeat until buffer_contains \"DONE\" {\n    type \"a\"\n    wait 1\n}\nwait 2");
+        let expected = vec![
+            Instruction::RepeatUntil {
+                condition: Condition::BufferContains("DONE".into()),
+                body: vec![print_str("a"), wait(1)],
+            },
+            wait(2),
+        ];
+        assert_eq!(output, expected);
+
+        let output = parse_ok("repeat until var_eq name \"parrot\" { wait 1 }");
+        let expected = vec![Instruction::RepeatUntil {
+            condition: Condition::VarEq("name".into(), "parrot".into()),
+            body: vec![wait(1)],
+        }];
+        assert_eq!(output, expected);
+
+        assert!(parse("repeat until { wait 1 }").is_err());
+        assert!(parse("repeat until var_eq name { wait 1 }").is_err());
+        assert!(parse("repeat until buffer_contains \"a\" wait 1").is_err());
+        assert!(parse("repeat until buffer_contains \"a\" {\nwait 1\n").is_err());
+    }
+
+    #[test]
+    fn parse_forward_jump() {
+        let output = parse_ok("jump end\nwait 1\nlabel end\nwait 2");
+        let expected = vec![
+            Instruction::JumpToLabel("end".into()),
+            wait(1),
+            Instruction::Label("end".into()),
+            wait(2),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parse_backward_jump() {
+        let output = parse_ok("label start\nwait 1\njump start");
+        let expected = vec![
+            Instruction::Label("start".into()),
+            wait(1),
+            Instruction::JumpToLabel("start".into()),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn error_position() {
+        let err = parse("wait 1\n\ngoto 3 \"four\"\nwait 2").unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.starts_with("error at 3:8: expected `number`, found `\"four\"`\n"));
+        assert!(msg.contains("3: goto 3 \"four\"\n"));
+
+        let msg = parse("wait 1\n  bogus 2").unwrap_err().to_string();
+        assert!(msg.starts_with("error at 2:3: invalid instruction: `bogus`"));
+
+        let err = parse("clear\nclear 2").unwrap_err();
+        assert!(err.to_string().starts_with("error at 2:7: unexpected token"));
+    }
+
+    #[test]
+    fn error_caret_under_token() {
+        let msg = parse("wait 1\ngoto 3 \"four\"").unwrap_err().to_string();
+        assert!(msg.contains("2: goto 3 \"four\"\n          ^~~~~~ expected `number`"));
+
+        // Tabs are expanded so the caret lines up with the token
+        let msg = parse("repeat 2 {\n\tgoto line \"x2\"\n}").unwrap_err().to_string();
+        assert!(msg.contains("2:     goto line \"x2\"\n                 ^~~~ expected `line number`"));
+
+        let msg = parse("wait 1\n \t\twait x").unwrap_err().to_string();
+        assert!(msg.contains("2:         wait x\n                ^ expected `duration`"));
+
+        // Wide characters take up two columns
+        let msg = parse("wait 1\nfind \"日本\" x").unwrap_err().to_string();
+        assert!(msg.contains("2: find \"日本\" x\n               ^ unexpected token"));
+    }
+
+    #[test]
+    fn error_caret_aligns_with_token() {
+        // The script, the line of the error and the token the caret is under
+        let cases = [
+            // invalid_arg
+            ("goto line 0", "goto line 0", "0"),
+            ("wait 1\nselect lines 3 4", "select lines 3 4", "4"),
+            ("wait 1\nselect lines 3..1", "select lines 3..1", "1"),
+            ("repeat 2 x", "repeat 2 x", "x"),
+            ("insert_each \"a\" 3\nwait 1", "insert_each \"a\" 3", "3"),
+            ("type \"a\" align 0", "type \"a\" align 0", "0"),
+            // unexpected_token
+            ("clear 2", "clear 2", "2"),
+            ("wait 1  wait 2", "wait 1  wait 2", "wait"),
+            ("wait 1\nbackspace 2 3", "backspace 2 3", "3"),
+        ];
+
+        for (code, line, token) in cases {
+            let msg = parse(code).unwrap_err().to_string();
+            let mut lines = msg.lines().skip_while(|l| !l.ends_with(line)).skip(1);
+            let caret = lines.next().unwrap_or_else(|| panic!("no caret for {code:?} in {msg}"));
+
+            let column = caret.find('^').unwrap();
+            let expected = msg.lines().find(|l| l.ends_with(line)).unwrap().rfind(token).unwrap();
+            assert_eq!(column, expected, "{code:?}\n{msg}");
+
+            let underline = caret[column..].split(' ').next().unwrap();
+            assert_eq!(underline.len(), token.len(), "{code:?}\n{msg}");
+        }
+    }
+
+    #[test]
+    fn error_position_counts_characters() {
+        // Every character is one column, however many bytes it takes
+        let msg = parse("type \"héllo wörld\" align x").unwrap_err().to_string();
+        assert!(msg.starts_with("error at 1:26: expected `tab width`, found `x`"));
+
+        let err = parse("wait 1\ntype \"ü\\q\"").unwrap_err();
+        assert!(err.to_string().starts_with("error at 2:8: unknown escape `\\q`"));
+    }
+
+    #[test]
+    fn suggest_closest_instruction() {
+        let msg = parse("typp \"hello\"").unwrap_err().to_string();
+        assert!(msg.starts_with("error at 1:1: invalid instruction: `typp`, did you mean `type`?"));
+
+        let msg = parse("selct 1 2").unwrap_err().to_string();
+        assert!(msg.contains("did you mean `select`?"));
+
+        let msg = parse("xylophone 1").unwrap_err().to_string();
+        assert!(msg.starts_with("error at 1:1: invalid instruction: `xylophone`\n"));
+    }
+
+    #[test]
+    fn parse_jump_to_label_elsewhere() {
+        // The label can be in an included script
+        let output = parse_ok("include \"intro.echo\"\njump intro");
+        let expected = vec![
+            Instruction::Include("intro.echo".into()),
+            Instruction::JumpToLabel("intro".into()),
+        ];
+        assert_eq!(output, expected);
+
+        assert!(parse("label 1").is_err());
+        assert!(parse("jump \"intro\"").is_err());
+    }
+
+    #[test]
+    fn parse_goto_capture() {
+        let output = parse_ok("goto capture /let (\\w+)/ 1");
+        let expected = goto(Dest::Capture {
+            pattern: "let (\\w+)".into(),
+            group: 1,
+        });
+        assert_eq!(output, vec![expected]);
+
+        // A marker can still be named capture
+        let output = parse_ok("goto capture");
+        assert_eq!(output, vec![goto("capture")]);
+
+        assert!(parse("goto capture /let (\\w+)/").is_err());
+        assert!(parse("goto capture /let (\\w+)/ -1").is_err());
+
+        // A slash anywhere else doesn't start a regex
+        let msg = parse("wait 1 / 2").unwrap_err().to_string();
+        assert!(msg.starts_with("error at 1:10: unexpected token"));
+    }
+
+    #[test]
+    fn parse_goto_last_type() {
+        let output = parse_ok("goto last_type\ngoto last_type center");
+        let expected = vec![
+            goto(Dest::LastType),
+            Instruction::Goto {
+                dest: Dest::LastType,
+                center: true,
+            },
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parse_goto_offset() {
+        let output = parse_ok("goto offset 1234\ngoto offset 0 center");
+        let expected = vec![
+            goto(Dest::Offset(1234)),
+            Instruction::Goto {
+                dest: Dest::Offset(0),
+                center: true,
+            },
+        ];
+        assert_eq!(output, expected);
+
+        // A marker can still be named offset
+        let output = parse_ok("goto offset");
+        assert_eq!(output, vec![goto("offset")]);
+
+        assert!(parse("goto offset -1").is_err());
+    }
+
+    #[test]
+    fn parse_goto_line() {
+        let output = parse_ok("goto line 10");
+        assert_eq!(output, vec![goto(Dest::Line(10))]);
+
+        let output = parse_ok("goto line pos");
+        assert_eq!(output, vec![goto(Dest::LineVariable("pos".into()))]);
+
+        assert!(parse("goto line 0").is_err());
+        assert!(parse("goto line \"10\"").is_err());
+
+        // `line` on its own is a marker
+        let output = parse_ok("load \"a\" as line\ngoto line\ngoto line center");
+        let expected = vec![
+            load("a", "line"),
+            goto("line"),
+            Instruction::Goto {
+                dest: "line".into(),
+                center: true,
+            },
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parse_goto_page() {
+        let output = parse_ok("goto page +1");
+        assert_eq!(output, vec![goto(Dest::PageRelative(1))]);
+
+        let output = parse_ok("goto page -2");
+        assert_eq!(output, vec![goto(Dest::PageRelative(-2))]);
+
+        assert!(parse("goto page 0").is_err());
+        assert!(parse("goto page 4294967297").is_err());
+
+        // Without a count `page` is a marker
+        let output = parse_ok("load \"a\" as page\ngoto page");
+        assert_eq!(output, vec![load("a", "page"), goto("page")]);
+    }
+
+    #[test]
+    fn parse_goto_center() {
+        let output = parse_ok("goto main center");
+        let expected = vec![Instruction::Goto {
+            dest: "main".into(),
+            center: true,
+        }];
+        assert_eq!(output, expected);
+
+        let output = parse_ok("goto line 40 center\ngoto line 40");
+        let expected = vec![
+            Instruction::Goto {
+                dest: Dest::Line(40),
+                center: true,
+            },
+            goto(Dest::Line(40)),
+        ];
+        assert_eq!(output, expected);
+
+        let output = parse_ok("load \"a\" as center\ngoto center\ngoto center center");
+        let expected = vec![
+            load("a", "center"),
+            goto("center"),
+            Instruction::Goto {
+                dest: "center".into(),
+                center: true,
+            },
+        ];
+        assert_eq!(output, expected);
+    }
+
     #[test]
     fn parse_goto_negatives() {
         let output = parse_ok("goto -1 -2");
@@ -412,4 +2180,82 @@ goto 1     2
         let expected = vec![goto((1, 2)), wait(1), wait(2)];
         assert_eq!(output, expected);
     }
+
+    #[test]
+    fn recover_from_multiple_errors() {
+        let input = "wait 1
+goto \"nope\"
+wait 2
+speed fast
+wait 3";
+        let tokens = lex(input).unwrap();
+        let (instructions, errors) = super::parse_recoverable(tokens);
+
+        let expected = vec![wait(1), wait(2), wait(3)];
+        assert_eq!(instructions.take_instructions(), expected);
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn recover_from_error_on_last_line() {
+        let input = "wait 1
+wait";
+        let tokens = lex(input).unwrap();
+        let (instructions, errors) = super::parse_recoverable(tokens);
+
+        assert_eq!(instructions.take_instructions(), vec![wait(1)]);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn recover_with_comment_prefixes() {
+        let input = "# intro
+wait 1
+goto \"nope\"
+-- outro
+wait 2";
+        let (instructions, errors) = crate::parse_recoverable_with_comment_prefixes(input, &["#", "--"]);
+
+        assert_eq!(instructions.take_instructions(), vec![wait(1), wait(2)]);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn recover_from_lex_errors() {
+        let input = "wait 1
+find \"a\" \"\\q\"
+goto \"nope\"
+wait 99999999999999999999
+wait 2
+type \"unterminated";
+        let (instructions, errors) = crate::parse_recoverable(input);
+
+        assert_eq!(instructions.take_instructions(), vec![wait(1), wait(2)]);
+        let lines = errors.iter().map(|err| err.line()).collect::<Vec<_>>();
+        assert_eq!(lines, [2, 3, 4, 6]);
+        assert!(errors[0].to_string().starts_with("error at 2:11: unknown escape"));
+    }
+
+    #[test]
+    fn parse_single_line() {
+        let output = crate::parse_line("wait 1 # one second", "#").unwrap();
+        assert_eq!(output, Some(wait(1)));
+
+        let output = crate::parse_line("jump nowhere", "#").unwrap();
+        assert_eq!(output, Some(Instruction::JumpToLabel("nowhere".into())));
+    }
+
+    #[test]
+    fn parse_blank_line() {
+        assert_eq!(crate::parse_line("", "#").unwrap(), None);
+        assert_eq!(crate::parse_line("   # nothing here", "#").unwrap(), None);
+    }
+
+    #[test]
+    fn parse_line_with_trailing_content() {
+        let err = crate::parse_line("wait 1 2", "#").unwrap_err();
+        assert!(err.to_string().contains("unexpected token, `end of line`, found `2`"));
+
+        assert!(crate::parse_line("wait 1\nwait 2", "#").is_err());
+    }
 }
@@ -1,38 +1,134 @@
+use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
 
-#[derive(Debug, Default, PartialEq)]
-pub enum Token {
+/// Every keyword and its token, so a keyword is never an identifier
+pub(crate) static KEYWORDS: &[(&str, Token<'static>)] = &[
+    ("align", Token::Align),
+    ("as", Token::As),
+    ("assert_var", Token::AssertVar),
+    ("autoindent", Token::Autoindent),
+    ("backspace", Token::Backspace),
+    ("breakpoint", Token::Breakpoint),
+    ("clear", Token::Clear),
+    ("complete", Token::Complete),
+    ("context_dump", Token::ContextDump),
+    ("copy", Token::Copy),
+    ("delete", Token::Delete),
+    ("echo_raw", Token::EchoRaw),
+    ("edit", Token::Edit),
+    ("false", Token::Bool(false)),
+    ("find", Token::Find),
+    ("flush", Token::Flush),
+    ("goto", Token::Goto),
+    ("include", Token::Include),
+    ("insert", Token::Insert),
+    ("insert_each", Token::InsertEach),
+    ("jump", Token::Jump),
+    ("label", Token::Label),
+    ("linepause", Token::LinePause),
+    ("lines", Token::Lines),
+    ("load", Token::Load),
+    ("nonl", Token::NoNewline),
+    ("numbers", Token::ShowLineNumbers),
+    ("on_error", Token::OnError),
+    ("paste", Token::Paste),
+    ("pause", Token::Pause),
+    ("pop_selection", Token::PopSelection),
+    ("push_selection", Token::PushSelection),
+    ("reindent", Token::Reindent),
+    ("repeat", Token::Repeat),
+    ("replace", Token::Replace),
+    ("search_next", Token::SearchNext),
+    ("search_prev", Token::SearchPrev),
+    ("select", Token::Select),
+    ("set", Token::Set),
+    ("sleep", Token::Wait),
+    ("speed", Token::Speed),
+    ("surround", Token::Surround),
+    ("theme", Token::Theme),
+    ("title", Token::SetTitle),
+    ("true", Token::Bool(true)),
+    ("type", Token::Type),
+    ("type_recording", Token::TypeRecording),
+    ("typenl", Token::TypeNl),
+    ("until", Token::Until),
+    ("wait", Token::Wait),
+    ("wait_file", Token::WaitFile),
+];
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum Token<'src> {
     // Single char tokens
     Newline,
     Bang,
     Equal,
     At,
+    DotDot,
+    PlusMinus,
+    LBrace,
+    RBrace,
 
     // Multi char tokens
+    Align,
     As,
+    Autoindent,
+    Complete,
     Delete,
     Bool(bool),
     Int(i64),
-    Str(String),
-    Ident(String),
+    Duration(Duration),
+    Str(Cow<'src, str>),
+    Ident(&'src str),
+    /// A regular expression between two slashes, as written
+    Regex(&'src str),
     Comment,
     Whitespace,
     NoNewline,
+    Reindent,
 
     // Actions
+    AssertVar,
+    Backspace,
+    Breakpoint,
+    Clear,
+    ContextDump,
+    Copy,
+    EchoRaw,
+    Edit,
     Find,
+    Flush,
     Goto,
+    Include,
     Insert,
+    InsertEach,
+    Jump,
+    Label,
     LinePause,
+    Lines,
     Load,
+    OnError,
+    Paste,
+    Pause,
+    PopSelection,
+    PushSelection,
+    Repeat,
     Replace,
+    SearchNext,
+    SearchPrev,
     Select,
+    Set,
     SetTitle,
     ShowLineNumbers,
     Speed,
+    Surround,
+    Theme,
     Type,
     TypeNl,
+    TypeRecording,
+    Until,
     Wait,
+    WaitFile,
 
     // Eof
     Eof,
@@ -42,35 +138,122 @@ pub enum Token {
     Consumed,
 }
 
-impl Display for Token {
+impl Token<'_> {
+    /// The token is the keyword of an instruction, like `goto`, rather than of an argument
+    pub(crate) fn starts_instruction(&self) -> bool {
+        matches!(
+            self,
+            Token::AssertVar
+                | Token::Backspace
+                | Token::Breakpoint
+                | Token::Clear
+                | Token::ContextDump
+                | Token::Copy
+                | Token::Delete
+                | Token::EchoRaw
+                | Token::Edit
+                | Token::Find
+                | Token::Flush
+                | Token::Goto
+                | Token::Include
+                | Token::Insert
+                | Token::InsertEach
+                | Token::Jump
+                | Token::Label
+                | Token::LinePause
+                | Token::Load
+                | Token::OnError
+                | Token::Paste
+                | Token::Pause
+                | Token::PopSelection
+                | Token::PushSelection
+                | Token::Repeat
+                | Token::Replace
+                | Token::SearchNext
+                | Token::SearchPrev
+                | Token::Select
+                | Token::Set
+                | Token::SetTitle
+                | Token::ShowLineNumbers
+                | Token::Speed
+                | Token::Surround
+                | Token::Theme
+                | Token::Type
+                | Token::TypeNl
+                | Token::TypeRecording
+                | Token::Wait
+                | Token::WaitFile
+        )
+    }
+}
+
+impl Display for Token<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Token::At => write!(f, "@"),
+            Token::LBrace => write!(f, "{{"),
+            Token::RBrace => write!(f, "}}"),
             Token::Equal => write!(f, "="),
             Token::Bang => write!(f, "!"),
+            Token::DotDot => write!(f, ".."),
+            Token::PlusMinus => write!(f, "+-"),
             Token::Newline => write!(f, "<nl>"),
 
+            Token::Align => write!(f, "align"),
             Token::As => write!(f, "as"),
+            Token::Complete => write!(f, "complete"),
             Token::Delete => write!(f, "delete"),
+            Token::EchoRaw => write!(f, "echo_raw"),
+            Token::Edit => write!(f, "edit"),
             Token::Ident(s) => write!(f, "{s}"),
             Token::Int(int) => write!(f, "{int}"),
+            Token::Duration(duration) => write!(f, "{duration:?}"),
             Token::NoNewline => write!(f, "no newline"),
+            Token::Reindent => write!(f, "reindent"),
             Token::Str(s) => write!(f, "\"{s}\""),
+            Token::Regex(re) => write!(f, "/{re}/"),
             Token::Bool(b) => write!(f, "{b}"),
+            Token::Autoindent => write!(f, "autoindent"),
 
+            Token::AssertVar => write!(f, "assert_var"),
+            Token::Backspace => write!(f, "backspace"),
+            Token::Breakpoint => write!(f, "breakpoint"),
+            Token::Clear => write!(f, "clear"),
+            Token::ContextDump => write!(f, "context_dump"),
+            Token::Copy => write!(f, "copy"),
             Token::Find => write!(f, "find"),
+            Token::Flush => write!(f, "flush"),
             Token::Goto => write!(f, "goto"),
+            Token::Include => write!(f, "include"),
             Token::Insert => write!(f, "insert"),
+            Token::InsertEach => write!(f, "insert_each"),
+            Token::Jump => write!(f, "jump"),
+            Token::Label => write!(f, "label"),
             Token::LinePause => write!(f, "line pause"),
+            Token::Lines => write!(f, "lines"),
             Token::Load => write!(f, "load"),
+            Token::OnError => write!(f, "on_error"),
+            Token::Paste => write!(f, "paste"),
+            Token::Pause => write!(f, "pause"),
+            Token::PopSelection => write!(f, "pop_selection"),
+            Token::PushSelection => write!(f, "push_selection"),
+            Token::Repeat => write!(f, "repeat"),
             Token::Replace => write!(f, "change"),
+            Token::SearchNext => write!(f, "search_next"),
+            Token::SearchPrev => write!(f, "search_prev"),
             Token::Select => write!(f, "select"),
+            Token::Set => write!(f, "set"),
             Token::SetTitle => write!(f, "set title"),
             Token::ShowLineNumbers => write!(f, "show line numbers"),
             Token::Speed => write!(f, "speed"),
+            Token::Surround => write!(f, "surround"),
+            Token::Theme => write!(f, "theme"),
             Token::Type => write!(f, "type"),
             Token::TypeNl => write!(f, "typenl"),
+            Token::TypeRecording => write!(f, "type_recording"),
+            Token::Until => write!(f, "until"),
             Token::Wait => write!(f, "wait"),
+            Token::WaitFile => write!(f, "wait_file"),
 
             Token::Eof => write!(f, "EOF"),
 
@@ -100,24 +283,37 @@ impl Span {
 pub struct Tokens<'src> {
     pub(crate) source: &'src str,
     index: usize,
-    tokens: Vec<Token>,
+    // The index of the most recently consumed token
+    consumed: usize,
+    tokens: Vec<Token<'src>>,
     spans: Vec<Span>,
 }
 
 impl<'src> Tokens<'src> {
-    pub(crate) fn new(source: &'src str, tokens: Vec<Token>, spans: Vec<Span>) -> Self {
+    pub(crate) fn new(source: &'src str, tokens: Vec<Token<'src>>, spans: Vec<Span>) -> Self {
         Self {
             source,
             index: 0,
+            consumed: 0,
             tokens,
             spans,
         }
     }
 
-    pub(crate) fn current(&self) -> &Token {
+    pub(crate) fn current(&self) -> &Token<'src> {
         &self.tokens[self.index]
     }
 
+    /// Look ahead `n` tokens without consuming anything, skipping whitespace and comments.
+    /// `peek(0)` is the next token to be taken. Peeking past the end returns `Eof`.
+    pub(crate) fn peek(&self, n: usize) -> &Token<'src> {
+        self.tokens[self.index..]
+            .iter()
+            .filter(|token| !matches!(token, Token::Whitespace | Token::Comment))
+            .nth(n)
+            .unwrap_or(&Token::Eof)
+    }
+
     fn skip_pointelss_tokens(&mut self) {
         while let Token::Whitespace | Token::Comment = self.tokens[self.index] {
             self.index += 1;
@@ -125,6 +321,7 @@ impl<'src> Tokens<'src> {
     }
 
     pub(crate) fn consume(&mut self) {
+        self.consumed = self.index;
         if self.index == self.tokens.len() - 1 {
             return;
         }
@@ -133,7 +330,7 @@ impl<'src> Tokens<'src> {
         self.skip_pointelss_tokens();
     }
 
-    pub(crate) fn consume_if(&mut self, token: Token) -> bool {
+    pub(crate) fn consume_if(&mut self, token: Token<'_>) -> bool {
         self.skip_pointelss_tokens();
 
         if token.eq(self.current()) {
@@ -144,7 +341,7 @@ impl<'src> Tokens<'src> {
         }
     }
 
-    pub(crate) fn take(&mut self) -> Token {
+    pub(crate) fn take(&mut self) -> Token<'src> {
         self.skip_pointelss_tokens();
 
         let token = std::mem::take(&mut self.tokens[self.index]);
@@ -152,16 +349,93 @@ impl<'src> Tokens<'src> {
         token
     }
 
+    pub(crate) fn at_end(&self) -> bool {
+        self.index == self.tokens.len() - 1
+    }
+
+    /// Skip every token that starts on or before the given line.
+    pub(crate) fn skip_line(&mut self, line: u16) {
+        while !self.at_end() && self.spans[self.index].line <= line {
+            self.index += 1;
+        }
+    }
+
+    /// The spans of the most recently consumed token, for errors about that token
     pub(crate) fn spans(&self) -> (Span, Span) {
-        let start = self.spans[self.index];
-        let end = if self.index + 1 == self.tokens.len() { start } else { self.spans[self.index + 1] };
+        let start = self.spans[self.consumed];
+        let end = self.spans.get(self.consumed + 1).copied().unwrap_or(start);
 
         (start, end)
     }
 
+    /// The span of the next token to be taken
+    pub(crate) fn current_span(&self) -> Span {
+        self.spans[self.index]
+    }
+
     #[cfg(test)]
-    pub fn take_tokens(self) -> Vec<Token> {
+    pub fn take_tokens(self) -> Vec<Token<'src>> {
         self.tokens
     }
 
 }
+
+#[cfg(test)]
+mod test {
+    use crate::lexer::lex;
+
+    use super::*;
+
+    #[test]
+    fn peek_ahead() {
+        let tokens = lex("goto line 10").unwrap();
+
+        assert_eq!(tokens.peek(0), &Token::Goto);
+        assert_eq!(tokens.peek(1), &Token::Ident("line"));
+        assert_eq!(tokens.peek(2), &Token::Int(10));
+        assert_eq!(tokens.peek(3), &Token::Eof);
+        assert_eq!(tokens.peek(10), &Token::Eof);
+    }
+
+    #[test]
+    fn peek_skips_whitespace_and_comments() {
+        let mut tokens = lex("  select // comment\nword").unwrap();
+
+        assert_eq!(tokens.peek(0), &Token::Select);
+        assert_eq!(tokens.peek(1), &Token::Ident("word"));
+
+        // Peeking does not consume anything
+        assert_eq!(tokens.take(), Token::Select);
+        assert_eq!(tokens.peek(0), &Token::Ident("word"));
+    }
+
+    #[test]
+    fn spans_of_tokens() {
+        let tokens = lex("goto 3 \"ö\"\n  wait 1").unwrap();
+        let positions = tokens.spans.iter().map(|span| (span.line, span.col));
+        let positions = positions.collect::<Vec<_>>();
+        let expected = [
+            (1, 1),
+            (1, 5),
+            (1, 6),
+            (1, 7),
+            (1, 8),
+            (1, 11),
+            (2, 1),
+            (2, 3),
+            (2, 7),
+            (2, 8),
+            (2, 9),
+        ];
+        assert_eq!(positions, expected);
+    }
+
+    #[test]
+    fn spans_of_taken_token() {
+        let mut tokens = lex("goto  line").unwrap();
+        assert_eq!(tokens.take(), Token::Goto);
+        assert_eq!(tokens.spans().0.col, 1);
+        assert_eq!(tokens.take(), Token::Ident("line"));
+        assert_eq!(tokens.spans().0.col, 7);
+    }
+}
@@ -1,5 +1,9 @@
 use std::env::args;
-use parser::parse;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use parser::{DEFAULT_COMMENT_PREFIX, Instructions, Resource, parse_with_comment_prefixes};
 
 fn help() {
     println!("
@@ -13,22 +17,599 @@ parrot <file path> <comment prefix>
 example: play back code.echo with `#` as the comments
 parrot code.echo #
 
+Without a comment prefix it is picked from the file extension, e.g. `#` for
+demo.py or demo.py.echo, and `//` for anything unknown
+
+example: check code.echo without playing it back, e.g. in CI
+parrot --validate code.echo
+
+Use `-` as the file path to read from stdin
+
+Flags
+-----
+
+-c, --comment-prefix <str>    set the comment prefix, or several separated by commas (takes precedence over the positional form)
+--print-duration              print the estimated playback time as MM:SS and exit
+--dry-files                   print every file the script reads or waits for and exit
+--dump                        print the parsed instructions, one per line, and exit
+--list-loads                  print every file compiling reads and whether it exists, and exit with an error if any are missing
+--validate                    parse and compile the script, checking every `assert_var`, and exit with an error if it fails
+--normalize-ws                replace tabs in the indentation of typed text with spaces
+--smooth                      type fractions of a character per frame instead of one per `speed`
+--trail                       briefly highlight the most recently typed characters
+--profile                     print how long every instruction took, slowest first, once playback ends
+--teleprompter <lines>        only show the newest lines, scrolling older ones off the top
+-v, --verbose                 print the variables to stderr on `context_dump`
+--no-replace-selection        type and insert next to a selection rather than replacing it
+--target-duration <duration>  scale speeds and waits to fit the playback into a duration, e.g. 60s or 2m
+--env-file <path>             set the variables from a file of KEY=VALUE lines before playback
+--safe                        reject instructions that write to the terminal directly, like `echo_raw`
+
 For more information see https://github.com/togglebyte/parrot
 ");
 }
 
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    path: Option<String>,
+    comment_prefix: Option<String>,
+    print_duration: bool,
+    dry_files: bool,
+    dump: bool,
+    list_loads: bool,
+    validate: bool,
+    normalize_whitespace: bool,
+    smooth: bool,
+    trail: bool,
+    profile: bool,
+    teleprompter: Option<usize>,
+    verbose: bool,
+    target_duration: Option<Duration>,
+    keep_selection: bool,
+    env_file: Option<String>,
+    safe: bool,
+}
+
+impl Args {
+    fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut output = Self::default();
+        let mut positional_prefix = None;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--print-duration" => output.print_duration = true,
+                "--dry-files" => output.dry_files = true,
+                "--dump" => output.dump = true,
+                "--list-loads" => output.list_loads = true,
+                "--validate" => output.validate = true,
+                "--normalize-ws" => output.normalize_whitespace = true,
+                "--smooth" => output.smooth = true,
+                "--trail" => output.trail = true,
+                "--profile" => output.profile = true,
+                "--teleprompter" => {
+                    let lines = args.next().unwrap_or_default();
+                    let Some(lines) = lines.parse().ok().filter(|lines| *lines > 0) else {
+                        anyhow::bail!("invalid --teleprompter \"{lines}\", expected a number of lines above 0");
+                    };
+                    output.teleprompter = Some(lines);
+                }
+                "-v" | "--verbose" => output.verbose = true,
+                "--no-replace-selection" => output.keep_selection = true,
+                "--safe" => output.safe = true,
+                "--target-duration" => {
+                    let duration = args.next().unwrap_or_default();
+                    let Some(duration) = parse_duration(&duration) else {
+                        anyhow::bail!("invalid --target-duration \"{duration}\", expected e.g. 60s or 2m");
+                    };
+                    output.target_duration = Some(duration);
+                }
+                "-c" | "--comment-prefix" => {
+                    let prefix = args.next().unwrap_or_default();
+                    if prefix.is_empty() || is_flag(&prefix) {
+                        anyhow::bail!("invalid --comment-prefix \"{prefix}\", expected e.g. # or //");
+                    }
+                    output.comment_prefix = Some(prefix);
+                }
+                "--env-file" => {
+                    let path = args.next().unwrap_or_default();
+                    if path.is_empty() || is_flag(&path) {
+                        anyhow::bail!("invalid --env-file \"{path}\", expected a path");
+                    }
+                    output.env_file = Some(path);
+                }
+                _ if output.path.is_none() => output.path = Some(arg),
+                _ if positional_prefix.is_none() => positional_prefix = Some(arg),
+                _ => {}
+            }
+        }
+
+        // The named flag takes precedence over the positional prefix
+        if output.comment_prefix.is_none() {
+            output.comment_prefix = positional_prefix;
+        }
+
+        Ok(output)
+    }
+}
+
+// The next flag rather than the value of the one before it, e.g. `--validate` but not the comment prefix `--`
+fn is_flag(arg: &str) -> bool {
+    match arg.strip_prefix("--") {
+        Some(name) => name.starts_with(char::is_alphabetic),
+        None => matches!(arg, "-c" | "-v"),
+    }
+}
+
+fn read_source(path: &str) -> std::io::Result<String> {
+    if path == "-" {
+        let mut code = String::new();
+        std::io::stdin().read_to_string(&mut code)?;
+        return Ok(code);
+    }
+
+    std::fs::read_to_string(path)
+}
+
+fn read_env_file(path: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let content = std::fs::read_to_string(path)?;
+    let variables = vm::parse_env(&content).map_err(|err| anyhow::anyhow!("{path}: {err}"))?;
+    Ok(variables)
+}
+
+// A number of seconds, optionally followed by `s`, or a number of minutes followed by `m`
+fn parse_duration(duration: &str) -> Option<Duration> {
+    if let Some(minutes) = duration.strip_suffix('m') {
+        let minutes: u64 = minutes.parse().ok()?;
+        return Some(Duration::from_secs(minutes * 60));
+    }
+
+    let secs = duration.strip_suffix('s').unwrap_or(duration);
+    secs.parse().ok().map(Duration::from_secs)
+}
+
+// The comment prefix of the language the script is named after, e.g. `#` for `demo.py`
+// or `demo.py.echo`, falling back to `//`
+fn detect_comment_prefix(path: &str) -> &'static str {
+    let path = Path::new(path);
+    let extension = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("echo") => Path::new(path.file_stem().unwrap_or_default()).extension(),
+        _ => path.extension(),
+    };
+
+    match extension.and_then(|ext| ext.to_str()) {
+        Some("rs" | "c" | "js") => "//",
+        Some("py" | "sh") => "#",
+        Some("sql" | "hs") => "--",
+        _ => DEFAULT_COMMENT_PREFIX,
+    }
+}
+
+// Several comment prefixes are given separated by commas, e.g. `#,//`
+fn split_comment_prefixes(comment_prefix: &str) -> Vec<&str> {
+    comment_prefix.split(',').collect()
+}
+
+// Print every file compiling reads and whether it exists, failing if any are missing
+fn list_loads(instructions: &Instructions, script: Option<&Path>, output: &mut impl Write) -> anyhow::Result<()> {
+    let paths = instructions
+        .resources(script)
+        .into_iter()
+        .filter_map(|resource| match resource {
+            Resource::Read(path) => Some(path),
+            Resource::Tail(_) | Resource::Wait(_) => None,
+        });
+
+    let mut missing = 0;
+    for path in paths {
+        let status = if path.exists() { "ok" } else { "missing" };
+        missing += usize::from(!path.exists());
+        writeln!(output, "{status:<8}{}", path.display())?;
+    }
+
+    match missing {
+        0 => Ok(()),
+        1 => anyhow::bail!("1 file is missing"),
+        _ => anyhow::bail!("{missing} files are missing"),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = (duration.as_millis() + 500) / 1000;
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
 fn main() -> anyhow::Result<()> {
-    let mut args = args().skip(1);
-    let Some(path) = args.next() else {
+    let args = Args::parse(args().skip(1))?;
+    let Some(path) = args.path else {
         help();
         return Ok(());
     };
 
     // let path = "/media/rustvids/anathema/hackbar/i3.echo";
 
-    let code = std::fs::read_to_string(path)?;
-    let instructions = parse(&code)?;
-    let instructions = vm::compile(instructions)?;
-    ui::run(instructions);
+    let code = read_source(&path)?;
+    let detected = detect_comment_prefix(&path);
+    let comment_prefix = args.comment_prefix.as_deref().unwrap_or(detected);
+    let comment_prefixes = split_comment_prefixes(comment_prefix);
+    let instructions = parse_with_comment_prefixes(&code, &comment_prefixes)?;
+
+    // Nothing is compiled, as compiling reads the loaded files
+    let script = (path != "-").then(|| PathBuf::from(&path));
+    if args.dry_files {
+        for resource in instructions.resources(script.as_deref()) {
+            println!("{resource}");
+        }
+        return Ok(());
+    }
+
+    if args.dump {
+        print!("{instructions}");
+        return Ok(());
+    }
+
+    if args.list_loads {
+        return list_loads(&instructions, script.as_deref(), &mut std::io::stdout());
+    }
+
+    let variables = match args.env_file {
+        Some(env_file) => read_env_file(&env_file)?,
+        None => vec![],
+    };
+
+    let options = vm::Options {
+        normalize_whitespace: args.normalize_whitespace,
+        verbose: args.verbose,
+        replace_selection: !args.keep_selection,
+        validate: args.validate,
+        path: script,
+        comment_prefixes: comment_prefixes.iter().map(|prefix| prefix.to_string()).collect(),
+        variables,
+        safe: args.safe,
+        ..Default::default()
+    };
+    let (mut instructions, warnings) = vm::compile_with_warnings(instructions, &options)?;
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+
+    if args.validate {
+        return Ok(());
+    }
+
+    let mut frame_time = vm::DEFAULT_SPEED;
+    if let Some(target) = args.target_duration {
+        frame_time = vm::fit_duration(&mut instructions, frame_time, target);
+        let duration = vm::estimate(&instructions, frame_time);
+        if duration > target + Duration::from_secs(1) {
+            eprintln!(
+                "warning: playback takes at least {} and doesn't fit in {}",
+                format_duration(duration),
+                format_duration(target)
+            );
+        }
+    }
+
+    if args.print_duration {
+        let duration = vm::estimate(&instructions, frame_time);
+        println!("{}", format_duration(duration));
+        return Ok(());
+    }
+
+    ui::run(
+        instructions,
+        frame_time,
+        args.smooth,
+        args.trail,
+        args.profile,
+        args.teleprompter,
+    );
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use parser::parse;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Args {
+        Args::parse(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn parse_print_duration() {
+        let output = args(&["--print-duration", "code.echo"]);
+        assert_eq!(output.path.as_deref(), Some("code.echo"));
+        assert!(output.print_duration);
+
+        let output = args(&["code.echo", "#"]);
+        assert_eq!(output.path.as_deref(), Some("code.echo"));
+        assert!(!output.print_duration);
+    }
+
+    #[test]
+    fn parse_validate() {
+        assert!(args(&["--validate", "code.echo"]).validate);
+        assert!(!args(&["code.echo"]).validate);
+
+        // The comment prefix still follows the path
+        let output = args(&["--validate", "code.echo", "#"]);
+        assert!(output.validate);
+        assert_eq!(output.path.as_deref(), Some("code.echo"));
+        assert_eq!(output.comment_prefix.as_deref(), Some("#"));
+    }
+
+    #[test]
+    fn validate_reports_errors() {
+        let instructions = parse("goto nowhere").unwrap();
+        let options = vm::Options {
+            validate: true,
+            ..Default::default()
+        };
+        assert!(vm::compile_with_options(instructions, &options).is_ok());
+
+        let instructions = parse("type code").unwrap();
+        assert!(vm::compile_with_options(instructions, &options).is_err());
+        assert!(parse("wait forever").is_err());
+    }
+
+    #[test]
+    fn parse_dry_files() {
+        assert!(args(&["--dry-files", "code.echo"]).dry_files);
+        assert!(!args(&["code.echo"]).dry_files);
+    }
+
+    #[test]
+    fn parse_dump() {
+        assert!(args(&["--dump", "code.echo"]).dump);
+        assert!(!args(&["code.echo"]).dump);
+    }
+
+    #[test]
+    fn dump_instructions() {
+        let code = "
+// A comment is not an instruction
+load \"src/main.rs\" as main
+goto 2 0
+typenl main nonl
+repeat 2 {
+    select word
+    replace \"a\"   b
+}
+wait 500ms
+";
+        let expected = "\
+load \"src/main.rs\" as main
+goto 2 0
+typenl main nonl
+repeat 2 {
+    select word
+    replace \"a\" b
+}
+wait 500ms
+";
+        assert_eq!(parse(code).unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn list_present_and_missing_loads() {
+        let dir = vm::TempDir::new("parrot-list-loads");
+        let present = dir.join("present.rs");
+        std::fs::write(&present, "fn main() {}").unwrap();
+        std::fs::write(dir.join("intro.echo"), "").unwrap();
+        let missing = dir.join("missing.rs");
+
+        let code = format!(
+            "load \"{}\" as a\nrepeat 2 {{\n    load \"{}\" as b\n}}\ninclude \"intro.echo\"",
+            present.display(),
+            missing.display()
+        );
+        let instructions = parse(&code).unwrap();
+        let script = dir.join("demo.echo");
+        let mut output = vec![];
+        let err = list_loads(&instructions, Some(&script), &mut output).unwrap_err();
+        assert_eq!(err.to_string(), "1 file is missing");
+
+        let expected = format!(
+            "ok      {}\nmissing {}\nok      {}\n",
+            present.display(),
+            missing.display(),
+            dir.join("intro.echo").display()
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        let instructions = parse(&format!("load \"{}\" as a", present.display())).unwrap();
+        assert!(list_loads(&instructions, Some(&script), &mut vec![]).is_ok());
+    }
+
+    #[test]
+    fn parse_comment_prefix() {
+        let output = args(&["code.echo", "#"]);
+        assert_eq!(output.comment_prefix.as_deref(), Some("#"));
+
+        let output = args(&["code.echo", "#", "--comment-prefix", "--"]);
+        assert_eq!(output.comment_prefix.as_deref(), Some("--"));
+
+        let output = args(&["-c", ";", "-", "--print-duration"]);
+        assert_eq!(output.path.as_deref(), Some("-"));
+        assert_eq!(output.comment_prefix.as_deref(), Some(";"));
+        assert!(output.print_duration);
+
+        let output = args(&["-"]);
+        assert_eq!(output.path.as_deref(), Some("-"));
+        assert_eq!(output.comment_prefix, None);
+
+        let output = args(&["-c", "--", "schema.sql"]);
+        assert_eq!(output.comment_prefix.as_deref(), Some("--"));
+
+        // The prefix can't be missing
+        let parse = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+        let err = parse(&["code.echo", "-c"]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid --comment-prefix \"\", expected e.g. # or //");
+        assert!(parse(&["-c", "--validate", "code.echo"]).is_err());
+    }
+
+    #[test]
+    fn parse_normalize_ws() {
+        let output = args(&["code.echo", "--normalize-ws"]);
+        assert_eq!(output.path.as_deref(), Some("code.echo"));
+        assert!(output.normalize_whitespace);
+        assert!(!args(&["code.echo"]).normalize_whitespace);
+    }
+
+    #[test]
+    fn parse_smooth() {
+        assert!(args(&["code.echo", "--smooth"]).smooth);
+        assert!(!args(&["code.echo"]).smooth);
+    }
+
+    #[test]
+    fn parse_profile() {
+        assert!(args(&["code.echo", "--profile"]).profile);
+        assert!(!args(&["code.echo"]).profile);
+    }
+
+    #[test]
+    fn parse_teleprompter() {
+        assert_eq!(args(&["code.echo", "--teleprompter", "3"]).teleprompter, Some(3));
+        assert_eq!(args(&["code.echo"]).teleprompter, None);
+
+        let parse = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+        let err = parse(&["code.echo", "--teleprompter", "few"]).unwrap_err();
+        let expected = "invalid --teleprompter \"few\", expected a number of lines above 0";
+        assert_eq!(err.to_string(), expected);
+        assert!(parse(&["code.echo", "--teleprompter", "0"]).is_err());
+        assert!(parse(&["code.echo", "--teleprompter"]).is_err());
+    }
+
+    #[test]
+    fn parse_trail() {
+        assert!(args(&["code.echo", "--trail"]).trail);
+        assert!(!args(&["code.echo"]).trail);
+    }
+
+    #[test]
+    fn parse_verbose() {
+        assert!(args(&["code.echo", "-v"]).verbose);
+        assert!(args(&["code.echo", "--verbose"]).verbose);
+        assert!(!args(&["code.echo"]).verbose);
+    }
+
+    #[test]
+    fn parse_no_replace_selection() {
+        assert!(args(&["code.echo", "--no-replace-selection"]).keep_selection);
+        assert!(!args(&["code.echo"]).keep_selection);
+    }
+
+    #[test]
+    fn parse_safe() {
+        assert!(args(&["code.echo", "--safe"]).safe);
+        assert!(!args(&["code.echo"]).safe);
+    }
+
+    #[test]
+    fn safe_mode_rejects_echo_raw() {
+        let compile = |flags: &[&str]| {
+            let options = vm::Options {
+                safe: args(flags).safe,
+                ..Default::default()
+            };
+            vm::compile_with_options(parse("wait 1\necho_raw \"\\x1b[2J\"").unwrap(), &options)
+        };
+
+        let err = compile(&["code.echo", "--safe"]).unwrap_err();
+        assert_eq!(err.to_string(), "echo_raw is not allowed in safe mode");
+        assert!(compile(&["code.echo"]).is_ok());
+    }
+
+    #[test]
+    fn parse_target_duration() {
+        let output = args(&["code.echo", "--target-duration", "60s"]);
+        assert_eq!(output.target_duration, Some(Duration::from_secs(60)));
+
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("soon"), None);
+
+        let parse = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+        let err = parse(&["code.echo", "--target-duration", "soon"]).unwrap_err();
+        let expected = "invalid --target-duration \"soon\", expected e.g. 60s or 2m";
+        assert_eq!(err.to_string(), expected);
+        assert!(parse(&["code.echo", "--target-duration"]).is_err());
+    }
+
+    #[test]
+    fn variables_from_env_file() {
+        let output = args(&["code.echo", "--env-file", "demo.env"]);
+        assert_eq!(output.env_file.as_deref(), Some("demo.env"));
+
+        // The path can't be missing
+        let parse_args = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+        let err = parse_args(&["code.echo", "--env-file"]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid --env-file \"\", expected a path");
+        assert!(parse_args(&["--env-file", "--safe", "code.echo"]).is_err());
+
+        let dir = vm::TempDir::new("parrot-env-file");
+        let env_file = dir.join("demo.env");
+        std::fs::write(&env_file, "# the demo\nNAME=\"parrot\"\nexport GREETING=hello\n").unwrap();
+
+        let options = vm::Options {
+            variables: read_env_file(env_file.to_str().unwrap()).unwrap(),
+            ..Default::default()
+        };
+        let instructions = parse("type = \"${GREETING} ${NAME}\"\ntype NAME").unwrap();
+        let instructions = vm::compile_with_options(instructions, &options).unwrap();
+        let typed = instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                vm::Instruction::LoadTypeBuffer(content, _) => Some(content.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(typed, ["hello parrot", "parrot"]);
+
+        std::fs::write(&env_file, "NAME=parrot\nGREETING\n").unwrap();
+        let err = read_env_file(env_file.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().ends_with("line 2 of the env file: expected KEY=VALUE"));
+    }
+
+    #[test]
+    fn comment_prefix_from_extension() {
+        assert_eq!(detect_comment_prefix("src/main.rs"), "//");
+        assert_eq!(detect_comment_prefix("demo.js"), "//");
+        assert_eq!(detect_comment_prefix("setup.sh"), "#");
+        assert_eq!(detect_comment_prefix("demo.py.echo"), "#");
+        assert_eq!(detect_comment_prefix("schema.sql"), "--");
+        assert_eq!(detect_comment_prefix("Main.hs"), "--");
+        assert_eq!(detect_comment_prefix("code.echo"), "//");
+        assert_eq!(detect_comment_prefix("notes.txt"), "//");
+        assert_eq!(detect_comment_prefix("-"), "//");
+    }
+
+    #[test]
+    fn comment_prefix_is_used_when_parsing() {
+        let output = args(&["-c", "#,//", "-"]);
+        let prefixes = split_comment_prefixes(output.comment_prefix.as_deref().unwrap());
+        let instructions = parse_with_comment_prefixes("# a comment\n// a comment\nwait 1", &prefixes).unwrap();
+        let instructions = vm::compile(instructions).unwrap();
+        assert_eq!(instructions, vec![vm::Instruction::Wait(Duration::from_secs(1))]);
+    }
+
+    #[test]
+    fn print_duration() {
+        let code = "
+speed 100
+wait 60
+type \"abc\"
+wait 5
+";
+        let instructions = vm::compile(parse(code).unwrap()).unwrap();
+        let duration = vm::estimate(&instructions, vm::DEFAULT_SPEED);
+
+        // 20ms for `speed` and the random delay of 9.5ms on average, which the
+        // speed ends, and 400ms for loading and typing the text
+        assert_eq!(duration, Duration::from_micros(65_429_500));
+        assert_eq!(format_duration(duration), "01:05");
+    }
+}
@@ -1,7 +1,7 @@
 use std::ops::Range;
 
 use anathema::geometry::{Pos, Region};
-use unicode_width::UnicodeWidthChar;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::markers::{Marker, Markers};
 
@@ -34,6 +34,21 @@ impl Document {
         self.markers.get(key)
     }
 
+    pub fn line(&self, row: usize) -> &str {
+        self.text.lines().nth(row).unwrap_or_default()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.text.lines().count()
+    }
+
+    /// The leading whitespace of a line
+    pub fn indentation(&self, row: usize) -> &str {
+        let line = self.line(row);
+        let len = line.len() - line.trim_start_matches([' ', '\t']).len();
+        &line[..len]
+    }
+
     fn byte_offset(&self, pos: Pos) -> usize {
         let line_offset = self.text.split_inclusive('\n').map(str::len).take(pos.y as usize).sum();
         let Some(line) = self.text[line_offset..].split('\n').next() else { return line_offset };
@@ -66,6 +81,31 @@ impl Document {
         }
     }
 
+    // Insert `left` before the selection from `start` up to and including `end`, and `right` after it.
+    // Returns where the selected text starts and ends afterwards
+    pub(crate) fn surround(&mut self, start: Pos, end: Pos, left: &str, right: &str) -> (Pos, Pos) {
+        let end_width = self.line(end.y as usize).width();
+        let (right_at, start_after, end_after) = vm::surround_positions(start, end, end_width, left);
+        self.insert_str(right_at, right);
+        self.insert_str(start, left);
+        (start_after, end_after)
+    }
+
+    // The byte offset of every occurrence of `needle`
+    pub(crate) fn match_offsets(&self, needle: &str) -> Vec<usize> {
+        self.text.match_indices(needle).map(|(offset, _)| offset).collect()
+    }
+
+    // Insert `s` in front of every one of the `matches`, byte offsets from before
+    // `typed` bytes were inserted in front of each of them
+    pub(crate) fn insert_each(&mut self, matches: &[usize], typed: usize, s: &str) {
+        // Inserting from the last match keeps the earlier offsets valid
+        for (i, offset) in matches.iter().enumerate().rev() {
+            let ((row, col), _) = vm::offset_position(&self.text, offset + (i + 1) * typed);
+            self.insert_str(Pos::new(col as i32, row as i32), s);
+        }
+    }
+
     // Get the byte position in the string
     pub(crate) fn get_byte_offset(&self, pos: Pos, mut width: usize) -> Range<usize> {
         let start = self.byte_offset(pos);
@@ -98,6 +138,39 @@ impl Document {
         // _ = self.text.drain(self.get_byte_offset(pos, width));
     }
 
+    // Remove the character before `pos`, joining the line with the one above
+    // at the start of a line. Returns where the cursor ends up.
+    pub(crate) fn backspace(&mut self, pos: Pos) -> Pos {
+        let line_start = self.byte_offset(Pos::new(0, pos.y));
+        let end = self.byte_offset(pos);
+
+        if let Some(c) = self.text[line_start..end].chars().next_back() {
+            self.text.drain(end - c.len_utf8()..end);
+            return Pos::new(pos.x - c.width().unwrap_or(0) as i32, pos.y);
+        }
+
+        // Nothing to join at the start of the buffer
+        if pos.y == 0 || !self.text[..line_start].ends_with('\n') {
+            return Pos::new(0, pos.y);
+        }
+
+        let y = pos.y - 1;
+        let x = self.line(y as usize).width() as i32;
+        self.text.remove(line_start - 1);
+        self.markers.remove_line(pos.y as usize);
+        Pos::new(x, y)
+    }
+
+    pub(crate) fn region_text(&self, region: Region) -> String {
+        let mut lines = vec![];
+        for y in region.from.y..region.to.y {
+            let pos = Pos::new(region.from.x, y);
+            let width = 1 + region.to.x - region.from.x;
+            lines.push(&self.text[self.get_byte_offset(pos, width as usize)]);
+        }
+        lines.join("\n")
+    }
+
     pub(crate) fn find(&self, cursor: Pos, needle: String) -> Option<usize> {
         let (_, y) = (cursor.x, cursor.y);
         let line_offset = self.byte_offset(Pos::new(0, y));
@@ -138,6 +211,107 @@ abcdefg";
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn backspace_joins_lines() {
+        let mut doc = Document::new("ab\n🐇c\n");
+
+        let cursor = doc.backspace(Pos::new(3, 1));
+        assert_eq!(cursor, Pos::new(2, 1));
+        assert_eq!(doc.text(), "ab\n🐇\n");
+
+        let cursor = doc.backspace(cursor);
+        assert_eq!(cursor, Pos::new(0, 1));
+        assert_eq!(doc.text(), "ab\n\n");
+
+        let cursor = doc.backspace(cursor);
+        assert_eq!(cursor, Pos::new(2, 0));
+        assert_eq!(doc.text(), "ab\n");
+
+        // Stops at the start of the buffer
+        let cursor = (0..3).fold(cursor, |cursor, _| doc.backspace(cursor));
+        assert_eq!(cursor, Pos::new(0, 0));
+        assert_eq!(doc.text(), "\n");
+    }
+
+    #[test]
+    fn surround_selection() {
+        let mut doc = Document::new("let a = b + c;");
+        let (start, end) = doc.surround(Pos::new(8, 0), Pos::new(12, 0), "(", ")");
+        assert_eq!(doc.text(), "let a = (b + c);");
+        assert_eq!((start, end), (Pos::new(9, 0), Pos::new(13, 0)));
+
+        // A selection past the end of the line is closed at the end of the line
+        let mut doc = Document::new("say hi");
+        let (start, end) = doc.surround(Pos::new(4, 0), Pos::new(9, 0), "\"", "\"");
+        assert_eq!(doc.text(), "say \"hi\"");
+        assert_eq!((start, end), (Pos::new(5, 0), Pos::new(6, 0)));
+
+        // Positions are display columns, not bytes
+        let mut doc = Document::new("say 🐇🐇 hi");
+        let (start, end) = doc.surround(Pos::new(4, 0), Pos::new(6, 0), "「", "」");
+        assert_eq!(doc.text(), "say 「🐇🐇」 hi");
+        assert_eq!((start, end), (Pos::new(6, 0), Pos::new(8, 0)));
+    }
+
+    #[test]
+    fn surround_lines() {
+        // Selected from an anchor after the cursor column, so the start
+        // and end aren't the corners of the selected region
+        let mut doc = Document::new("    if a {\n        b();\n    }");
+        let (start, end) = doc.surround(Pos::new(7, 0), Pos::new(4, 2), "(", ")");
+        assert_eq!(doc.text(), "    if (a {\n        b();\n    })");
+        assert_eq!((start, end), (Pos::new(8, 0), Pos::new(4, 2)));
+    }
+
+    #[test]
+    fn insert_in_front_of_each() {
+        // Type one character per frame, like the editor
+        let type_each = |text: &str, needle: &str, content: &str| {
+            let mut doc = Document::new(text);
+            let matches = doc.match_offsets(needle);
+            let mut typed = 0;
+            for c in content.chars() {
+                doc.insert_each(&matches, typed, c.encode_utf8(&mut [0; 4]));
+                typed += c.len_utf8();
+            }
+            doc.text().to_string()
+        };
+
+        let text = "fn a() {}\n\nfn b() { fn c() {} }";
+        let expected = "pub fn a() {}\n\npub fn b() { pub fn c() {} }";
+        assert_eq!(type_each(text, "fn", "pub "), expected);
+
+        // The typed text is never matched itself
+        assert_eq!(type_each("a x b x", "x", "xy"), "a xyx b xyx");
+        assert_eq!(type_each("🐇x🐇x", "x", "🦜,"), "🐇🦜,x🐇🦜,x");
+    }
+
+    #[test]
+    fn backspace_moves_markers() {
+        let mut doc = Document::new("// @zero\na\nb\n// @one\nc\n");
+        let row = |doc: &Document, name| doc.lookup_marker(name).map(|m| m.row);
+
+        // Joining `b` with `a` moves the marker below up, but not the one above
+        doc.backspace(Pos::new(0, 1));
+        assert_eq!(doc.text(), "ab\nc\n");
+        assert_eq!(row(&doc, "zero"), Some(0));
+        assert_eq!(row(&doc, "one"), Some(1));
+
+        // The marker moves along with the line it's on
+        doc.backspace(Pos::new(0, 1));
+        assert_eq!(doc.text(), "abc\n");
+        assert_eq!(row(&doc, "one"), Some(0));
+    }
+
+    #[test]
+    fn line_indentation() {
+        let doc = Document::new("fn main() {\n    let a = 1;\n\tb\n");
+        assert_eq!(doc.indentation(0), "");
+        assert_eq!(doc.indentation(1), "    ");
+        assert_eq!(doc.indentation(2), "\t");
+        assert_eq!(doc.indentation(10), "");
+    }
+
     #[test]
     fn insert_offsets_marker() {
         static NEWLINES: usize = 4;
@@ -1,4 +1,9 @@
+use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Stdout};
+use std::ops::Range;
+use std::rc::Rc;
 use std::time::Duration;
 
 use anathema::component::*;
@@ -6,13 +11,23 @@ use anathema::default_widgets::{Canvas, CanvasBuffer};
 use anathema::geometry::{LocalPos, Pos, Region, Size};
 use anathema::widgets::query::Elements;
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
-use vm::Instruction;
+use vm::{
+    Autopair, Instruction, Keystroke, Loops, OnError, Registers, Selections, Tail, TypeOptions, WaitFile, end_of,
+};
 
 use crate::Random;
 use crate::document::Document;
 use crate::markers::generate;
+use crate::pace::Pace;
+use crate::profile::Profile;
+use crate::raw::RawOutput;
 use crate::syntax::{Highlighter, InactiveScratch};
+use crate::teleprompter::Teleprompter;
 use crate::textbuffer::TextBuffer;
+use crate::trail::{Trail, lighten};
+
+// The seed of the `speed` jitter, so every playback of a script types at the same pace
+const JITTER_SEED: u64 = 0x5eed;
 
 enum RenderAction {
     Render,
@@ -40,12 +55,37 @@ pub struct DocState {
 #[derive(Debug)]
 struct VisualRange {
     region: Region,
+    // The first and last selected positions.
+    // A selection between an anchor and the cursor doesn't have to start
+    // and end in the corners of the region
+    start: Pos,
+    end: Pos,
 }
 
 impl VisualRange {
     fn new(pos: Pos, size: Size) -> Self {
+        Self::from_region(Region::from((pos, size)))
+    }
+
+    fn from_region(region: Region) -> Self {
+        Self {
+            region,
+            start: region.from,
+            end: region.to - Pos::new(1, 1),
+        }
+    }
+
+    // The selection from the anchor to the cursor, either way around
+    fn between(anchor: Pos, cursor: Pos) -> Self {
+        let (pos, size) = vm::select_between(anchor, cursor);
+        let (start, end) = match (anchor.y, anchor.x) <= (cursor.y, cursor.x) {
+            true => (anchor, cursor),
+            false => (cursor, anchor),
+        };
         Self {
             region: Region::from((pos, size)),
+            start,
+            end,
         }
     }
 }
@@ -72,38 +112,179 @@ pub struct Editor {
     offset: Pos,
     selected_range: Option<VisualRange>,
     frame_time: Duration,
+    // Every frame is randomly up to this much longer or shorter than the `frame_time`
+    // Unset until the first `speed`, when frames get a random delay instead
+    jitter: Option<Duration>,
+    jitter_rand: Random,
+    // Picks the durations of `wait one_of`, seeded like the jitter
+    wait_rand: Random,
     current_time: Duration,
     instructions: VecDeque<Instruction>,
+    // Every compiled instruction, for jumping back to instructions already played
+    program: Vec<Instruction>,
     type_buffer: TextBuffer,
     highlighter: Highlighter,
     rand: Random,
     buffer: CanvasBuffer,
     lines: InactiveScratch,
     line_pause: Duration,
+    // Where the cursor ends up once a reversed type buffer is exhausted
+    reverse_end: Option<Pos>,
+    // Where the cursor was when the most recent type buffer was typed, for `goto last_type`
+    last_type: Option<Pos>,
+    // The type buffer holds the text of a `type`, rather than e.g. a tailed file
+    typing: bool,
+    on_error: OnError,
+    retries: usize,
+    // The instruction at the front of the queue is a failed one, tried again
+    retrying: bool,
+    // Playback is paused by a breakpoint
+    paused: bool,
+    // Playback is paused by `pause` until any key is pressed
+    key_pause: bool,
+    // Pause again after the next instruction (stepping through a breakpoint)
+    step: bool,
+    // A file being tailed, typing every new line
+    tail: Option<Tail<File>>,
+    // The number of rows visible in the canvas
+    viewport_height: u16,
+    // A file to wait for before moving on to the next instruction
+    wait_file: Option<WaitFile<fn(&std::path::Path) -> bool>>,
+    registers: Registers,
+    // Where `echo_raw` writes, bypassing the canvas
+    raw_output: RawOutput<Stdout>,
+    // Pause after punctuation while typing the current buffer
+    natural: bool,
+    // Pause at newlines of the current buffer instead of the `line_pause`
+    type_line_pause: Option<Duration>,
+    // Close brackets and quotes while typing the current buffer
+    autopair: Option<Autopair>,
+    // The byte offsets of the occurrences an `insert_each` types in front of,
+    // and how many bytes it typed so far
+    insert_each: Option<(Vec<usize>, usize)>,
+    // Where `select to` selects from
+    anchor: Option<Pos>,
+    // Selections saved by `push_selection`
+    selections: Selections,
+    loops: Loops,
+    // Type a fraction of a character per tick rather than one character every `frame_time`
+    pace: Option<Pace>,
+    // Recently typed characters, drawn brighter until they fade out
+    trail: Option<Trail>,
+    // How long each instruction took, shared with `run` to print once playback ends
+    profile: Option<Rc<RefCell<Profile>>>,
+    // Only show this many of the newest lines, scrolling older ones off the top
+    teleprompter: Option<Teleprompter>,
 }
 
 impl Editor {
-    pub fn new(instructions: Vec<Instruction>, frame_time: Duration) -> Self {
+    pub fn new(
+        instructions: Vec<Instruction>,
+        frame_time: Duration,
+        smooth: bool,
+        trail: bool,
+        profile: Option<Rc<RefCell<Profile>>>,
+        teleprompter: Option<usize>,
+    ) -> Self {
         Self {
             doc: Document::new(String::new()),
             cursor: Pos::ZERO,
             offset: Pos::ZERO,
             selected_range: None,
             frame_time,
+            jitter: None,
+            jitter_rand: Random::with_seed(JITTER_SEED),
+            wait_rand: Random::for_waits(),
             current_time: Duration::ZERO,
-            instructions: instructions.into(),
+            instructions: instructions.iter().cloned().collect(),
+            program: instructions,
             type_buffer: TextBuffer::new(),
             highlighter: Highlighter::new(),
             rand: Random::new(),
             buffer: CanvasBuffer::default(),
             lines: InactiveScratch::new(),
             line_pause: Duration::ZERO,
+            reverse_end: None,
+            last_type: None,
+            typing: false,
+            on_error: OnError::Halt,
+            retries: 0,
+            retrying: false,
+            paused: false,
+            key_pause: false,
+            step: false,
+            tail: None,
+            viewport_height: 0,
+            wait_file: None,
+            registers: Registers::new(),
+            raw_output: RawOutput::new(std::io::stdout()),
+            natural: false,
+            type_line_pause: None,
+            autopair: None,
+            insert_each: None,
+            anchor: None,
+            selections: Selections::new(),
+            loops: Loops::new(),
+            pace: smooth.then(Pace::new),
+            trail: trail.then(Trail::new),
+            profile,
+            teleprompter: teleprompter.map(Teleprompter::new),
         }
     }
 
-    fn error(&mut self, state: &mut DocState, msg: impl Into<String>) {
-        self.instructions.clear();
-        state.error.set(msg.into());
+    // Show the state of the editor while paused on a breakpoint
+    fn inspect(&self, state: &mut DocState) {
+        let line = self.doc.line(self.cursor.y as usize);
+        let msg = format!(
+            "breakpoint [c: continue, n: step] | cursor: {}:{} | line: {line:?} | remaining: {}",
+            self.cursor.y + 1,
+            self.cursor.x + 1,
+            self.instructions.len(),
+        );
+        state.debug.set(msg);
+    }
+
+    // The time until the next frame, give or take the jitter of the `speed`
+    fn frame_delay(&mut self) -> Duration {
+        let Some(jitter) = self.jitter else {
+            let max = vm::RANDOM_DELAY.as_millis() as u64;
+            return self.frame_time + Duration::from_millis(self.rand.next(max));
+        };
+
+        // No longer than the frame, so the delay is spread evenly around the frame time
+        let jitter = jitter.min(self.frame_time).as_millis() as u64;
+        let offset = Duration::from_millis(self.jitter_rand.next(2 * jitter + 1));
+        self.frame_time + offset - Duration::from_millis(jitter)
+    }
+
+    // Move the saved selections along with `content`, inserted at the cursor
+    fn move_selections(&mut self, content: &str) {
+        self.selections.insert(self.cursor, content);
+    }
+
+    // Select the first occurrence of the needle on one of the rows, if there is one
+    fn select_in_rows(&mut self, needle: &str, rows: Range<usize>) -> bool {
+        let Some((row, col)) = vm::find_in_rows(self.doc.text(), needle, rows) else { return false };
+        let pos = Pos::new(col as i32, row as i32);
+        let region = Region::from((pos, Size::new(needle.width() as u16, 1)));
+        self.cursor = region.to - Pos::new(1, 1);
+        self.selected_range = Some(VisualRange::from_region(region));
+        true
+    }
+
+    fn error(&mut self, state: &mut DocState, msg: impl Into<String>, instruction: Instruction) {
+        match self.on_error {
+            OnError::Skip => state.debug.set(msg.into()),
+            OnError::Retry if self.retries < vm::MAX_RETRIES && instruction.is_retryable() => {
+                self.retries += 1;
+                self.retrying = true;
+                self.instructions.push_front(instruction);
+            }
+            OnError::Halt | OnError::Retry => {
+                self.instructions.clear();
+                state.error.set(msg.into());
+            }
+        }
     }
 
     fn apply(&mut self, state: &mut DocState) -> RenderAction {
@@ -112,33 +293,142 @@ impl Editor {
         if let Some(s) = self.type_buffer.next() {
             // type next char
             state.debug.set(format!("{s}"));
+            match self.autopair.as_mut().map(|autopair| autopair.keystroke(s)) {
+                Some(Keystroke::TypeThrough) => {
+                    self.cursor.x += s.width() as i32;
+                    return RenderAction::Render;
+                }
+                Some(Keystroke::Pair(closer)) => self.doc.insert_str(self.cursor, closer.to_string()),
+                Some(Keystroke::Type) | None => {}
+            }
             self.doc.insert_str(self.cursor, s);
+            self.move_selections(s);
+
+            if let Some(trail) = self.trail.as_mut() {
+                trail.push(self.cursor);
+            }
 
-            if s == "\n" {
+            // When typing in reverse the cursor stays put
+            // and every character is inserted in front of the previous one
+            let line_pause = self.type_line_pause.unwrap_or(self.line_pause);
+            if self.reverse_end.is_some() {
+                if s == "\n" && line_pause > Duration::ZERO {
+                    self.current_time = line_pause;
+                }
+            } else if s == "\n" {
                 self.cursor.x = 0;
                 self.cursor.y += 1;
 
-                if self.line_pause > Duration::ZERO {
-                    self.current_time = self.line_pause;
+                if line_pause > Duration::ZERO {
+                    self.current_time = line_pause;
                 }
             } else {
                 self.cursor.x += s.width() as i32;
             }
 
+            if self.natural {
+                self.current_time += vm::natural_pause(s, self.frame_time);
+            }
+
             return RenderAction::Render;
         }
 
+        if let Some(end) = self.reverse_end.take() {
+            self.cursor = end;
+        }
+
+        if std::mem::take(&mut self.typing) {
+            self.last_type = Some(self.cursor);
+        }
+
+        // Every closer is typed over by the time the buffer is exhausted
+        self.autopair = None;
+
+        if let Some(tail) = self.tail.as_mut() {
+            match tail.poll() {
+                // Nothing is rendered until the new lines are typed
+                Some(lines) => {
+                    self.type_buffer.push(lines);
+                    return RenderAction::Skip;
+                }
+                None => self.tail = None,
+            }
+        }
+
+        if let Some(wait) = self.wait_file.as_ref() {
+            match wait.poll() {
+                Ok(true) => self.wait_file = None,
+                Ok(false) => {
+                    self.current_time = vm::WAIT_FILE_INTERVAL;
+                    return RenderAction::Skip;
+                }
+                Err(err) => {
+                    let instruction = wait.instruction();
+                    self.wait_file = None;
+                    self.error(state, err.to_string(), instruction);
+                    return RenderAction::Render;
+                }
+            }
+        }
+
         let instruction = self.instructions.pop_front();
+        // Anything but a retry comes after an instruction that succeeded
+        if !std::mem::take(&mut self.retrying) {
+            self.retries = 0;
+        }
+        if let Some(profile) = self.profile.as_ref() {
+            match instruction.as_ref() {
+                Some(instruction) => profile.borrow_mut().start(instruction),
+                None => profile.borrow_mut().stop(),
+            }
+        }
+
         match instruction {
             None => return RenderAction::Skip,
             Some(instruction) => match instruction {
-                Instruction::LoadTypeBuffer(content) => {
+                Instruction::LoadTypeBuffer(
+                    content,
+                    TypeOptions {
+                        reverse,
+                        align,
+                        reindent,
+                        by_word,
+                        natural,
+                        line_pause,
+                        autopair,
+                    },
+                ) => {
+                    self.natural = natural;
+                    self.type_line_pause = line_pause;
+
                     // Make markers and all that what what
-                    let (content, markers) = generate(content);
+                    let (mut content, markers) = generate(content);
                     if let Some(markers) = markers {
                         self.doc.add_markers(self.cursor.y, markers);
                     }
-                    self.type_buffer.push(content);
+
+                    if reindent {
+                        content = vm::indent(&content, self.doc.indentation(self.cursor.y as usize));
+                    }
+
+                    if let Some(width) = align {
+                        content = vm::align_tabs(&content, self.cursor.x as usize, width);
+                    }
+
+                    // Pairs are closed a character at a time, after a cursor that moves forward
+                    if autopair && !reverse && !by_word {
+                        self.autopair = Some(Autopair::new(&content));
+                    }
+
+                    self.typing = true;
+                    if reverse {
+                        self.reverse_end = Some(end_of(self.cursor, &content));
+                        self.type_buffer.push_reversed(content);
+                    } else if by_word {
+                        self.type_buffer.push_words(content);
+                    } else {
+                        self.type_buffer.push(content);
+                    }
                 }
                 Instruction::Jump(pos) => {
                     self.cursor += pos;
@@ -147,12 +437,60 @@ impl Editor {
                 }
                 Instruction::JumpToMarker(name) => {
                     let Some(row) = self.doc.lookup_marker(&name).map(|m| m.row) else {
-                        self.error(state, format!("marker \"{name}\" does not exist"));
+                        let msg = format!("marker \"{name}\" does not exist");
+                        self.error(state, msg, Instruction::JumpToMarker(name));
                         return RenderAction::Render;
                     };
                     self.cursor.y = row as i32;
                     self.cursor.x = 0;
                 }
+                Instruction::JumpToRow(row) => {
+                    self.cursor.y = row as i32;
+                    self.cursor.x = 0;
+                }
+                Instruction::JumpToCapture { pattern, group } => {
+                    let Some((row, col)) = vm::find_capture(self.doc.text(), &pattern, group) else {
+                        let msg = format!("no match for /{pattern}/");
+                        self.error(state, msg, Instruction::JumpToCapture { pattern, group });
+                        return RenderAction::Render;
+                    };
+                    self.cursor = Pos::new(col as i32, row as i32);
+                }
+                Instruction::JumpToLastType => {
+                    // The vm rejects `goto last_type` before any `type`
+                    if let Some(pos) = self.last_type {
+                        self.cursor = pos;
+                    }
+                }
+                Instruction::JumpToOffset(offset) => {
+                    let ((row, col), snapped) = vm::offset_position(self.doc.text(), offset);
+                    if snapped {
+                        let msg = format!("offset {offset} snapped to a character boundary");
+                        state.debug.set(msg);
+                    }
+                    self.cursor = Pos::new(col as i32, row as i32);
+                }
+                Instruction::Until { condition, end } => match self.loops.done(end, condition.holds(self.doc.text())) {
+                    Ok(true) => self.instructions = self.program[end..].iter().cloned().collect(),
+                    Ok(false) => {}
+                    Err(err) => {
+                        // Leave the loop, unless it's retried from the start
+                        self.instructions = self.program[end..].iter().cloned().collect();
+                        self.error(state, err.to_string(), Instruction::Until { condition, end });
+                    }
+                },
+                Instruction::JumpToInstruction(index) => {
+                    self.instructions = self.program[index..].iter().cloned().collect();
+                }
+                Instruction::CenterCursor => {
+                    self.offset.y = vm::center_offset(self.cursor.y as usize, self.viewport_height);
+                }
+                Instruction::JumpPages(pages) => {
+                    let page_height = self.viewport_height as usize;
+                    let row = vm::page_jump(self.cursor.y as usize, pages, page_height, self.doc.line_count());
+                    self.cursor.y = row as i32;
+                    self.cursor.x = 0;
+                }
                 Instruction::Select(size) => {
                     if size == Size::ZERO {
                         return RenderAction::Render;
@@ -162,13 +500,138 @@ impl Editor {
                     self.cursor = visual_range.region.to - Pos::new(1, 1);
                     self.selected_range = Some(visual_range);
                 }
+                Instruction::Anchor => self.anchor = Some(self.cursor),
+                Instruction::SelectToAnchor => {
+                    let Some(anchor) = self.anchor else {
+                        self.error(state, "select to requires an anchor", Instruction::SelectToAnchor);
+                        return RenderAction::Render;
+                    };
+
+                    let visual_range = VisualRange::between(anchor, self.cursor);
+                    self.cursor = visual_range.region.to - Pos::new(1, 1);
+                    self.selected_range = Some(visual_range);
+                }
+                Instruction::SelectWords(count) => {
+                    let line = self.doc.line(self.cursor.y as usize);
+                    let Some((start, end)) = vm::word_extent(line, self.cursor.x as usize, count) else {
+                        return RenderAction::Render;
+                    };
+
+                    let pos = Pos::new(start as i32, self.cursor.y);
+                    let visual_range = VisualRange::new(pos, Size::new((end - start) as u16, 1));
+                    self.cursor = visual_range.region.to - Pos::new(1, 1);
+                    self.selected_range = Some(visual_range);
+                }
+                Instruction::SelectLines { start, end } => {
+                    if end >= self.doc.line_count() {
+                        let msg = format!("line {} does not exist", end + 1);
+                        self.error(state, msg, Instruction::SelectLines { start, end });
+                        return RenderAction::Render;
+                    }
+                    self.select_lines(start, end);
+                }
+                Instruction::SelectRegion { start, end } => {
+                    let first = self.doc.lookup_marker(&start).map(|m| m.row);
+                    let last = self.doc.lookup_marker(&end).map(|m| m.row);
+                    let msg = match (first, last) {
+                        (Some(first), Some(last)) if first < last => {
+                            self.select_lines(first, last - 1);
+                            return RenderAction::Render;
+                        }
+                        (Some(_), Some(_)) => format!("marker \"{end}\" is not after marker \"{start}\""),
+                        (None, _) => format!("marker \"{start}\" does not exist"),
+                        (_, None) => format!("marker \"{end}\" does not exist"),
+                    };
+                    self.error(state, msg, Instruction::SelectRegion { start, end });
+                }
+                Instruction::Tail(path, timeout) => {
+                    let file = File::open(&path).and_then(|mut file| file.seek(SeekFrom::End(0)).map(|_| file));
+                    match file {
+                        Ok(file) => self.tail = Some(Tail::new(file, timeout)),
+                        Err(err) => {
+                            let msg = format!("failed to tail \"{}\": {err}", path.display());
+                            self.error(state, msg, Instruction::Tail(path, timeout));
+                        }
+                    }
+                }
                 Instruction::Insert(content) => {
                     let (content, markers) = generate(content);
                     self.cursor.x = 0;
                     self.doc.insert_str(self.cursor, &content);
+                    self.move_selections(&content);
+                    if let Some(markers) = markers {
+                        self.doc.add_markers(self.cursor.y, markers);
+                    }
+                }
+                Instruction::InsertEach { needle, content } => {
+                    // The occurrences are only found once, so the typed text is never matched itself
+                    let (matches, typed) = match self.insert_each.take() {
+                        Some(progress) => progress,
+                        None => (self.doc.match_offsets(&needle), 0),
+                    };
+                    if matches.is_empty() {
+                        let msg = format!("no occurrence of \"{needle}\"");
+                        self.error(state, msg, Instruction::InsertEach { needle, content });
+                        return RenderAction::Render;
+                    }
+
+                    // One character per frame in front of every occurrence
+                    let mut chars = content.chars();
+                    let Some(c) = chars.next() else { return RenderAction::Skip };
+                    let c = c.to_string();
+                    self.doc.insert_each(&matches, typed, &c);
+
+                    let rest = chars.as_str();
+                    if !rest.is_empty() {
+                        self.insert_each = Some((matches, typed + c.len()));
+                        let content = rest.to_string();
+                        self.instructions.push_front(Instruction::InsertEach { needle, content });
+                    }
+                }
+                Instruction::Paste(content) => {
+                    let (content, markers) = generate(content);
+                    self.doc.insert_str(self.cursor, &content);
+                    self.move_selections(&content);
                     if let Some(markers) = markers {
                         self.doc.add_markers(self.cursor.y, markers);
                     }
+                    self.cursor = end_of(self.cursor, &content);
+                }
+                Instruction::Keystroke(key, delay) => {
+                    let key = key.to_string();
+                    self.doc.insert_str(self.cursor, &key);
+                    self.move_selections(&key);
+                    if let Some(trail) = self.trail.as_mut() {
+                        trail.push(self.cursor);
+                    }
+                    self.cursor = end_of(self.cursor, &key);
+                    self.current_time = delay;
+                }
+                Instruction::Backspace => {
+                    let cursor = self.doc.backspace(self.cursor);
+                    if cursor.y < self.cursor.y {
+                        self.selections.move_lines(Pos::new(0, self.cursor.y), -1);
+                    }
+                    self.cursor = cursor;
+                }
+                Instruction::PushSelection => match self.selected_range.as_ref() {
+                    Some(range) => self.selections.push(range.region),
+                    None => self.error(state, "push_selection requires a selection", Instruction::PushSelection),
+                },
+                Instruction::PopSelection => match self.selections.pop() {
+                    Ok(region) => {
+                        self.cursor = region.to - Pos::new(1, 1);
+                        self.selected_range = Some(VisualRange::from_region(region));
+                    }
+                    Err(err) => self.error(state, err.to_string(), Instruction::PopSelection),
+                },
+                Instruction::Clear => {
+                    self.doc = Document::new(String::new());
+                    self.cursor = Pos::ZERO;
+                    self.offset = Pos::ZERO;
+                    self.selected_range = None;
+                    self.anchor = None;
+                    self.selections = Selections::new();
                 }
                 Instruction::Delete => match self.selected_range.take() {
                     Some(range) => {
@@ -177,21 +640,114 @@ impl Editor {
                     }
                     None => self.doc.delete(Region::from((self.cursor, Size::new(1, 1)))),
                 },
+                Instruction::DeleteSelection => {
+                    if let Some(range) = self.selected_range.take() {
+                        self.cursor = range.region.from;
+                        self.doc.delete(range.region);
+                    }
+                }
+                // Every instruction is followed by a render,
+                // so there is nothing buffered to flush
+                Instruction::Flush => {}
+                Instruction::Copy(register) => {
+                    let content = match self.selected_range.take() {
+                        Some(range) => self.doc.region_text(range.region),
+                        None => String::new(),
+                    };
+                    self.registers.copy(register, content);
+                }
+                Instruction::Surround { left, right } => {
+                    let Some(range) = self.selected_range.take() else {
+                        let msg = "surround requires a selection";
+                        self.error(state, msg, Instruction::Surround { left, right });
+                        return RenderAction::Render;
+                    };
+
+                    let (start, end) = self.doc.surround(range.start, range.end, &left, &right);
+                    self.selected_range = Some(VisualRange::between(start, end));
+                    self.cursor = end;
+                }
+                Instruction::PasteRegister(register) => {
+                    let content = match self.registers.paste(&register) {
+                        Ok(content) => content.to_string(),
+                        Err(err) => {
+                            self.error(state, err.to_string(), Instruction::PasteRegister(register));
+                            return RenderAction::Render;
+                        }
+                    };
+                    self.doc.insert_str(self.cursor, &content);
+                    self.move_selections(&content);
+                    self.cursor = end_of(self.cursor, &content);
+                }
+                Instruction::Breakpoint => {
+                    self.paused = true;
+                    self.inspect(state);
+                }
+                Instruction::Pause => self.key_pause = true,
                 Instruction::Wait(dur) => self.current_time = dur,
-                Instruction::Speed(dur) => self.frame_time = dur,
+                Instruction::WaitOneOf(choices) => self.current_time = self.wait_rand.wait_one_of(&choices),
+                Instruction::WaitFile { path, timeout } => self.wait_file = Some(WaitFile::on_disk(path, timeout)),
+                Instruction::Speed { base, jitter } => {
+                    self.frame_time = base;
+                    self.jitter = Some(jitter);
+                }
                 Instruction::FindInCurrentLine(text) => {
                     let Some(x) = self.doc.find(self.cursor, text) else { return RenderAction::Render };
                     self.cursor.x = x as i32;
                 }
+                Instruction::SelectInDocument(needle) => {
+                    if !self.select_in_rows(&needle, 0..self.doc.line_count()) {
+                        let msg = format!("\"{needle}\" is not in the document");
+                        self.error(state, msg, Instruction::SelectInDocument(needle));
+                        return RenderAction::Render;
+                    }
+                }
+                Instruction::SelectInViewport(needle) => {
+                    let top = -self.offset.y.min(0) as usize;
+                    let rows = top..top + self.viewport_height as usize;
+                    if !self.select_in_rows(&needle, rows) {
+                        let msg = format!("\"{needle}\" is not on a visible line");
+                        self.error(state, msg, Instruction::SelectInViewport(needle));
+                        return RenderAction::Render;
+                    }
+                }
+                Instruction::Search { needle, count } => {
+                    let cursor = (self.cursor.y as usize, self.cursor.x as usize);
+                    let Some((row, col)) = vm::search_step(self.doc.text(), cursor, &needle, count) else {
+                        return RenderAction::Render;
+                    };
+                    self.cursor = Pos::new(col as i32, row as i32);
+                }
                 Instruction::LinePause(duration) => self.line_pause = duration,
                 Instruction::SetTitle(title) => state.title.set(title),
+                Instruction::EchoRaw(text) => {
+                    if let Err(err) = self.raw_output.echo(&text) {
+                        self.error(state, format!("echo_raw failed: {err}"), Instruction::EchoRaw(text));
+                    }
+                }
+                Instruction::LoadTheme(path, theme) => {
+                    if let Err(err) = self.highlighter.load_theme(&theme) {
+                        let msg = format!("invalid theme \"{}\": {err}", path.display());
+                        self.error(state, msg, Instruction::LoadTheme(path, theme));
+                    }
+                }
                 Instruction::ShowLineNumbers(show) => state.show_line_numbers.set(show),
+                Instruction::OnError(mode) => self.on_error = mode,
             },
         }
 
         RenderAction::Render
     }
 
+    // Select whole rows, including the end
+    fn select_lines(&mut self, start: usize, end: usize) {
+        let width = (start..=end).map(|row| self.doc.line(row).width()).max().unwrap_or(0);
+        let size = Size::new(width as u16, (end - start + 1) as u16);
+        let visual_range = VisualRange::new(Pos::new(0, start as i32), size);
+        self.cursor = visual_range.region.to - Pos::new(1, 1);
+        self.selected_range = Some(visual_range);
+    }
+
     fn update_cursor(&mut self, size: Size, state: &mut DocState) {
         static PADDING: i32 = 5;
 
@@ -205,6 +761,10 @@ impl Editor {
             self.offset.y -= self.cursor.y + self.offset.y;
         }
 
+        if let Some(teleprompter) = self.teleprompter {
+            self.offset.y = teleprompter.offset(self.cursor.y as usize);
+        }
+
         let x = self.cursor.x + self.offset.x;
         if x > width {
             self.offset.x = width - self.cursor.x;
@@ -226,6 +786,10 @@ impl Editor {
             canvas.clear();
 
             let mut y = self.offset.y;
+            let rows = match self.teleprompter {
+                Some(teleprompter) => teleprompter.rows(self.cursor.y as usize).len(),
+                None => usize::MAX,
+            };
 
             // re-highlight the content
             let scratch = unsafe { self.lines.activate(self.doc.text()) };
@@ -234,7 +798,7 @@ impl Editor {
 
                 let skip = (y < 0).then_some(y.abs() as usize).unwrap_or(0);
                 y = 0;
-                for spans in lines.iter().skip(skip) {
+                for spans in lines.iter().skip(skip).take(rows) {
                     let mut x = self.offset.x;
                     for span in spans {
                         for c in span.src.chars() {
@@ -247,6 +811,15 @@ impl Editor {
                                 if self.selected_range.contains(pos.into()) {
                                     style.bg = Some(Color::Red);
                                 }
+
+                                let doc_pos = Pos::new(x - self.offset.x, y + skip as i32);
+                                if let Some(fade) = self.trail.as_ref().and_then(|trail| trail.fade(doc_pos)) {
+                                    style.set_bold(true);
+                                    if let Some(Color::Rgb(r, g, b)) = style.fg {
+                                        let lit = |channel| lighten(channel, fade);
+                                        style.fg = Some(Color::Rgb(lit(r), lit(g), lit(b)));
+                                    }
+                                }
                                 canvas.put(c, style, pos);
                             }
                             x += c.width().unwrap_or(0) as i32;
@@ -265,6 +838,24 @@ impl Component for Editor {
     type State = DocState;
 
     fn on_key(&mut self, key: KeyEvent, _: &mut Self::State, _: Children<'_, '_>, _: Context<'_, '_, Self::State>) {
+        if self.paused {
+            match key.code {
+                KeyCode::Char('c') => self.paused = false,
+                KeyCode::Char('n') => {
+                    self.paused = false;
+                    self.step = true;
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        // Any key resumes playback after a `pause`
+        if self.key_pause {
+            self.key_pause = false;
+            return;
+        }
+
         match key.code {
             KeyCode::Char('h') => self.instructions.push_back(Instruction::Jump(Pos::new(-1, 0))),
             KeyCode::Char('j') => self.instructions.push_back(Instruction::Jump(Pos::new(0, 1))),
@@ -287,17 +878,61 @@ impl Component for Editor {
         };
 
         state.height.set(size.height);
+        self.viewport_height = size.height;
+
+        if let Some(profile) = self.profile.as_ref() {
+            profile.borrow_mut().tick(dt);
+        }
+
+        if self.paused || self.key_pause {
+            return;
+        }
 
         self.current_time = self.current_time.saturating_sub(dt);
 
+        // The trail keeps fading while nothing is typed
+        let faded = self.trail.as_mut().is_some_and(|trail| trail.tick(dt));
+
         if self.current_time > Duration::ZERO {
+            if let Some(pace) = self.pace.as_mut() {
+                pace.reset();
+            }
+            if faded {
+                self.draw(children.elements());
+            }
             return;
         }
 
-        self.current_time = self.frame_time + Duration::from_millis(self.rand.next(20));
-        if let RenderAction::Render = self.apply(state) {
+        let render = match self.pace.as_mut() {
+            Some(pace) => {
+                // Apply as many instructions as are due this tick, stopping at
+                // anything that pauses playback
+                let mut render = false;
+                for _ in 0..pace.chars(dt, self.frame_time) {
+                    render |= matches!(self.apply(state), RenderAction::Render);
+                    if self.current_time > Duration::ZERO || self.paused || self.key_pause || self.step {
+                        break;
+                    }
+                }
+                render
+            }
+            None => {
+                self.current_time = self.frame_delay();
+                matches!(self.apply(state), RenderAction::Render)
+            }
+        };
+
+        if render {
             self.update_cursor(size, state);
             self.draw(children.elements());
+        } else if faded {
+            self.draw(children.elements());
+        }
+
+        if self.step {
+            self.step = false;
+            self.paused = true;
+            self.inspect(state);
         }
     }
 
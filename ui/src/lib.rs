@@ -1,20 +1,36 @@
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::time::Duration;
 
 use anathema::prelude::*;
 use vm::Instruction;
 
 use crate::editor::Editor;
+use crate::profile::Profile;
 use crate::random::Random;
 
 mod document;
 mod editor;
 mod markers;
+mod pace;
+mod profile;
 mod random;
+mod raw;
 pub(crate) mod syntax;
+mod teleprompter;
 mod textbuffer;
+mod trail;
 
-pub fn run(instructions: Vec<Instruction>) {
-    let editor = Editor::new(instructions, Duration::from_millis(20));
+pub fn run(
+    instructions: Vec<Instruction>,
+    frame_time: Duration,
+    smooth: bool,
+    trail: bool,
+    profile: bool,
+    teleprompter: Option<usize>,
+) {
+    let profile = profile.then(|| Rc::new(RefCell::new(Profile::new())));
+    let editor = Editor::new(instructions, frame_time, smooth, trail, profile.clone(), teleprompter);
 
     let doc = Document::new("@index");
 
@@ -42,4 +58,9 @@ pub fn run(instructions: Vec<Instruction>) {
         Ok(()) | Err(anathema::runtime::Error::Stop) => {}
         Err(e) => eprintln!("{e}"),
     }
+
+    // Printed once the alt screen is gone
+    if let Some(profile) = profile {
+        eprint!("{}", profile.borrow_mut().summary());
+    }
 }
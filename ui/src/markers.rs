@@ -36,53 +36,11 @@ impl From<&Marker> for Pos {
 }
 
 // marker: <maybe comment> @<ident>
-
-// Possible comment syntax:
-// * //
-// * #
-// * ;
-// * --
-
-// 1. Trim whitespace
-// 2. Source[..possible comment symbols length] == possible comment symbol
-// 3. Trim whitespace
-// 4. Position of '@'
-// 5. Marker = line[pos..].take_while(char::is_ascii_alphabetic].join()
 fn marker(offset: usize, line: &str) -> Option<Marker> {
-    static SYMBOLS: &[&str] = &["//", "#", ";;", ";", "--"];
-
-    // If a string is less than this many bytes
-    // it could not possibly hold a marker
-    const MIN_POSSIBLE_LEN: usize = 3;
-
-    let mut line = line.trim_start();
-
-    if line.len() < MIN_POSSIBLE_LEN {
-        return None;
-    }
-
-    let symbol_len = SYMBOLS
-        .iter()
-        .find(|symbol| line.starts_with(*symbol))
-        .map(|symbol| symbol.len())?;
-
-    line = line[symbol_len..].trim();
-
-    if line.is_empty() || line.as_bytes()[0] != b'@' {
-        return None;
-    }
-
-    // Strip the marker prefix: '@'
-    line = line[1..].trim();
-
-    let marker = line
-        .chars()
-        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
-        .collect::<String>();
-
+    let name = vm::marker_name(line)?;
     Some(Marker {
         row: offset,
-        name: marker.to_string(),
+        name: name.into(),
     })
 }
 
@@ -113,6 +71,12 @@ impl Markers {
         self.inner[index..].iter_mut().for_each(|marker| marker.row += offset);
     }
 
+    // Move the markers from `row` on up a line, once the line before it is removed
+    pub fn remove_line(&mut self, row: usize) {
+        let index = self.inner.partition_point(|marker| marker.row < row);
+        self.inner[index..].iter_mut().for_each(|marker| marker.row -= 1);
+    }
+
     pub fn get(&self, key: &str) -> Option<&Marker> {
         self.inner.iter().find(|Marker { name, .. }| key.eq(name))
     }
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+/// Decouples typing speed from the frame rate.
+/// Every frame adds its duration, and a character is typed for every
+/// `speed` that has passed, carrying the remainder over to the next frame.
+#[derive(Debug, Default)]
+pub struct Pace {
+    carry: Duration,
+}
+
+impl Pace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of characters to type in a frame lasting `dt`
+    pub fn chars(&mut self, dt: Duration, speed: Duration) -> usize {
+        if speed.is_zero() {
+            self.carry = Duration::ZERO;
+            return 1;
+        }
+
+        self.carry += dt;
+        let count = (self.carry.as_nanos() / speed.as_nanos()) as usize;
+        self.carry -= speed * count as u32;
+        count
+    }
+
+    /// Drop the remainder, e.g. after a pause, so it isn't typed in a burst
+    pub fn reset(&mut self) {
+        self.carry = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fractional_chars_add_up() {
+        let mut pace = Pace::new();
+        let speed = Duration::from_millis(20);
+
+        // 125 frames a second for ten seconds, each frame is 0.4 characters
+        let frame = Duration::from_millis(8);
+        let per_frame = (0..1250).map(|_| pace.chars(frame, speed)).collect::<Vec<_>>();
+        assert_eq!(per_frame[..5], [0, 0, 1, 0, 1]);
+        assert_eq!(per_frame.iter().sum::<usize>(), 500);
+
+        // Frames longer than the speed type more than one character
+        assert_eq!(pace.chars(Duration::from_millis(50), speed), 2);
+
+        pace.reset();
+        assert_eq!(pace.chars(Duration::from_millis(10), speed), 0);
+        assert_eq!(pace.chars(Duration::from_millis(10), speed), 1);
+    }
+}
@@ -0,0 +1,93 @@
+use std::cmp::Reverse;
+use std::fmt::Write;
+use std::time::Duration;
+
+use vm::Instruction;
+
+/// The number of characters of each instruction shown in the summary
+const LABEL_LEN: usize = 48;
+
+/// How long every instruction took to play, including typing out its
+/// content and any pauses, until the next instruction started.
+/// Time is added by the frames rather than read from a clock.
+#[derive(Debug, Default)]
+pub struct Profile {
+    timings: Vec<(String, Duration)>,
+    current: Option<(String, Duration)>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start timing the next instruction, ending the current one
+    pub fn start(&mut self, instruction: &Instruction) {
+        self.stop();
+        self.current = Some((label(instruction), Duration::ZERO));
+    }
+
+    /// End the current instruction, e.g. once there are no more instructions
+    pub fn stop(&mut self) {
+        self.timings.extend(self.current.take());
+    }
+
+    /// Add the duration of a frame to the current instruction
+    pub fn tick(&mut self, dt: Duration) {
+        if let Some((_, elapsed)) = self.current.as_mut() {
+            *elapsed += dt;
+        }
+    }
+
+    /// Every instruction and how long it took, slowest first
+    pub fn timings(&mut self) -> &[(String, Duration)] {
+        self.stop();
+        self.timings.sort_by_key(|(_, elapsed)| Reverse(*elapsed));
+        &self.timings
+    }
+
+    pub fn summary(&mut self) -> String {
+        let mut summary = String::new();
+        for (label, elapsed) in self.timings() {
+            _ = writeln!(summary, "{:>10.1}ms  {label}", elapsed.as_secs_f64() * 1000.0);
+        }
+        summary
+    }
+}
+
+fn label(instruction: &Instruction) -> String {
+    let label = format!("{instruction:?}");
+    match label.char_indices().nth(LABEL_LEN) {
+        Some((end, _)) => format!("{}...", &label[..end]),
+        None => label,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record_instruction_timings() {
+        let mut profile = Profile::new();
+        let frame = Duration::from_millis(20);
+
+        // Nothing is recorded before the first instruction
+        profile.tick(frame);
+
+        profile.start(&Instruction::Wait(Duration::from_secs(1)));
+        (0..50).for_each(|_| profile.tick(frame));
+        profile.start(&Instruction::Delete);
+        profile.tick(frame);
+        profile.start(&Instruction::Insert("a".repeat(100)));
+        (0..3).for_each(|_| profile.tick(frame));
+
+        let timings = profile.timings();
+        let expected = [
+            ("Wait(1s)".to_string(), Duration::from_secs(1)),
+            (format!("Insert(\"{}...", "a".repeat(40)), Duration::from_millis(60)),
+            ("Delete".to_string(), Duration::from_millis(20)),
+        ];
+        assert_eq!(timings, expected);
+    }
+}
@@ -1,4 +1,7 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// The seed of `wait one_of`, so every playback of a script waits the same
+const WAIT_SEED: u64 = 0x3a17;
 
 pub struct Random {
     state: u64,
@@ -13,10 +16,65 @@ impl Random {
         Self { state }
     }
 
+    /// The same sequence every time for the same seed
+    pub fn with_seed(seed: u64) -> Self {
+        // Xorshift never leaves zero
+        Self { state: seed.max(1) }
+    }
+
+    /// The generator picking the durations of `wait one_of`
+    pub fn for_waits() -> Self {
+        Self::with_seed(WAIT_SEED)
+    }
+
     pub fn next(&mut self, max: u64) -> u64 {
         self.state ^= self.state >> 13;
         self.state ^= self.state << 5;
         self.state ^= self.state >> 17;
         self.state % max
     }
+
+    /// One of the items, or `None` if there are none
+    pub fn one_of<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        items.get(self.next(items.len() as u64) as usize)
+    }
+
+    /// One of the durations of a `wait one_of`, or no wait if there are none
+    pub fn wait_one_of(&mut self, choices: &[Duration]) -> Duration {
+        self.one_of(choices).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seeded_one_of() {
+        let choices = [1, 2, 5];
+        let picks = |seed| {
+            let mut rand = Random::with_seed(seed);
+            (0..20).map(|_| *rand.one_of(&choices).unwrap()).collect::<Vec<_>>()
+        };
+
+        assert!(picks(7).iter().all(|pick| choices.contains(pick)));
+        assert_eq!(picks(7), picks(7));
+        assert!(Random::with_seed(7).one_of::<u64>(&[]).is_none());
+    }
+
+    #[test]
+    fn waits_are_the_same_every_playback() {
+        let choices = [1, 2, 5].map(Duration::from_secs);
+        let waits = || {
+            let mut rand = Random::for_waits();
+            (0..20).map(|_| rand.wait_one_of(&choices)).collect::<Vec<_>>()
+        };
+
+        assert!(waits().iter().all(|wait| choices.contains(wait)));
+        assert_eq!(waits(), waits());
+        assert_eq!(Random::for_waits().wait_one_of(&[]), Duration::ZERO);
+    }
 }
@@ -0,0 +1,33 @@
+use std::io::{self, Write};
+
+/// Writes the text of `echo_raw` to the terminal as is, next to what the
+/// editor draws, so escape sequences reach the terminal undecoded.
+#[derive(Debug)]
+pub struct RawOutput<W> {
+    writer: W,
+}
+
+impl<W: Write> RawOutput<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Write the bytes of the text, flushed so they aren't held back until the next frame
+    pub fn echo(&mut self, text: &str) -> io::Result<()> {
+        self.writer.write_all(text.as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_exact_bytes() {
+        let mut output = RawOutput::new(vec![]);
+        output.echo("\x1b[2J").unwrap();
+        output.echo("\x1b[H").unwrap();
+        assert_eq!(output.writer, b"\x1b[2J\x1b[H");
+    }
+}
@@ -1,6 +1,8 @@
 use anathema::state::Color;
 use syntect::easy::HighlightLines;
-use syntect::highlighting::{FontStyle, Style, Theme, ThemeSet};
+use syntect::highlighting::{
+    self, FontStyle, ScopeSelectors, Style, StyleModifier, Theme, ThemeItem, ThemeSet, ThemeSettings,
+};
 use syntect::parsing::SyntaxSet;
 use syntect::util::LinesWithEndings;
 
@@ -143,6 +145,40 @@ impl Highlighter {
         Self { ps, theme }
     }
 
+    /// Replace the theme with one loaded by `theme load`,
+    /// failing if a scope isn't a valid scope selector
+    pub fn load_theme(&mut self, theme: &vm::Theme) -> Result<(), String> {
+        let mut scopes = vec![];
+        for (scope, style) in &theme.scopes {
+            let mut font_style = FontStyle::empty();
+            font_style.set(FontStyle::BOLD, style.bold);
+            font_style.set(FontStyle::ITALIC, style.italic);
+            font_style.set(FontStyle::UNDERLINE, style.underline);
+
+            let selectors = scope.parse::<ScopeSelectors>();
+            scopes.push(ThemeItem {
+                scope: selectors.map_err(|err| format!("scope \"{scope}\": {err}"))?,
+                style: StyleModifier {
+                    foreground: style.foreground.map(color),
+                    background: style.background.map(color),
+                    font_style: Some(font_style),
+                },
+            });
+        }
+
+        let settings = ThemeSettings {
+            foreground: theme.foreground.map(color),
+            background: theme.background.map(color),
+            ..Default::default()
+        };
+        self.theme = Theme {
+            settings,
+            scopes,
+            ..Default::default()
+        };
+        Ok(())
+    }
+
     pub fn highlight<'a>(&self, src: &'a str, ext: &str, buffer: &mut Lines<'a>) {
         buffer.reset();
 
@@ -163,3 +199,7 @@ impl Highlighter {
         }
     }
 }
+
+fn color(vm::Rgb(r, g, b): vm::Rgb) -> highlighting::Color {
+    highlighting::Color { r, g, b, a: 0xff }
+}
@@ -0,0 +1,55 @@
+use std::ops::Range;
+
+/// Only shows the newest lines, ending at the cursor row,
+/// so older lines scroll off the top like on a teleprompter.
+/// The buffer keeps every line, only the view drops them.
+#[derive(Debug, Copy, Clone)]
+pub struct Teleprompter {
+    lines: usize,
+}
+
+impl Teleprompter {
+    pub fn new(lines: usize) -> Self {
+        Self { lines }
+    }
+
+    /// The rows to draw while the cursor is on `cursor_row`
+    pub fn rows(&self, cursor_row: usize) -> Range<usize> {
+        vm::teleprompter_rows(cursor_row, self.lines)
+    }
+
+    /// The vertical offset that puts the oldest row drawn at the top of the viewport
+    pub fn offset(&self, cursor_row: usize) -> i32 {
+        -(self.rows(cursor_row).start as i32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anathema::geometry::Pos;
+
+    use super::*;
+    use crate::document::Document;
+
+    #[test]
+    fn only_the_newest_lines_are_drawn() {
+        let teleprompter = Teleprompter::new(3);
+        let mut doc = Document::new(String::new());
+
+        // Typing five lines, one at a time
+        for row in 0..5 {
+            let line = format!("line {row}\n");
+            doc.insert_str(Pos::new(0, row), line);
+        }
+
+        let rows = teleprompter.rows(4);
+        let drawn = rows.map(|row| doc.line(row)).collect::<Vec<_>>();
+        assert_eq!(drawn, ["line 2", "line 3", "line 4"]);
+        assert_eq!(teleprompter.offset(4), -2);
+        assert_eq!(doc.line_count(), 5);
+
+        // Until there are more lines than fit, every line is drawn from the top
+        assert_eq!(teleprompter.rows(1), 0..2);
+        assert_eq!(teleprompter.offset(1), 0);
+    }
+}
@@ -4,6 +4,7 @@ static TAB: &str = "    ";
 pub struct TextBuffer {
     inner: String,
     index: usize,
+    by_word: bool,
 }
 
 impl TextBuffer {
@@ -11,6 +12,7 @@ impl TextBuffer {
         Self {
             inner: String::new(),
             index: 0,
+            by_word: false,
         }
     }
 
@@ -18,14 +20,31 @@ impl TextBuffer {
         self.inner.push_str(s.as_ref());
     }
 
+    pub fn push_reversed(&mut self, s: impl AsRef<str>) {
+        self.inner.extend(s.as_ref().chars().rev());
+    }
+
+    // Type a word at a time until the buffer is exhausted
+    pub fn push_words(&mut self, s: impl AsRef<str>) {
+        self.by_word = true;
+        self.push(s);
+    }
+
     pub fn next(&mut self) -> Option<&str> {
         if self.index == self.inner.len() {
             self.index = 0;
             self.inner.clear();
+            self.by_word = false;
             return None;
         }
 
         let next = &self.inner[self.index..];
+        if self.by_word {
+            let group = vm::word_group(next);
+            self.index += group.len();
+            return Some(group);
+        }
+
         if next.starts_with(TAB) {
             self.index += TAB.len();
             return Some(TAB);
@@ -58,4 +77,28 @@ mod test {
         assert_eq!("c", buf.next().unwrap());
         assert!(buf.next().is_none());
     }
+
+    #[test]
+    fn buffer_next_word() {
+        let mut buf = TextBuffer::new();
+        buf.push_words("a.b c\n");
+
+        assert_eq!("a.", buf.next().unwrap());
+        assert_eq!("b ", buf.next().unwrap());
+        assert_eq!("c", buf.next().unwrap());
+        assert_eq!("\n", buf.next().unwrap());
+        assert!(buf.next().is_none());
+    }
+
+    #[test]
+    fn buffer_next_reversed() {
+        let mut buf = TextBuffer::new();
+        buf.push_reversed("ab\n🐇");
+
+        assert_eq!("🐇", buf.next().unwrap());
+        assert_eq!("\n", buf.next().unwrap());
+        assert_eq!("b", buf.next().unwrap());
+        assert_eq!("a", buf.next().unwrap());
+        assert!(buf.next().is_none());
+    }
 }
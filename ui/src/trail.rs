@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anathema::geometry::Pos;
+
+/// How long a typed character is part of the trail
+const LIFETIME: Duration = Duration::from_millis(300);
+
+/// The most recently typed cells, fading out over a few frames.
+/// This is only used when drawing and never changes the document.
+#[derive(Debug, Default)]
+pub struct Trail {
+    cells: VecDeque<(Pos, Duration)>,
+}
+
+impl Trail {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a cell that was just typed
+    pub fn push(&mut self, pos: Pos) {
+        self.cells.push_back((pos, LIFETIME));
+    }
+
+    /// Age every cell by `dt`, dropping the cells that have faded out.
+    /// Returns `true` if there was anything to fade, meaning it should be redrawn.
+    pub fn tick(&mut self, dt: Duration) -> bool {
+        if self.cells.is_empty() {
+            return false;
+        }
+
+        for (_, left) in self.cells.iter_mut() {
+            *left = left.saturating_sub(dt);
+        }
+        self.cells.retain(|(_, left)| !left.is_zero());
+        true
+    }
+
+    /// How much of the trail is left at `pos`, from 1.0 (just typed) down to 0.0
+    pub fn fade(&self, pos: Pos) -> Option<f32> {
+        self.cells
+            .iter()
+            .rev()
+            .find(|(cell, _)| *cell == pos)
+            .map(|(_, left)| left.as_secs_f32() / LIFETIME.as_secs_f32())
+    }
+}
+
+/// Move a colour channel towards white by `fade`
+pub fn lighten(channel: u8, fade: f32) -> u8 {
+    channel + ((255 - channel) as f32 * fade) as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trail_fades_out() {
+        let mut trail = Trail::new();
+        let frame = Duration::from_millis(20);
+
+        trail.push(Pos::new(0, 0));
+        trail.tick(frame);
+        trail.push(Pos::new(1, 0));
+        trail.tick(frame);
+        trail.push(Pos::new(2, 0));
+
+        // The last few cells carry the trail, newest the brightest
+        let fades = (0..3).map(|x| trail.fade(Pos::new(x, 0)).unwrap()).collect::<Vec<_>>();
+        assert!(fades[0] < fades[1] && fades[1] < fades[2]);
+        assert_eq!(fades[2], 1.0);
+        assert!(trail.fade(Pos::new(3, 0)).is_none());
+
+        // Later frames don't
+        while trail.tick(frame) {}
+        assert!((0..3).all(|x| trail.fade(Pos::new(x, 0)).is_none()));
+    }
+
+    #[test]
+    fn lighten_channel() {
+        assert_eq!(lighten(55, 1.0), 255);
+        assert_eq!(lighten(55, 0.5), 155);
+        assert_eq!(lighten(55, 0.0), 55);
+    }
+}
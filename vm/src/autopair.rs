@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+/// What typing a character does when brackets and quotes are closed automatically
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Keystroke {
+    /// Type the character as is
+    Type,
+    /// Type the character, and the closer after the cursor
+    Pair(char),
+    /// Move the cursor over the closer typed along with its opener
+    TypeThrough,
+}
+
+/// Closes brackets and quotes while typing, like an editor does.
+/// The text is known up front, so an opener is only closed when its closer
+/// really follows in the text. Every closer is inserted right after the cursor,
+/// and pairs nest, so the closer is the next character by the time it's typed
+/// and the typed text always ends up as written.
+#[derive(Debug)]
+pub struct Autopair {
+    // What typing the character at each byte offset of the text does
+    keystrokes: HashMap<usize, Keystroke>,
+    // The byte offset of the next character to type
+    offset: usize,
+}
+
+impl Autopair {
+    pub fn new(text: &str) -> Self {
+        let mut keystrokes = HashMap::new();
+        let mut pair = |start: usize, end: usize, closer: char| {
+            keystrokes.insert(start, Keystroke::Pair(closer));
+            keystrokes.insert(end, Keystroke::TypeThrough);
+        };
+
+        // The open brackets without a closer yet, the innermost last
+        let mut open = vec![];
+        let mut offset = 0;
+
+        while let Some(c) = text[offset..].chars().next() {
+            let start = offset;
+            offset += c.len_utf8();
+
+            match c {
+                '(' => open.push((start, ')')),
+                '[' => open.push((start, ']')),
+                '{' => open.push((start, '}')),
+                // A closer of another bracket, e.g. in `[0, 1)`, is typed as is
+                ')' | ']' | '}' if open.last().is_some_and(|(_, closer)| *closer == c) => {
+                    let (opener, _) = open.pop().expect("checked by the guard");
+                    pair(opener, start, c);
+                }
+                // Brackets in a string are part of the string
+                '"' | '`' | '\'' => {
+                    let end = match c {
+                        '\'' => char_literal_end(&text[offset..]),
+                        _ => string_end(&text[offset..], c),
+                    };
+                    if let Some(end) = end {
+                        offset += end;
+                        pair(start, offset, c);
+                        offset += c.len_utf8();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Self { keystrokes, offset: 0 }
+    }
+
+    pub fn keystroke(&mut self, s: &str) -> Keystroke {
+        let offset = self.offset;
+        self.offset += s.len();
+
+        // Only single characters are paired, not indentation typed at once
+        if s.chars().nth(1).is_some() {
+            return Keystroke::Type;
+        }
+        self.keystrokes.get(&offset).copied().unwrap_or(Keystroke::Type)
+    }
+}
+
+// The offset of the quote closing a string, skipping escaped quotes
+fn string_end(rest: &str, quote: char) -> Option<usize> {
+    let mut escaping = false;
+    for (offset, c) in rest.char_indices() {
+        match c {
+            _ if escaping => escaping = false,
+            '\\' => escaping = true,
+            _ if c == quote => return Some(offset),
+            _ => {}
+        }
+    }
+    None
+}
+
+// The offset of the quote closing a character literal like `'a'` or `'\n'`.
+// Anything else is an apostrophe, as in "don't", or a lifetime, as in `&'a str`
+fn char_literal_end(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices();
+    match chars.next()? {
+        (_, '\'' | '\n') => None,
+        // An escape, up to the quote after the escaped character: `'\''`, `'\x41'` or `'\u{1F99C}'`
+        (_, '\\') => {
+            chars.next()?;
+            chars
+                .take_while(|(_, c)| *c != '\n')
+                .find(|(_, c)| *c == '\'')
+                .map(|(offset, _)| offset)
+        }
+        _ => chars.next().filter(|(_, c)| *c == '\'').map(|(offset, _)| offset),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Type the first `count` characters of the text, returning the buffer
+    fn type_out(text: &str, count: usize) -> String {
+        let mut autopair = Autopair::new(text);
+        let mut buffer = String::new();
+        let mut cursor = 0;
+
+        for c in text.chars().take(count) {
+            match autopair.keystroke(c.encode_utf8(&mut [0; 4])) {
+                Keystroke::Type => buffer.insert(cursor, c),
+                Keystroke::Pair(closer) => {
+                    buffer.insert(cursor, closer);
+                    buffer.insert(cursor, c);
+                }
+                Keystroke::TypeThrough => {}
+            }
+            cursor += c.len_utf8();
+        }
+
+        buffer
+    }
+
+    fn type_all(text: &str) -> String {
+        type_out(text, usize::MAX)
+    }
+
+    #[test]
+    fn auto_close() {
+        assert_eq!(type_out("foo(bar)", 7), "foo(bar)");
+        assert_eq!(type_out("[1, {a: \"b\"}]", 10), "[1, {a: \"b\"}]");
+        assert_eq!(type_out("fn main() {\n    a();\n}", 16), "fn main() {\n    }");
+        assert_eq!(type_out("let c = 'a';", 10), "let c = 'a'");
+    }
+
+    #[test]
+    fn typed_text_is_unchanged() {
+        let texts = [
+            "foo(bar)",
+            "[1, {a: \"b\"}]",
+            "fn main() {\n    a[0];\n}",
+            "fn f<'a>(x: &'a str) {}",
+            "let s = \"a(b\";",
+            "let c = ['\\'', '\\n', '\\u{1F99C}', '('];",
+            "say \"it's\" and don't",
+            "a) [0, 1) \"open",
+            "let s = \"\\\"(\\\"\";",
+        ];
+        for text in texts {
+            assert_eq!(type_all(text), text);
+        }
+    }
+
+    #[test]
+    fn only_pair_with_a_closer() {
+        // A bracket in a string, and a bracket without a closer
+        assert_eq!(type_out("let s = \"a(b\";", 11), "let s = \"a(\"");
+        assert_eq!(type_out("a (b", 3), "a (");
+
+        // A lifetime rather than a character literal
+        assert_eq!(type_out("fn f<'a>", 6), "fn f<'");
+        assert_eq!(type_out("don't", 4), "don'");
+    }
+
+    #[test]
+    fn indentation_is_typed_as_is() {
+        let mut autopair = Autopair::new("    (a)");
+        assert_eq!(autopair.keystroke("    "), Keystroke::Type);
+        assert_eq!(autopair.keystroke("("), Keystroke::Pair(')'));
+        assert_eq!(autopair.keystroke("a"), Keystroke::Type);
+        assert_eq!(autopair.keystroke(")"), Keystroke::TypeThrough);
+    }
+}
@@ -1,13 +1,125 @@
 use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use parser::Source;
 
 use crate::error::{Error, Result};
+use crate::files::{Files, relative_to_script};
 
 pub struct Context {
     data: HashMap<String, String>,
+    // The index of the first instruction after each label, by the repeat iterations it's in.
+    // Labels are kept in order, so the ones added by a failed instruction can be dropped
+    labels: Vec<((Vec<usize>, String), usize)>,
+    // The index of every jump, the repeat iterations it's in and the label it jumps to
+    jumps: Vec<(usize, Vec<usize>, String)>,
+    // The repeat iterations being compiled, innermost last.
+    // A repeat block is copied for every iteration, so every copy has its own labels
+    iterations: Vec<usize>,
+    // The number of repeat iterations compiled so far, to tell them apart
+    iteration_count: usize,
+    // The scripts being included, innermost last
+    includes: Vec<PathBuf>,
+    files: Files,
 }
 impl Context {
     pub(crate) fn new() -> Self {
-        Self { data: HashMap::new() }
+        Self {
+            data: HashMap::new(),
+            labels: vec![],
+            jumps: vec![],
+            iterations: vec![],
+            iteration_count: 0,
+            includes: vec![],
+            files: Files::on_disk(),
+        }
+    }
+
+    /// Read a file, reusing the content if it was read before and hasn't changed since
+    pub(crate) fn read_file(&mut self, path: &Path) -> Result<String> {
+        self.files.read(path).map_err(|err| Error::Import(path.into(), err))
+    }
+
+    /// Resolve the path of an included script against the script including it
+    pub(crate) fn include_path(&self, path: &Path, script: Option<&Path>) -> PathBuf {
+        let including = self.includes.last().map(PathBuf::as_path).or(script);
+        relative_to_script(path, including)
+    }
+
+    /// Start including a script, failing if it is already being included
+    pub(crate) fn start_include(&mut self, path: &Path, script: Option<&Path>) -> Result<()> {
+        let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.into());
+        let included = canonical(path);
+
+        let mut including = script.into_iter().chain(self.includes.iter().map(PathBuf::as_path));
+        if including.any(|p| canonical(p) == included) {
+            return Err(Error::IncludeCycle(path.into()));
+        }
+
+        self.includes.push(included);
+        Ok(())
+    }
+
+    pub(crate) fn end_include(&mut self) {
+        self.includes.pop();
+    }
+
+    /// Start compiling another iteration of a repeat block
+    pub(crate) fn start_iteration(&mut self) {
+        self.iteration_count += 1;
+        self.iterations.push(self.iteration_count);
+    }
+
+    pub(crate) fn end_iteration(&mut self) {
+        self.iterations.pop();
+    }
+
+    pub(crate) fn add_label(&mut self, label: String, index: usize) -> Result<()> {
+        let key = (self.iterations.clone(), label);
+        if self.label(&key).is_some() {
+            return Err(Error::DuplicateLabel(key.1));
+        }
+        self.labels.push((key, index));
+        Ok(())
+    }
+
+    fn label(&self, key: &(Vec<usize>, String)) -> Option<usize> {
+        self.labels
+            .iter()
+            .find(|(label, _)| label == key)
+            .map(|(_, index)| *index)
+    }
+
+    pub(crate) fn add_jump(&mut self, index: usize, label: String) {
+        self.jumps.push((index, self.iterations.clone(), label));
+    }
+
+    /// The number of labels and jumps, to roll back to if compiling an instruction fails
+    pub(crate) fn checkpoint(&self) -> (usize, usize) {
+        (self.labels.len(), self.jumps.len())
+    }
+
+    pub(crate) fn roll_back(&mut self, (labels, jumps): (usize, usize)) {
+        self.labels.truncate(labels);
+        self.jumps.truncate(jumps);
+    }
+
+    /// Every jump with the index of the instruction it jumps to.
+    /// A jump goes to the label in the innermost iteration it's in
+    pub(crate) fn resolve_jumps(&self) -> Result<Vec<(usize, usize)>> {
+        self.jumps
+            .iter()
+            .map(|(index, iterations, label)| {
+                let dest = (0..=iterations.len())
+                    .rev()
+                    .find_map(|depth| self.label(&(iterations[..depth].to_vec(), label.clone())));
+                match dest {
+                    Some(dest) => Ok((*index, dest)),
+                    None => Err(Error::UndefinedLabel(label.clone())),
+                }
+            })
+            .collect()
     }
 
     pub fn set(&mut self, key: String, value: String) {
@@ -18,4 +130,109 @@ impl Context {
         let key = key.as_ref();
         self.data.get(key).cloned().ok_or_else(|| Error::Load(key.into()))
     }
+
+    /// The text of a source: a string as written, the value of an ident, or an
+    /// interpolated string with every `${name}` replaced by the value of the
+    /// variable and every `$$` by a `$`
+    pub(crate) fn resolve(&self, source: Source) -> Result<String> {
+        match source {
+            Source::Str(text) => Ok(text),
+            Source::Ident(key) => self.load(key),
+            Source::Interpolated(text) => self.interpolate(&text),
+        }
+    }
+
+    fn interpolate(&self, text: &str) -> Result<String> {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find('$') {
+            output.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                output.push('$');
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('{') {
+                let Some(end) = after.find('}') else {
+                    return Err(Error::UnclosedInterpolation(text.into()));
+                };
+                output.push_str(&self.load(after[..end].trim())?);
+                rest = &after[end + 1..];
+            } else {
+                // A lone `$`, e.g. a shell prompt
+                output.push('$');
+            }
+        }
+
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    /// The name of every variable, sorted
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys = self.data.keys().map(String::as_str).collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Write every variable with its value, truncated to `max_len` characters
+    pub fn dump(&self, out: &mut impl Write, max_len: usize) -> std::io::Result<()> {
+        for key in self.keys() {
+            let value = &self.data[key];
+            match value.char_indices().nth(max_len) {
+                Some((end, _)) => writeln!(out, "{key} = {:?}...", &value[..end])?,
+                None => writeln!(out, "{key} = {value:?}")?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a value as a line number, starting at one
+    pub fn load_line(&self, key: impl AsRef<str>) -> Result<u16> {
+        let key = key.as_ref();
+        let value = self.load(key)?;
+        match value.trim().parse() {
+            Ok(line @ 1..) => Ok(line),
+            _ => Err(Error::InvalidLine(key.into(), value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dump_variables() {
+        let mut context = Context::new();
+        context.set("b".into(), "fn main() {\n}".into());
+        context.set("a".into(), "short".into());
+
+        let mut out = vec![];
+        context.dump(&mut out, 8).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, "a = \"short\"\nb = \"fn main(\"...\n");
+    }
+
+    #[test]
+    fn interpolate_variables() {
+        let mut context = Context::new();
+        context.set("name".into(), "parrot".into());
+
+        let interpolate = |text: &str| context.resolve(Source::Interpolated(text.into()));
+
+        assert_eq!(interpolate("Hello ${name}!").unwrap(), "Hello parrot!");
+        assert_eq!(interpolate("$$HOME is $5 ${ name }$$").unwrap(), "$HOME is $5 parrot$");
+
+        // A plain string is left as written
+        let text = context.resolve(Source::Str("${name} costs $$5".into())).unwrap();
+        assert_eq!(text, "${name} costs $$5");
+
+        let err = interpolate("Hello ${missing}").unwrap_err();
+        assert_eq!(err.to_string(), "\"missing\" does not exist");
+
+        let err = interpolate("Hello ${name").unwrap_err();
+        assert_eq!(err.to_string(), "\"Hello ${name\" has a \"${\" without a closing \"}\"");
+    }
 }
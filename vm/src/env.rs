@@ -0,0 +1,132 @@
+use crate::error::{Error, Result};
+
+/// Parse `KEY=VALUE` pairs, one per line, as in a `.env` file.
+///
+/// Empty lines and lines starting with `#` are skipped, and a line can start with `export`.
+/// A value in double quotes can contain `\n`, `\"` and `\\` escapes, a value in single
+/// quotes is taken as written, and any value can be followed by a ` #` comment.
+pub fn parse_env(content: &str) -> Result<Vec<(String, String)>> {
+    let mut variables = vec![];
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let err = |reason: &str| Error::Env(index + 1, reason.into());
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(err("expected KEY=VALUE"));
+        };
+
+        let key = key.trim();
+        let valid_key = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_key {
+            return Err(err("the key can only contain letters, digits and `_`"));
+        }
+
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let Some(end) = closing_quote(&value[1..], quote) else {
+                    return Err(err("unterminated quote"));
+                };
+                let (quoted, rest) = (&value[1..end + 1], value[end + 2..].trim_start());
+                if !(rest.is_empty() || rest.starts_with('#')) {
+                    return Err(err("unexpected text after the closing quote"));
+                }
+                if quote == '"' { unescape(quoted) } else { quoted.to_string() }
+            }
+            _ => match value.find(" #") {
+                Some(comment) => value[..comment].trim_end().to_string(),
+                None => value.to_string(),
+            },
+        };
+
+        variables.push((key.to_string(), value));
+    }
+
+    Ok(variables)
+}
+
+// The byte offset of the quote closing the value, skipping escaped quotes in double quotes
+fn closing_quote(value: &str, quote: char) -> Option<usize> {
+    let mut chars = value.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' if quote == '"' => {
+                chars.next();
+            }
+            c if c == quote => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unescape(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => output.push('\n'),
+            ('\\', Some(escaped @ ('"' | '\\'))) => output.push(escaped),
+            _ => {
+                output.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_dotenv() {
+        let content = r#"
+# the demo
+NAME=parrot
+export LANG = rust # the language
+GREETING="Hello, \"world\"\nbye"
+RAW='a\nb # c'
+EMPTY=
+NOTE="x" # the note
+"#;
+        let variables = parse_env(content).unwrap();
+        let expected = [
+            ("NAME", "parrot"),
+            ("LANG", "rust"),
+            ("GREETING", "Hello, \"world\"\nbye"),
+            ("RAW", "a\\nb # c"),
+            ("EMPTY", ""),
+            ("NOTE", "x"),
+        ];
+        let expected = expected.map(|(key, value)| (key.to_string(), value.to_string()));
+        assert_eq!(variables, expected);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let err = parse_env("A=1\n\nB 2").unwrap_err();
+        assert_eq!(err.to_string(), "line 3 of the env file: expected KEY=VALUE");
+
+        let err = parse_env("1A=1").unwrap_err();
+        assert!(err.to_string().starts_with("line 1 of the env file: the key"));
+
+        let err = parse_env("# comment\nA=\"open").unwrap_err();
+        assert_eq!(err.to_string(), "line 2 of the env file: unterminated quote");
+
+        assert!(parse_env("A=\"").is_err());
+        assert!(parse_env("A=\"a\\\"").is_err());
+
+        let err = parse_env("A=\"x\" y").unwrap_err();
+        assert!(err.to_string().ends_with("unexpected text after the closing quote"));
+    }
+}
@@ -1,20 +1,104 @@
 use std::path::PathBuf;
 
+use crate::MAX_ITERATIONS;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
-    Import(PathBuf),
+    Import(PathBuf, std::io::Error),
+    Parse(PathBuf, String),
+    IncludeCycle(PathBuf),
     Load(String),
+    Undefined(String, usize),
+    AssertVar(String, String, String),
+    InvalidLine(String, String),
+    LineRange(String, usize, usize),
+    Expression(String, String),
+    Env(usize, String),
+    UnclosedInterpolation(String),
+    Recording(PathBuf, String),
+    Theme(PathBuf, String),
+    Unsafe(String),
+    Timeout(PathBuf),
+    EmptyRegister(String),
+    NoAnchor,
+    EmptySelectionStack,
+    NoSearch,
+    NoType,
+    ReservedMarker(String),
+    MultilineDelimiter(String),
+    InvalidRegex(String),
+    MaxIterations(usize),
+    RepeatCount(u32),
+    MissingGroup(String, usize),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+}
+
+impl Error {
+    /// Only reading a file can succeed when tried again, once the file exists
+    pub(crate) fn is_transient(&self) -> bool {
+        matches!(self, Error::Import(..))
+    }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::Import(path) => write!(f, "failed to load \"{}\"", path.to_str().unwrap_or("<path>")),
+            Error::Import(path, _) => write!(f, "failed to load \"{}\"", path.to_str().unwrap_or("<path>")),
+            Error::Parse(path, err) => write!(f, "failed to parse \"{}\": {err}", path.display()),
+            Error::IncludeCycle(path) => write!(f, "\"{}\" includes itself", path.display()),
             Error::Load(key) => write!(f, "\"{key}\" does not exist"),
+            Error::Undefined(key, index) => {
+                write!(f, "instruction {index} uses \"{key}\" before it is loaded")
+            }
+            Error::Expression(expr, reason) => write!(f, "invalid expression \"{expr}\": {reason}"),
+            Error::Env(line, reason) => write!(f, "line {line} of the env file: {reason}"),
+            Error::UnclosedInterpolation(text) => write!(f, "\"{text}\" has a \"${{\" without a closing \"}}\""),
+            Error::AssertVar(key, expected, actual) => write!(f, "\"{key}\" is {actual:?}, expected {expected:?}"),
+            Error::NoAnchor => write!(f, "select to requires an anchor"),
+            Error::EmptySelectionStack => write!(f, "pop_selection without a pushed selection"),
+            Error::DuplicateLabel(label) => write!(f, "label \"{label}\" is already defined"),
+            Error::UndefinedLabel(label) => write!(f, "jump to undefined label \"{label}\""),
+            Error::InvalidRegex(err) => write!(f, "{err}"),
+            Error::MaxIterations(max) => write!(f, "repeat until exceeded {max} iterations"),
+            Error::RepeatCount(count) => write!(f, "repeat {count} exceeds {MAX_ITERATIONS} iterations"),
+            Error::MissingGroup(pattern, group) => write!(f, "/{pattern}/ has no group {group}"),
+            Error::NoSearch => write!(f, "search_next and search_prev require a previous find"),
+            Error::NoType => write!(f, "goto last_type requires a previous type"),
+            Error::ReservedMarker(name) => write!(f, "the marker name \"{name}\" is reserved for goto {name}"),
+            Error::MultilineDelimiter(delimiter) => write!(f, "surround {delimiter:?} contains a newline"),
+            Error::EmptyRegister(name) => write!(f, "register \"{name}\" is empty"),
+            Error::Timeout(path) => write!(f, "timed out waiting for \"{}\"", path.display()),
+            Error::InvalidLine(key, value) => write!(f, "\"{key}\" is not a line number: \"{value}\""),
+            Error::LineRange(key, line, count) => write!(f, "\"{key}\" has {count} lines, there is no line {line}"),
+            Error::Recording(path, reason) => write!(f, "invalid recording \"{}\": {reason}", path.display()),
+            Error::Unsafe(instruction) => write!(f, "{instruction} is not allowed in safe mode"),
+            Error::Theme(path, reason) => write!(f, "invalid theme \"{}\": {reason}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Import(_, err) => Some(err),
+            _ => None,
         }
     }
 }
 
-impl std::error::Error for Error {}
+/// A problem that doesn't stop the script from compiling
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    /// The index of the instruction in the script
+    pub instruction: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "instruction {}: {}", self.instruction, self.message)
+    }
+}
@@ -0,0 +1,318 @@
+use std::time::Duration;
+
+use crate::instructions::{Instruction, TypeOptions};
+use crate::{CLAUSE_PAUSE_FRAMES, MIN_SPEED, RANDOM_DELAY, SENTENCE_PAUSE_FRAMES};
+
+// The ui types four spaces as a single character
+static TAB: &str = "    ";
+
+/// Estimate how long it takes to play back the instructions.
+///
+/// Frames count the average of the random delay the ui adds to them until the first `speed`.
+/// A jump forward skips the instructions in between, and the body of a
+/// `repeat until` is counted once. Jumping back to a label loops forever,
+/// so the estimate stops at the jump.
+pub fn estimate(instructions: &[Instruction], frame_time: Duration) -> Duration {
+    estimate_with(instructions, frame_time, mean_random_delay())
+}
+
+// The random delay is a whole number of milliseconds less than `RANDOM_DELAY`
+fn mean_random_delay() -> Duration {
+    RANDOM_DELAY.saturating_sub(Duration::from_millis(1)) / 2
+}
+
+// The estimate with `random_delay` as the average random delay of a frame before the first `speed`
+fn estimate_with(instructions: &[Instruction], mut frame_time: Duration, mut random_delay: Duration) -> Duration {
+    let mut line_pause = Duration::ZERO;
+    let mut total = Duration::ZERO;
+    let mut index = 0;
+
+    while let Some(instruction) = instructions.get(index) {
+        index += 1;
+        total += match instruction {
+            Instruction::Wait(duration) | Instruction::Keystroke(_, duration) => *duration,
+            // The average of the choices
+            Instruction::WaitOneOf(choices) => choices.iter().sum::<Duration>() / choices.len().max(1) as u32,
+            // The longest a tail can take
+            Instruction::Tail(_, timeout) => *timeout,
+            _ => frame_time + random_delay,
+        };
+
+        match instruction {
+            // The jitter averages out, and replaces the random delay
+            Instruction::Speed { base, .. } => {
+                frame_time = *base;
+                random_delay = Duration::ZERO;
+            }
+            Instruction::LinePause(duration) => line_pause = *duration,
+            Instruction::LoadTypeBuffer(content, options) => {
+                let line_pause = options.line_pause.unwrap_or(line_pause);
+                total += typing(content, *options, frame_time, random_delay, line_pause)
+            }
+            Instruction::JumpToInstruction(dest) if *dest >= index => index = *dest,
+            // The end of the body of a `repeat until`, which continues after the loop
+            Instruction::JumpToInstruction(dest) if is_loop_head(instructions.get(*dest), index) => {}
+            Instruction::JumpToInstruction(_) => break,
+            _ => {}
+        }
+    }
+
+    total
+}
+
+// The `repeat until` that ends right before `index`
+fn is_loop_head(instruction: Option<&Instruction>, index: usize) -> bool {
+    matches!(instruction, Some(Instruction::Until { end, .. }) if *end == index)
+}
+
+/// Scale every speed, line pause and wait by the same factor so the estimated
+/// playback time is `target`, returning the frame time to start playback with.
+///
+/// Tails and the random delay of every frame are not scaled, and frames are never
+/// shorter than `MIN_SPEED`, so the estimate can still be longer than the target.
+pub fn fit_duration(instructions: &mut [Instruction], frame_time: Duration, target: Duration) -> Duration {
+    let tails = instructions
+        .iter()
+        .map(|instruction| match instruction {
+            Instruction::Tail(_, timeout) => *timeout,
+            _ => Duration::ZERO,
+        })
+        .sum::<Duration>();
+
+    let unscaled = estimate_with(instructions, frame_time, Duration::ZERO);
+    let random_delays = estimate(instructions, frame_time) - unscaled;
+    let scalable = unscaled.saturating_sub(tails);
+    if scalable.is_zero() {
+        return frame_time;
+    }
+
+    let factor = target.saturating_sub(tails + random_delays).as_secs_f64() / scalable.as_secs_f64();
+    let speed = |duration: Duration| duration.mul_f64(factor).max(MIN_SPEED);
+
+    for instruction in instructions.iter_mut() {
+        match instruction {
+            Instruction::Speed { base, jitter } => {
+                *base = speed(*base);
+                *jitter = jitter.mul_f64(factor).min(*base);
+            }
+            Instruction::Wait(duration) | Instruction::LinePause(duration) | Instruction::Keystroke(_, duration) => {
+                *duration = duration.mul_f64(factor)
+            }
+            Instruction::WaitOneOf(choices) => {
+                for duration in choices.iter_mut() {
+                    *duration = duration.mul_f64(factor);
+                }
+            }
+            Instruction::LoadTypeBuffer(_, TypeOptions { line_pause, .. }) => {
+                *line_pause = line_pause.map(|duration| duration.mul_f64(factor))
+            }
+            _ => {}
+        }
+    }
+
+    speed(frame_time)
+}
+
+/// The extra pause after typing `typed` when typing naturally,
+/// as a number of frames so it scales with the speed.
+pub fn natural_pause(typed: &str, frame_time: Duration) -> Duration {
+    let frames = match typed.trim_end_matches(' ').chars().last() {
+        Some('.') => SENTENCE_PAUSE_FRAMES,
+        Some(',' | ';' | '\n') => CLAUSE_PAUSE_FRAMES,
+        _ => 0,
+    };
+
+    frame_time * frames
+}
+
+fn typing(
+    mut content: &str,
+    options: TypeOptions,
+    frame_time: Duration,
+    random_delay: Duration,
+    line_pause: Duration,
+) -> Duration {
+    let mut total = Duration::ZERO;
+
+    while let Some(c) = content.chars().next() {
+        let typed = if options.by_word {
+            crate::text::word_group(content)
+        } else if content.starts_with(TAB) {
+            TAB
+        } else {
+            &content[..c.len_utf8()]
+        };
+        content = &content[typed.len()..];
+
+        // A line pause replaces the frame
+        total += if typed == "\n" && line_pause > Duration::ZERO { line_pause } else { frame_time + random_delay };
+        if options.natural {
+            total += natural_pause(typed, frame_time);
+        }
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // Whole frames, without the random delay
+    fn exact(instructions: &[Instruction], frame_time: Duration) -> Duration {
+        estimate_with(instructions, frame_time, Duration::ZERO)
+    }
+
+    #[test]
+    fn estimate_typing() {
+        let instructions = vec![Instruction::LoadTypeBuffer("ab\n    c".into(), TypeOptions::default())];
+
+        // One frame to load the buffer and one frame for each of `a`, `b`, `\n`, `    ` and `c`
+        let duration = exact(&instructions, ms(10));
+        assert_eq!(duration, ms(60));
+    }
+
+    #[test]
+    fn estimate_typing_by_word() {
+        let options = TypeOptions {
+            by_word: true,
+            ..Default::default()
+        };
+        let instructions = vec![Instruction::LoadTypeBuffer("let a = b;\nc".into(), options)];
+
+        // One frame to load the buffer and one frame for each of `let `, `a `, `= `, `b;`, `\n` and `c`
+        let duration = exact(&instructions, ms(10));
+        assert_eq!(duration, ms(70));
+    }
+
+    #[test]
+    fn estimate_typing_naturally() {
+        let options = TypeOptions {
+            natural: true,
+            ..Default::default()
+        };
+
+        // A period is followed by eight frames, a comma by four
+        let instructions = vec![Instruction::LoadTypeBuffer("a.".into(), options)];
+        assert_eq!(exact(&instructions, ms(10)), ms(110));
+
+        let instructions = vec![Instruction::LoadTypeBuffer("a,".into(), options)];
+        assert_eq!(exact(&instructions, ms(10)), ms(70));
+
+        let instructions = vec![Instruction::LoadTypeBuffer("a.".into(), TypeOptions::default())];
+        assert_eq!(exact(&instructions, ms(10)), ms(30));
+
+        // The pause scales with the speed
+        assert_eq!(natural_pause(".", ms(20)), ms(160));
+        assert_eq!(natural_pause("a", ms(20)), Duration::ZERO);
+    }
+
+    #[test]
+    fn estimate_waits_and_speed() {
+        let instructions = vec![
+            Instruction::Wait(Duration::from_secs(2)),
+            Instruction::Speed {
+                base: ms(100),
+                jitter: ms(30),
+            },
+            Instruction::LinePause(ms(500)),
+            Instruction::LoadTypeBuffer("a\nb".into(), TypeOptions::default()),
+        ];
+
+        let duration = exact(&instructions, ms(10));
+        assert_eq!(duration, ms(2000 + 10 + 100 + 100 + 100 + 500 + 100));
+
+        // A random wait counts as the average of the choices
+        let instructions = vec![Instruction::WaitOneOf(vec![ms(1000), ms(2000), ms(6000)])];
+        assert_eq!(estimate(&instructions, ms(10)), ms(3000));
+    }
+
+    #[test]
+    fn estimate_random_delay() {
+        // Every frame is 9.5ms longer on average, until a speed is set
+        let instructions = vec![Instruction::LoadTypeBuffer("ab".into(), TypeOptions::default())];
+        assert_eq!(estimate(&instructions, ms(10)), Duration::from_micros(3 * 19_500));
+
+        for jitter in [ms(5), Duration::ZERO] {
+            let instructions = vec![
+                Instruction::Speed { base: ms(10), jitter },
+                Instruction::LoadTypeBuffer("ab".into(), TypeOptions::default()),
+            ];
+            let expected = Duration::from_micros(19_500) + ms(3 * 10);
+            assert_eq!(estimate(&instructions, ms(10)), expected);
+        }
+    }
+
+    #[test]
+    fn estimate_type_line_pause() {
+        let options = TypeOptions {
+            line_pause: Some(ms(300)),
+            ..Default::default()
+        };
+        let instructions = vec![
+            Instruction::LinePause(ms(500)),
+            Instruction::LoadTypeBuffer("a\nb\nc\n".into(), options),
+            Instruction::LoadTypeBuffer("d\n".into(), TypeOptions::default()),
+        ];
+
+        // One frame for every instruction and letter. The three newlines of the first text
+        // pause for its own line pause, the newline of the next text for the line pause again.
+        let duration = exact(&instructions, ms(10));
+        assert_eq!(duration, ms(3 * 10 + 3 * 10 + 3 * 300 + 10 + 500));
+    }
+
+    #[test]
+    fn estimate_follows_jumps() {
+        let wait = Instruction::Wait(Duration::from_secs(1));
+        let until = Instruction::Until {
+            condition: crate::Condition::BufferContains("a".into()),
+            end: 3,
+        };
+
+        // The wait jumped over is skipped
+        let instructions = vec![
+            Instruction::JumpToInstruction(2),
+            wait.clone(),
+            Instruction::Insert("a".into()),
+        ];
+        assert_eq!(exact(&instructions, ms(10)), ms(2 * 10));
+
+        // The body of a loop counts once
+        let instructions = vec![until, wait.clone(), Instruction::JumpToInstruction(0), wait.clone()];
+        assert_eq!(exact(&instructions, ms(10)), ms(2000 + 2 * 10));
+
+        // A jump back to a label loops forever, so the estimate stops there
+        let instructions = vec![wait.clone(), Instruction::JumpToInstruction(0), wait];
+        assert_eq!(exact(&instructions, ms(10)), ms(1000 + 10));
+    }
+
+    #[test]
+    fn fit_to_target_duration() {
+        let mut instructions = vec![
+            Instruction::Wait(Duration::from_secs(2)),
+            Instruction::LinePause(ms(500)),
+            Instruction::LoadTypeBuffer("fn main() {\n    a.b();\n}".repeat(10), TypeOptions::default()),
+            Instruction::Speed {
+                base: ms(50),
+                jitter: Duration::ZERO,
+            },
+            Instruction::LoadTypeBuffer("let a = 1;".into(), TypeOptions::default()),
+            Instruction::Tail("app.log".into(), Duration::from_secs(5)),
+        ];
+
+        let target = Duration::from_secs(60);
+        let frame_time = fit_duration(&mut instructions, ms(20), target);
+        let duration = estimate(&instructions, frame_time);
+        assert!(duration.abs_diff(target) < ms(100), "{duration:?}");
+
+        // Frames can't be shorter than the minimum speed
+        let target = ms(5_500);
+        let frame_time = fit_duration(&mut instructions, frame_time, target);
+        assert_eq!(frame_time, MIN_SPEED);
+        assert!(estimate(&instructions, frame_time) > target);
+    }
+}
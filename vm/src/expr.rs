@@ -0,0 +1,197 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+
+/// Evaluate integer and float arithmetic with `+ - * / ( )`,
+/// where `${name}` is the numeric value of a variable.
+/// Integers stay integers unless divided with a remainder.
+pub(crate) fn evaluate(expr: &str, context: &Context) -> Result<String> {
+    let mut parser = Parser {
+        chars: expr.chars().peekable(),
+        context,
+    };
+
+    let value = parser.expr().and_then(|value| match parser.next() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected `{c}`")),
+    });
+
+    match value {
+        Ok(Number::Int(int)) => Ok(int.to_string()),
+        Ok(Number::Float(float)) => Ok(float.to_string()),
+        Err(reason) => Err(Error::Expression(expr.into(), reason)),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        match value.parse() {
+            Ok(int) => Some(Self::Int(int)),
+            Err(_) => value.parse().ok().map(Self::Float),
+        }
+    }
+
+    fn float(self) -> f64 {
+        match self {
+            Self::Int(int) => int as f64,
+            Self::Float(float) => float,
+        }
+    }
+
+    fn apply(self, op: char, rhs: Self) -> std::result::Result<Self, String> {
+        if op == '/' && rhs.float() == 0.0 {
+            return Err("division by zero".into());
+        }
+
+        if let (Self::Int(lhs), Self::Int(rhs)) = (self, rhs) {
+            let int = match op {
+                '+' => lhs.checked_add(rhs),
+                '-' => lhs.checked_sub(rhs),
+                '*' => lhs.checked_mul(rhs),
+                // A division with a remainder gives a float
+                _ => match lhs.checked_rem(rhs) {
+                    Some(0) => lhs.checked_div(rhs),
+                    Some(_) => return Ok(Self::Float(lhs as f64 / rhs as f64)),
+                    None => None,
+                },
+            };
+            return int.map(Self::Int).ok_or_else(|| "integer overflow".into());
+        }
+
+        let (lhs, rhs) = (self.float(), rhs.float());
+        let float = match op {
+            '+' => lhs + rhs,
+            '-' => lhs - rhs,
+            '*' => lhs * rhs,
+            _ => lhs / rhs,
+        };
+        Ok(Self::Float(float))
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    context: &'a Context,
+}
+
+impl Parser<'_> {
+    // The next character that isn't whitespace
+    fn next(&mut self) -> Option<char> {
+        self.chars.find(|c| !c.is_whitespace())
+    }
+
+    fn next_if(&mut self, f: impl Fn(char) -> bool) -> Option<char> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.next_if(|c| f(*c))
+    }
+
+    // expr = term (('+' | '-') term)*
+    fn expr(&mut self) -> std::result::Result<Number, String> {
+        let mut value = self.term()?;
+        while let Some(op) = self.next_if(|c| matches!(c, '+' | '-')) {
+            value = value.apply(op, self.term()?)?;
+        }
+        Ok(value)
+    }
+
+    // term = factor (('*' | '/') factor)*
+    fn term(&mut self) -> std::result::Result<Number, String> {
+        let mut value = self.factor()?;
+        while let Some(op) = self.next_if(|c| matches!(c, '*' | '/')) {
+            value = value.apply(op, self.factor()?)?;
+        }
+        Ok(value)
+    }
+
+    // factor = '-' factor | '(' expr ')' | '${' name '}' | number
+    fn factor(&mut self) -> std::result::Result<Number, String> {
+        match self.next() {
+            Some('-') => Number::Int(0).apply('-', self.factor()?),
+            Some('(') => {
+                let value = self.expr()?;
+                match self.next() {
+                    Some(')') => Ok(value),
+                    _ => Err("missing `)`".into()),
+                }
+            }
+            Some('$') => {
+                if self.chars.next() != Some('{') {
+                    return Err("expected `{` after `$`".into());
+                }
+                let name = self.chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                let name = name.trim();
+                let value = self.context.load(name).map_err(|err| err.to_string())?;
+                Number::parse(&value).ok_or_else(|| format!("\"{name}\" is not a number: \"{value}\""))
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let mut number = String::from(c);
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+                Number::parse(&number).ok_or_else(|| format!("invalid number `{number}`"))
+            }
+            Some(c) => Err(format!("unexpected `{c}`")),
+            None => Err("unexpected end of expression".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<String> {
+        let mut context = Context::new();
+        context.set("a".into(), "4".into());
+        context.set("b".into(), " 1.5 ".into());
+        context.set("name".into(), "parrot".into());
+        evaluate(expr, &context)
+    }
+
+    #[test]
+    fn evaluate_arithmetic() {
+        assert_eq!(eval("${a}+1").unwrap(), "5");
+        assert_eq!(eval("(${a} - 1) * 2 + -${a}").unwrap(), "2");
+        assert_eq!(eval("${a} / 2").unwrap(), "2");
+        assert_eq!(eval("${a} / 8").unwrap(), "0.5");
+        assert_eq!(eval("${a} * ${b}").unwrap(), "6");
+        assert_eq!(eval("${b} + 1").unwrap(), "2.5");
+    }
+
+    #[test]
+    fn evaluate_errors() {
+        let err = eval("${a} / (2 - 2)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid expression \"${a} / (2 - 2)\": division by zero"
+        );
+
+        let err = eval("${name} + 1").unwrap_err();
+        assert!(err.to_string().ends_with("\"name\" is not a number: \"parrot\""));
+
+        assert!(eval("${missing} + 1").is_err());
+        assert!(eval("(${a} + 1").is_err());
+        assert!(eval("${a} +").is_err());
+        assert!(eval("${a} 1").is_err());
+        assert!(eval("{a} + 1").is_err());
+
+        let mut context = Context::new();
+        context.set("min".into(), i64::MIN.to_string());
+        let err = evaluate("${min} / -1", &context).unwrap_err();
+        assert!(err.to_string().ends_with("integer overflow"));
+        assert!(evaluate("${min} * -1", &context).is_err());
+        assert!(eval("9223372036854775807 + 1").is_err());
+        assert!(eval("-9223372036854775807 - 2").is_err());
+        assert!(eval("4611686018427387904 * 2").is_err());
+        assert!(eval("$a + 1").is_err());
+    }
+}
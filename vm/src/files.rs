@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub use parser::relative_to_script;
+
+/// Files read while compiling, so a file that is loaded again is only read
+/// again if it was modified in between.
+/// Reading and the modification time are given so they can be replaced in tests.
+pub struct Files {
+    read: fn(&Path) -> io::Result<String>,
+    modified: fn(&Path) -> Option<SystemTime>,
+    cache: HashMap<PathBuf, (SystemTime, String)>,
+}
+
+impl Files {
+    pub fn on_disk() -> Self {
+        Self::new(
+            |path| std::fs::read_to_string(path),
+            |path| path.metadata().and_then(|meta| meta.modified()).ok(),
+        )
+    }
+
+    pub fn new(read: fn(&Path) -> io::Result<String>, modified: fn(&Path) -> Option<SystemTime>) -> Self {
+        Self {
+            read,
+            modified,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn read(&mut self, path: &Path) -> io::Result<String> {
+        let modified = (self.modified)(path);
+
+        if let (Some(modified), Some((cached, content))) = (modified, self.cache.get(path))
+            && modified == *cached
+        {
+            return Ok(content.clone());
+        }
+
+        let content = (self.read)(path)?;
+
+        // Without a modification time there is no telling if the file changed
+        if let Some(modified) = modified {
+            self.cache.insert(path.into(), (modified, content.clone()));
+        }
+
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    static READS: AtomicUsize = AtomicUsize::new(0);
+    static MODIFIED: AtomicUsize = AtomicUsize::new(0);
+
+    fn read(path: &Path) -> io::Result<String> {
+        READS.fetch_add(1, Ordering::Relaxed);
+        Ok(path.display().to_string())
+    }
+
+    fn modified(_: &Path) -> Option<SystemTime> {
+        let secs = MODIFIED.load(Ordering::Relaxed) as u64;
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn read_unchanged_file_once() {
+        let mut files = Files::new(read, modified);
+
+        assert_eq!(files.read(Path::new("a.rs")).unwrap(), "a.rs");
+        assert_eq!(files.read(Path::new("a.rs")).unwrap(), "a.rs");
+        assert_eq!(READS.load(Ordering::Relaxed), 1);
+
+        files.read(Path::new("b.rs")).unwrap();
+        assert_eq!(READS.load(Ordering::Relaxed), 2);
+
+        // A modified file is read again
+        MODIFIED.store(1, Ordering::Relaxed);
+        files.read(Path::new("a.rs")).unwrap();
+        assert_eq!(READS.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn resolve_against_script() {
+        let script = Path::new("demos/intro.echo");
+        let path = relative_to_script(Path::new("a.echo"), Some(script));
+        assert_eq!(path, Path::new("demos/a.echo"));
+        assert_eq!(relative_to_script(Path::new("a.echo"), None), Path::new("a.echo"));
+    }
+}
@@ -1,32 +1,155 @@
+use std::path::PathBuf;
 use std::time::Duration;
 
 use anathema::geometry::{Pos, Size};
+use parser::OnError;
 
-#[derive(Debug)]
+use crate::theme::Theme;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct TypeOptions {
+    // Type the content from the last character to the first
+    pub reverse: bool,
+    // Expand tabs to the next multiple of this many columns
+    pub align: Option<u16>,
+    // Indent every line but the first by the indentation of the cursor line
+    pub reindent: bool,
+    // Type a word, and the punctuation and spaces after it, per frame
+    pub by_word: bool,
+    // Pause after punctuation and newlines
+    pub natural: bool,
+    // Pause at every newline for this long rather than for the `LinePause`
+    pub line_pause: Option<Duration>,
+    // Close brackets and quotes as they are typed, typing over the closers
+    pub autopair: bool,
+}
+
+/// What ends a `repeat until` loop
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// The buffer contains the text
+    BufferContains(String),
+}
+
+impl Condition {
+    pub fn holds(&self, buffer: &str) -> bool {
+        match self {
+            Condition::BufferContains(text) => buffer.contains(text.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     // Relative jump
     Jump(Pos),
     JumpToMarker(String),
+    // Absolute jump to the start of a row (zero based)
+    JumpToRow(usize),
+    // Jump a number of screen pages up (negative) or down (positive)
+    JumpPages(i32),
+    // Jump to the start of a capture group in the first match of the pattern
+    JumpToCapture { pattern: String, group: usize },
+    // Jump to a byte offset in the buffer
+    JumpToOffset(usize),
+    // Jump to where the cursor was once the most recent type buffer was typed
+    JumpToLastType,
+    // Continue from `end` if the condition holds, otherwise play the loop body that follows
+    Until { condition: Condition, end: usize },
+    // Continue playback from the instruction at this index
+    JumpToInstruction(usize),
+    // Scroll the viewport to put the cursor line in the middle
+    CenterCursor,
     Select(Size),
+    // Record the cursor position to select from
+    Anchor,
+    // Select from the anchor to the cursor
+    SelectToAnchor,
+    // Select a number of words starting with the word under the cursor
+    SelectWords(usize),
+    // Select whole rows (zero based), including the end
+    SelectLines { start: usize, end: usize },
+    // Select the rows from the start marker up to the end marker
+    SelectRegion { start: String, end: String },
+    // Save the current selection, moving it along with edits above it
+    PushSelection,
+    // Restore the most recently saved selection
+    PopSelection,
 
     // -----------------------------------------------------------------------------
     //   - Modifying instructions -
     // -----------------------------------------------------------------------------
     // * Require new highlighting
     // * If the `content` contains a newline then offset all the subsequent markers
-    LoadTypeBuffer(String),
+    LoadTypeBuffer(String, TypeOptions),
     // Inserts all the content at once, unlike Type which types the content out
     // character by character
     Insert(String),
+    // Inserts all the content at once at the cursor and moves the cursor to the
+    // end of the content
+    Paste(String),
+    // Type the lines appended to a file until the timeout is reached
+    Tail(PathBuf, Duration),
+    // Type a single character, then wait the duration regardless of the speed
+    Keystroke(char, Duration),
+    // Remove the character before the cursor
+    Backspace,
     // Remove all character in the highlighted range of the editor, or 
     // if no selection exists: remove the character under the cursor
     Delete,
+    // Remove the highlighted range, doing nothing if no selection exists.
+    // Typing and inserting replace the selection with this
+    DeleteSelection,
+    // Remove all text and move the cursor to the start, as if in a new file
+    Clear,
+    // Render the current state before moving on to the next instruction
+    Flush,
+    // Pause playback until resumed from the inspector
+    Breakpoint,
+    // Pause playback until any key is pressed
+    Pause,
+    // Copy the selection into a register
+    Copy(String),
+    // Paste the content of a register at the cursor
+    PasteRegister(String),
+    // Insert the strings before and after the selection, keeping the same text selected
+    Surround { left: String, right: String },
     Wait(Duration),
-    Speed(Duration),
+    // Wait one of the durations, picked at random by the ui
+    WaitOneOf(Vec<Duration>),
+    // Wait for a file to exist, erroring once the timeout is reached
+    WaitFile { path: PathBuf, timeout: Duration },
+    // The time between two frames, each randomly up to `jitter` longer or shorter
+    Speed { base: Duration, jitter: Duration },
     LinePause(Duration),
 
     FindInCurrentLine(String),
+    // Select the first occurrence of the text in the document, an error if there is none
+    SelectInDocument(String),
+    // Select the first occurrence of the text on the lines visible in the viewport, an error if there is none
+    SelectInViewport(String),
+    // Type the content in front of every occurrence of the needle at the same time
+    InsertEach { needle: String, content: String },
+    // Move the cursor `count` matches through the whole buffer, backwards if negative
+    Search { needle: String, count: i32 },
 
     SetTitle(String),
+    // Written to the terminal as is, bypassing the buffer
+    EchoRaw(String),
+    // A color scheme read from the path, mapped to the highlighting by the ui
+    LoadTheme(PathBuf, Theme),
     ShowLineNumbers(bool),
+
+    // How to handle instructions that fail during playback
+    OnError(OnError),
+}
+
+impl Instruction {
+    /// Only an instruction that works with files or the terminal can succeed when tried again
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Tail(..) | Instruction::LoadTheme(..) | Instruction::WaitFile { .. } | Instruction::EchoRaw(_)
+        )
+    }
 }
@@ -1,95 +1,1496 @@
 use std::time::Duration;
 
-use anathema::geometry::Size;
-use parser::{Dest, Source};
+use anathema::geometry::{Pos, Size};
+pub use parser::OnError;
+use parser::{Dest, Select, Source};
+use regex::Regex;
 use unicode_width::UnicodeWidthStr;
 
+pub use crate::autopair::{Autopair, Keystroke};
 pub use crate::context::Context;
+pub use crate::env::parse_env;
+pub use crate::error::Warning;
 use crate::error::{Error, Result};
-pub use crate::instructions::Instruction;
+pub use crate::estimate::{estimate, fit_duration, natural_pause};
+pub use crate::files::relative_to_script;
+pub use crate::instructions::{Condition, Instruction, TypeOptions};
+pub use crate::loops::{Loops, MAX_ITERATIONS};
+pub use crate::markers::marker_name;
+pub use crate::options::Options;
+pub use crate::registers::{DEFAULT_REGISTER, Registers};
+pub use crate::selection::{Selections, select_between, surround_positions};
+pub use crate::tail::Tail;
+#[cfg(any(test, feature = "test-utils"))]
+pub use crate::temp_dir::TempDir;
+pub use crate::text::{
+    align_tabs, center_offset, end_of, find_capture, find_in_rows, indent, offset_position, page_jump, search_step,
+    teleprompter_rows, word_extent, word_group,
+};
+pub use crate::theme::{Rgb, ScopeStyle, Theme};
+pub use crate::wait_file::WaitFile;
 
+mod autopair;
 mod context;
+mod env;
 mod error;
+mod estimate;
+mod expr;
+mod files;
 mod instructions;
+mod loops;
+mod markers;
+mod options;
+mod recording;
+mod registers;
+mod selection;
+mod tail;
+#[cfg(any(test, feature = "test-utils"))]
+mod temp_dir;
+mod text;
+mod theme;
+mod wait_file;
+
+/// The time between two frames unless changed with `speed`
+pub const DEFAULT_SPEED: Duration = Duration::from_millis(20);
+
+/// The ui makes every frame randomly longer by less than this, until a `speed` sets the jitter
+pub const RANDOM_DELAY: Duration = Duration::from_millis(20);
+
+/// The shortest time between two frames when fitting playback into a duration
+pub const MIN_SPEED: Duration = Duration::from_millis(1);
+
+/// How long `wait_file` waits for a file unless a timeout is given
+pub const WAIT_FILE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often `wait_file` checks if the file exists
+pub const WAIT_FILE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of characters of each value printed by `context_dump`
+const CONTEXT_DUMP_LEN: usize = 40;
+
+/// How long a scripted mistake is shown before it's corrected
+const CORRECTION_DELAY: Duration = Duration::from_millis(300);
+
+/// The number of frames `natural` typing pauses for after the end of a sentence
+const SENTENCE_PAUSE_FRAMES: u32 = 8;
+
+/// The number of frames `natural` typing pauses for after a comma, a semicolon or a newline
+const CLAUSE_PAUSE_FRAMES: u32 = 4;
+
+/// How long an autocomplete suggestion is shown before it's accepted
+const SUGGESTION_DELAY: Duration = Duration::from_millis(400);
+
+/// The number of times a failing instruction is retried with `on_error retry`
+pub const MAX_RETRIES: usize = 3;
 
 pub fn compile(parsed_instructions: parser::Instructions) -> Result<Vec<Instruction>> {
+    compile_with_options(parsed_instructions, &Options::default())
+}
+
+pub fn compile_with_options(parsed_instructions: parser::Instructions, options: &Options) -> Result<Vec<Instruction>> {
+    compile_with_warnings(parsed_instructions, options).map(|(instructions, _)| instructions)
+}
+
+/// Compile the instructions, along with warnings about anything that compiled
+/// but is probably not what the script meant, like instructions skipped by `on_error skip`
+pub fn compile_with_warnings(
+    parsed_instructions: parser::Instructions,
+    options: &Options,
+) -> Result<(Vec<Instruction>, Vec<Warning>)> {
     let mut context = Context::new();
+    for (key, value) in &options.variables {
+        context.set(key.clone(), value.clone());
+    }
+
     let mut instructions = vec![];
+    let mut warnings = vec![];
+    let mut on_error = OnError::Halt;
+    // The index in the script of every compiled instruction
+    let mut origins = vec![];
+
+    for (index, inst) in parsed_instructions.into_iter().enumerate() {
+        if let parser::Instruction::OnError(mode) = inst {
+            on_error = mode;
+        }
 
-    for inst in parsed_instructions {
-        match inst {
-            parser::Instruction::Load(path, key) => {
-                let content = std::fs::read_to_string(&path).map_err(|_| Error::Import(path))?;
-                context.set(key, content);
-            }
-            parser::Instruction::Find(needle) => instructions.push(Instruction::FindInCurrentLine(needle)),
-            parser::Instruction::Goto(dest) => {
-                let inst = match dest {
-                    Dest::Relative { row, col } => Instruction::Jump((col, row).into()),
-                    Dest::Marker(name) => Instruction::JumpToMarker(name),
-                };
-                instructions.push(inst);
-            }
-            parser::Instruction::Select { width, height } => {
-                instructions.push(Instruction::Select(Size::new(width, height)))
-            }
-            parser::Instruction::Delete => instructions.push(Instruction::Delete),
-            parser::Instruction::Type {
-                source,
-                trim_trailing_newline,
-                prefix_newline,
-            } => {
-                let mut content = match source {
-                    Source::Str(content) => content,
-                    Source::Ident(key) => context.load(key)?,
-                };
-
-                if trim_trailing_newline && content.ends_with('\n') {
-                    _ = content.pop();
+        let len = instructions.len();
+        let checkpoint = context.checkpoint();
+        let mut retries = 0;
+        while let Err(err) = compile_instruction(inst.clone(), &mut context, options, &mut instructions) {
+            // Nothing of a failed instruction is played back, e.g. half of an include
+            instructions.truncate(len);
+            context.roll_back(checkpoint);
+
+            // Variables only exist once loaded, so name the instruction that used it too early
+            let err = match err {
+                Error::Load(key) => Error::Undefined(key, index),
+                err => err,
+            };
+
+            match on_error {
+                OnError::Halt => return Err(err),
+                OnError::Skip => {
+                    warnings.push(Warning {
+                        instruction: index,
+                        message: format!("skipped: {err}"),
+                    });
+                    break;
                 }
+                OnError::Retry if retries < MAX_RETRIES && err.is_transient() => retries += 1,
+                OnError::Retry => return Err(err),
+            }
+        }
+        origins.resize(instructions.len(), index);
+    }
+
+    // Labels can be defined after the jump, so jumps are resolved last
+    for (index, dest) in context.resolve_jumps()? {
+        instructions[index] = Instruction::JumpToInstruction(dest);
+    }
+
+    if let Some(name) = markers::reserved_marker(&instructions) {
+        return Err(Error::ReservedMarker(name.into()));
+    }
 
-                if prefix_newline {
-                    instructions.push(Instruction::Insert("\n".into()));
+    for (index, name) in markers::forward_markers(&instructions) {
+        warnings.push(Warning {
+            instruction: origins[index],
+            message: format!("goto \"{name}\" before the marker is defined"),
+        });
+    }
+    warnings.sort_by_key(|warning| warning.instruction);
+
+    Ok((instructions, warnings))
+}
+
+fn compile_instruction(
+    inst: parser::Instruction,
+    context: &mut Context,
+    options: &Options,
+    instructions: &mut Vec<Instruction>,
+) -> Result<()> {
+    match inst {
+        parser::Instruction::Load(path, key) => {
+            let content = context.read_file(&path)?;
+            context.set(key, content);
+        }
+        parser::Instruction::Include(path) => {
+            let script = options.path.as_deref();
+            let path = context.include_path(&path, script);
+            let content = context.read_file(&path)?;
+            let prefixes = options.comment_prefixes.iter().map(String::as_str).collect::<Vec<_>>();
+            let included = parser::parse_with_comment_prefixes(&content, &prefixes)
+                .map_err(|err| Error::Parse(path.clone(), err.to_string()))?;
+
+            context.start_include(&path, script)?;
+            let result = included
+                .into_iter()
+                .try_for_each(|inst| compile_instruction(inst, context, options, instructions));
+            context.end_include();
+            result?;
+        }
+        parser::Instruction::OpenFile(path) => {
+            let path = context.include_path(&path, options.path.as_deref());
+            let content = context.read_file(&path)?;
+            instructions.push(Instruction::Clear);
+            instructions.push(Instruction::Insert(content));
+        }
+        parser::Instruction::Find(needle) => instructions.push(Instruction::FindInCurrentLine(needle)),
+        parser::Instruction::SearchNext(count) => {
+            let needle = last_search(instructions)?;
+            instructions.push(Instruction::Search {
+                needle,
+                count: count as i32,
+            });
+        }
+        parser::Instruction::SearchPrev(count) => {
+            let needle = last_search(instructions)?;
+            instructions.push(Instruction::Search {
+                needle,
+                count: -(count as i32),
+            });
+        }
+        parser::Instruction::Goto { dest, center } => {
+            let inst = match dest {
+                Dest::Relative { row, col } => Instruction::Jump((col, row).into()),
+                Dest::Marker(name) => Instruction::JumpToMarker(name),
+                Dest::Line(line) => Instruction::JumpToRow(line as usize - 1),
+                Dest::LineVariable(key) => Instruction::JumpToRow(context.load_line(key)? as usize - 1),
+                Dest::PageRelative(pages) => Instruction::JumpPages(pages),
+                Dest::Offset(offset) => Instruction::JumpToOffset(offset),
+                Dest::LastType => {
+                    let typed = |inst: &Instruction| matches!(inst, Instruction::LoadTypeBuffer(..));
+                    if !instructions.iter().any(typed) {
+                        return Err(Error::NoType);
+                    }
+                    Instruction::JumpToLastType
                 }
-                instructions.push(Instruction::LoadTypeBuffer(content));
-            }
-            parser::Instruction::Insert(source) => {
-                let inst = match source {
-                    Source::Str(content) => Instruction::Insert(content),
-                    Source::Ident(key) => {
-                        let content = context.load(key)?;
-                        Instruction::Insert(content)
+                Dest::Capture { pattern, group } => {
+                    let regex = Regex::new(&pattern).map_err(|err| Error::InvalidRegex(err.to_string()))?;
+                    if group >= regex.captures_len() {
+                        return Err(Error::MissingGroup(pattern, group));
                     }
-                };
-                instructions.push(inst);
+                    Instruction::JumpToCapture { pattern, group }
+                }
+            };
+            instructions.push(inst);
+
+            if center {
+                instructions.push(Instruction::CenterCursor);
+            }
+        }
+        parser::Instruction::Select(Select::Rect { width, height }) => {
+            instructions.push(Instruction::Select(Size::new(width, height)))
+        }
+        parser::Instruction::Select(Select::Region(name)) => {
+            let end = format!("{name}_end");
+            instructions.push(Instruction::SelectRegion { start: name, end });
+        }
+        parser::Instruction::Anchor => instructions.push(Instruction::Anchor),
+        parser::Instruction::Repeat { count, body } => {
+            if count as usize > MAX_ITERATIONS {
+                return Err(Error::RepeatCount(count));
+            }
+
+            for _ in 0..count {
+                compile_iteration(&body, context, options, instructions)?;
             }
-            parser::Instruction::Replace { src, replacement } => {
-                let width = src.width() as u16;
-                instructions.push(Instruction::FindInCurrentLine(src));
+        }
+        parser::Instruction::RepeatUntil {
+            condition: parser::Condition::VarEq(key, value),
+            body,
+        } => {
+            // Variables only change while compiling, so the block is copied until the condition holds
+            let mut iterations = 0;
+            while context.load(&key)? != value {
+                if iterations == MAX_ITERATIONS {
+                    return Err(Error::MaxIterations(MAX_ITERATIONS));
+                }
+                iterations += 1;
+                compile_iteration(&body, context, options, instructions)?;
+            }
+        }
+        parser::Instruction::RepeatUntil {
+            condition: parser::Condition::BufferContains(text),
+            body,
+        } => {
+            let condition = Condition::BufferContains(text);
+
+            // The end of the loop is only known once the body is compiled
+            let head = instructions.len();
+            instructions.push(Instruction::Until {
+                condition: condition.clone(),
+                end: head,
+            });
+
+            for inst in body {
+                compile_instruction(inst, context, options, instructions)?;
+            }
+
+            instructions.push(Instruction::JumpToInstruction(head));
+            let end = instructions.len();
+            instructions[head] = Instruction::Until { condition, end };
+        }
+        parser::Instruction::Label(label) => context.add_label(label, instructions.len())?,
+        parser::Instruction::JumpToLabel(label) => {
+            context.add_jump(instructions.len(), label);
+            instructions.push(Instruction::JumpToInstruction(instructions.len()));
+        }
+        parser::Instruction::SelectTo(dest) => {
+            if !instructions.iter().any(|inst| matches!(inst, Instruction::Anchor)) {
+                return Err(Error::NoAnchor);
+            }
+
+            // Selecting to `here` doesn't move the cursor
+            if !matches!(dest, Dest::Relative { row: 0, col: 0 }) {
+                let inst = parser::Instruction::Goto { dest, center: false };
+                compile_instruction(inst, context, options, instructions)?;
+            }
+            instructions.push(Instruction::SelectToAnchor);
+        }
+        parser::Instruction::SelectWords(count) => instructions.push(Instruction::SelectWords(count as usize)),
+        parser::Instruction::SelectLines { start, end } => instructions.push(Instruction::SelectLines {
+            start: start - 1,
+            end: end - 1,
+        }),
+        parser::Instruction::PushSelection => instructions.push(Instruction::PushSelection),
+        parser::Instruction::PopSelection => instructions.push(Instruction::PopSelection),
+        parser::Instruction::Delete => instructions.push(Instruction::Delete),
+        parser::Instruction::Clear => instructions.push(Instruction::Clear),
+        parser::Instruction::Backspace(count) => instructions.extend((0..count).map(|_| Instruction::Backspace)),
+        parser::Instruction::Flush => instructions.push(Instruction::Flush),
+        parser::Instruction::Breakpoint => instructions.push(Instruction::Breakpoint),
+        parser::Instruction::Pause => instructions.push(Instruction::Pause),
+        parser::Instruction::ContextDump => {
+            if options.verbose {
+                _ = context.dump(&mut std::io::stderr(), CONTEXT_DUMP_LEN);
+            }
+        }
+        parser::Instruction::AssertVar { key, value } => {
+            if options.validate {
+                let actual = context.load(&key)?;
+                if actual != value {
+                    return Err(Error::AssertVar(key, value, actual));
+                }
+            }
+        }
+        parser::Instruction::Set { key, value } => {
+            // Copied, so changing one variable later leaves the other as it is
+            let value = context.resolve(value)?;
+            context.set(key, value);
+        }
+        parser::Instruction::SetExpr { key, expr } => {
+            let value = expr::evaluate(&expr, context)?;
+            context.set(key, value);
+        }
+        parser::Instruction::Copy(register) => {
+            let register = register.unwrap_or_else(|| DEFAULT_REGISTER.into());
+            instructions.push(Instruction::Copy(register))
+        }
+        parser::Instruction::Paste(register) => {
+            let register = register.unwrap_or_else(|| DEFAULT_REGISTER.into());
+            instructions.push(Instruction::PasteRegister(register))
+        }
+        parser::Instruction::Type {
+            source,
+            trim_trailing_newline,
+            prefix_newline,
+            reverse,
+            align,
+            reindent,
+            complete,
+            by_word,
+            fix,
+            natural,
+            think,
+            autoindent,
+            line_pause,
+            autopair,
+            lines,
+        } => {
+            let mut content = match (source, lines) {
+                (Source::Ident(key), Some((start, end))) => {
+                    let content = context.load(&key)?;
+                    match text::line_range(&content, start, end) {
+                        Some(lines) => lines.to_string(),
+                        None => return Err(Error::LineRange(key, end, content.lines().count())),
+                    }
+                }
+                (source, _) => context.resolve(source)?,
+            };
+
+            let completion = complete.map(|complete| context.resolve(complete)).transpose()?;
+            let fix = fix.map(|fix| context.resolve(fix)).transpose()?;
+
+            if trim_trailing_newline && content.ends_with('\n') {
+                _ = content.pop();
+            }
+
+            if options.normalize_whitespace {
+                content = text::normalize_indentation(&content, options.tab_width);
+            }
+
+            if reindent {
+                content = text::dedent(&content);
+            }
+
+            if autoindent {
+                content = text::autoindent(&content, options.tab_width);
+            }
+
+            if think > 0 {
+                instructions.push(Instruction::Wait(Duration::from_millis(think)));
+            }
+
+            if options.replace_selection && has_selection(instructions) {
+                instructions.push(Instruction::DeleteSelection);
+            }
+
+            if prefix_newline {
+                instructions.push(Instruction::Insert("\n".into()));
+            }
+            let options = TypeOptions {
+                reverse,
+                align,
+                reindent,
+                by_word,
+                natural,
+                line_pause,
+                autopair,
+            };
+
+            // The number of characters to erase from the end of the text, and the correction to type instead
+            let correction = fix.map(|fix| {
+                let (erased, retype) = text::correction(&content, &fix);
+                (erased.chars().count(), erased.width(), retype.to_string())
+            });
+
+            // The width of the last line once its tabs are aligned. Only the first line starts at
+            // the cursor column, which isn't known until playback, so it's aligned from column zero
+            let last_line = content.rsplit('\n').next().unwrap_or_default();
+            let mut width = match align {
+                Some(tab_width) => text::align_tabs(last_line, 0, tab_width).width(),
+                None => last_line.width(),
+            };
+            instructions.push(Instruction::LoadTypeBuffer(content, options));
+
+            if let Some((erase, erased_width, retype)) = correction {
+                width = width.saturating_sub(erased_width) + retype.width();
+                instructions.push(Instruction::Wait(CORRECTION_DELAY));
+                instructions.extend((0..erase).map(|_| Instruction::Backspace));
+                instructions.push(Instruction::LoadTypeBuffer(retype, TypeOptions::default()));
+            }
+
+            // Select the typed text as the suggestion, then replace it with the completion
+            let width = width as u16;
+            if let Some(completion) = completion {
+                instructions.push(Instruction::Jump(Pos::new(-(width as i32), 0)));
                 instructions.push(Instruction::Select(Size::new(width, 1)));
+                instructions.push(Instruction::Wait(SUGGESTION_DELAY));
                 instructions.push(Instruction::Delete);
-                let inst = match replacement {
-                    Source::Str(content) => Instruction::LoadTypeBuffer(content),
-                    Source::Ident(key) => {
-                        let content = context.load(key).unwrap();
-                        Instruction::LoadTypeBuffer(content)
-                    }
-                };
-                instructions.push(inst);
+                instructions.push(Instruction::Paste(completion));
             }
-            parser::Instruction::Wait(seconds) => instructions.push(Instruction::Wait(Duration::from_secs(seconds))),
-            parser::Instruction::Speed(millis) => instructions.push(Instruction::Speed(Duration::from_millis(millis))),
-            parser::Instruction::LinePause(millis) => {
-                instructions.push(Instruction::LinePause(Duration::from_millis(millis)))
+        }
+        parser::Instruction::Surround { left, right } => {
+            if let Some(delimiter) = [&left, &right].into_iter().find(|d| d.contains('\n')) {
+                return Err(Error::MultilineDelimiter(delimiter.clone()));
+            }
+            instructions.push(Instruction::Surround { left, right });
+        }
+        parser::Instruction::Tail { path, timeout } => {
+            instructions.push(Instruction::Tail(path, Duration::from_secs(timeout)))
+        }
+        parser::Instruction::Insert(source) => {
+            let mut content = context.resolve(source)?;
+
+            if options.normalize_whitespace {
+                content = text::normalize_indentation(&content, options.tab_width);
+            }
+
+            if options.replace_selection && has_selection(instructions) {
+                instructions.push(Instruction::DeleteSelection);
+            }
+
+            instructions.push(Instruction::Insert(content));
+        }
+        parser::Instruction::InsertEach { needle, source } => {
+            let content = context.resolve(source)?;
+            instructions.push(Instruction::InsertEach { needle, content });
+        }
+        parser::Instruction::Replace {
+            src,
+            replacement,
+            capture,
+            viewport,
+        } => {
+            let content = context.resolve(replacement)?;
+
+            // The text is found by an exact match, and playback fails if it isn't found,
+            // so the replaced text is the source
+            if let Some(key) = capture {
+                context.set(key, src.clone());
+            }
+
+            match viewport {
+                true => instructions.push(Instruction::SelectInViewport(src)),
+                false => instructions.push(Instruction::SelectInDocument(src)),
             }
-            parser::Instruction::SetTitle(title) => instructions.push(Instruction::SetTitle(title)),
-            parser::Instruction::ShowLineNumbers(show) => instructions.push(Instruction::ShowLineNumbers(show)),
+            instructions.push(Instruction::Delete);
+            instructions.push(Instruction::LoadTypeBuffer(content, TypeOptions::default()));
         }
+        parser::Instruction::Wait(duration) => instructions.push(Instruction::Wait(duration)),
+        parser::Instruction::WaitOneOf(choices) => instructions.push(Instruction::WaitOneOf(choices)),
+        parser::Instruction::WaitFile { path, timeout } => {
+            let timeout = timeout.map(Duration::from_secs).unwrap_or(WAIT_FILE_TIMEOUT);
+            instructions.push(Instruction::WaitFile { path, timeout })
+        }
+        parser::Instruction::Speed { base, jitter } => instructions.push(Instruction::Speed {
+            base: Duration::from_millis(base),
+            jitter: Duration::from_millis(jitter),
+        }),
+        parser::Instruction::LinePause(duration) => instructions.push(Instruction::LinePause(duration)),
+        parser::Instruction::SetTitle(title) => instructions.push(Instruction::SetTitle(title)),
+        parser::Instruction::EchoRaw(_) if options.safe => return Err(Error::Unsafe("echo_raw".into())),
+        parser::Instruction::EchoRaw(text) => instructions.push(Instruction::EchoRaw(text)),
+        parser::Instruction::ThemeLoad(path) => {
+            let content = context.read_file(&path)?;
+            let theme = theme::parse_theme(&content).map_err(|reason| Error::Theme(path.clone(), reason))?;
+            instructions.push(Instruction::LoadTheme(path, theme));
+        }
+        parser::Instruction::TypeRecording(path) => {
+            let content = context.read_file(&path)?;
+            let keys = recording::parse_recording(&content).map_err(|reason| Error::Recording(path, reason))?;
+            let keys = keys.into_iter().map(|(key, delay)| Instruction::Keystroke(key, delay));
+            instructions.extend(keys);
+        }
+        parser::Instruction::ShowLineNumbers(show) => instructions.push(Instruction::ShowLineNumbers(show)),
+        parser::Instruction::OnError(mode) => instructions.push(Instruction::OnError(mode)),
     }
 
-    Ok(instructions)
+    Ok(())
+}
+
+// A selection is active until it's deleted, copied or cleared.
+// Jumps and failed selections aren't followed, so the selection can be gone when
+// the instructions are played back, which is why replacing it uses `DeleteSelection`
+fn has_selection(instructions: &[Instruction]) -> bool {
+    let last = instructions.iter().rev().find(|inst| {
+        matches!(
+            inst,
+            Instruction::Select(_)
+                | Instruction::SelectToAnchor
+                | Instruction::SelectWords(_)
+                | Instruction::SelectInDocument(_)
+                | Instruction::SelectInViewport(_)
+                | Instruction::SelectLines { .. }
+                | Instruction::SelectRegion { .. }
+                | Instruction::PopSelection
+                | Instruction::Delete
+                | Instruction::DeleteSelection
+                | Instruction::Copy(_)
+                | Instruction::Clear
+        )
+    });
+
+    !matches!(
+        last,
+        None | Some(Instruction::Delete | Instruction::DeleteSelection | Instruction::Copy(_) | Instruction::Clear)
+    )
+}
+
+// One copy of the block of a loop, with its own labels
+fn compile_iteration(
+    body: &[parser::Instruction],
+    context: &mut Context,
+    options: &Options,
+    instructions: &mut Vec<Instruction>,
+) -> Result<()> {
+    context.start_iteration();
+    let result = body
+        .iter()
+        .cloned()
+        .try_for_each(|inst| compile_instruction(inst, context, options, instructions));
+    context.end_iteration();
+    result
+}
+
+// The needle of the most recent search in the current line
+fn last_search(instructions: &[Instruction]) -> Result<String> {
+    instructions
+        .iter()
+        .rev()
+        .find_map(|inst| match inst {
+            Instruction::FindInCurrentLine(needle)
+            | Instruction::SelectInDocument(needle)
+            | Instruction::SelectInViewport(needle) => Some(needle.clone()),
+            _ => None,
+        })
+        .ok_or(Error::NoSearch)
 }
 
 #[cfg(test)]
 mod test {
+    use super::*;
+
+    fn compile_str(input: &str) -> Vec<Instruction> {
+        let instructions = parser::parse(input).unwrap();
+        compile(instructions).unwrap()
+    }
+
+    #[test]
+    fn compile_type_reverse() {
+        let output = compile_str("type \"abc\" reverse");
+        let options = TypeOptions { reverse: true, ..Default::default() };
+        let expected = vec![Instruction::LoadTypeBuffer("abc".into(), options)];
+        assert_eq!(output, expected);
+
+        let output = compile_str("type \"abc\"");
+        let expected = vec![Instruction::LoadTypeBuffer("abc".into(), TypeOptions::default())];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_type_align() {
+        let output = compile_str("type \"a\tb\" align 4");
+        let options = TypeOptions { align: Some(4), ..Default::default() };
+        let expected = vec![Instruction::LoadTypeBuffer("a\tb".into(), options)];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_type_reindent() {
+        let output = compile_str("type \"        if a {\n            b\n        }\" reindent");
+        let options = TypeOptions { reindent: true, ..Default::default() };
+        let expected = vec![Instruction::LoadTypeBuffer("if a {\n    b\n}".into(), options)];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_type_complete() {
+        let output = compile_str("type \"prin\" complete \"println!()\"");
+        let expected = vec![
+            Instruction::LoadTypeBuffer("prin".into(), TypeOptions::default()),
+            Instruction::Jump(Pos::new(-4, 0)),
+            Instruction::Select(Size::new(4, 1)),
+            Instruction::Wait(SUGGESTION_DELAY),
+            Instruction::Delete,
+            Instruction::Paste("println!()".into()),
+        ];
+        assert_eq!(output, expected);
+
+        // The suggestion is selected as wide as it is once its tabs are aligned
+        let output = compile_str("type \"a\\nb\\tc\" align 4 complete \"b = c\"");
+        assert_eq!(output[1], Instruction::Jump(Pos::new(-5, 0)));
+        assert_eq!(output[2], Instruction::Select(Size::new(5, 1)));
+    }
+
+    #[test]
+    fn compile_normalized_whitespace() {
+        let options = Options {
+            normalize_whitespace: true,
+            ..Default::default()
+        };
+        let instructions = parser::parse("type \"fn a() {\n\tb();\n}\"\ninsert \"\t\tc\"").unwrap();
+        let output = compile_with_options(instructions, &options).unwrap();
+        let expected = vec![
+            Instruction::LoadTypeBuffer("fn a() {\n    b();\n}".into(), TypeOptions::default()),
+            Instruction::Insert("        c".into()),
+        ];
+        assert_eq!(output, expected);
+
+        let output = compile_str("insert \"\tc\"");
+        assert_eq!(output, vec![Instruction::Insert("\tc".into())]);
+    }
+
+    #[test]
+    fn compile_type_by_word() {
+        let output = compile_str("type \"a b\" by word");
+        let options = TypeOptions {
+            by_word: true,
+            ..Default::default()
+        };
+        assert_eq!(output, vec![Instruction::LoadTypeBuffer("a b".into(), options)]);
+    }
+
+    #[test]
+    fn compile_type_natural() {
+        let output = compile_str("type \"a. b\" natural");
+        let options = TypeOptions {
+            natural: true,
+            ..Default::default()
+        };
+        assert_eq!(output, vec![Instruction::LoadTypeBuffer("a. b".into(), options)]);
+    }
+
+    #[test]
+    fn compile_type_line_pause() {
+        let output = compile_str("linepause 1s\ntype \"a\nb\" line_pause 300ms");
+        let options = TypeOptions {
+            line_pause: Some(Duration::from_millis(300)),
+            ..Default::default()
+        };
+        let expected = vec![
+            Instruction::LinePause(Duration::from_secs(1)),
+            Instruction::LoadTypeBuffer("a\nb".into(), options),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_type_interpolation() {
+        let output = compile_str("set name \"parrot\"\ntype = \"Hello ${name}, $$5\"");
+        let expected = Instruction::LoadTypeBuffer("Hello parrot, $5".into(), TypeOptions::default());
+        assert_eq!(output, vec![expected]);
+
+        // Only a string after `=` is interpolated
+        let output = compile_str("set name \"parrot\"\ninsert \"${name} $$\"");
+        assert_eq!(output, vec![Instruction::Insert("${name} $$".into())]);
+
+        let err = compile(parser::parse("type = \"Hello ${name}\"").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "instruction 0 uses \"name\" before it is loaded");
+    }
+
+    #[test]
+    fn compile_type_line_range() {
+        let options = Options {
+            variables: vec![("foo".into(), "a\nb\nc\nd".into())],
+            ..Default::default()
+        };
+        let compile_code = |code| compile_with_options(parser::parse(code).unwrap(), &options);
+
+        let output = compile_code("type foo 2..3").unwrap();
+        let expected = Instruction::LoadTypeBuffer("b\nc\n".into(), TypeOptions::default());
+        assert_eq!(output, vec![expected]);
+
+        // The last line has no newline to keep
+        let output = compile_code("type foo 4..4").unwrap();
+        let expected = Instruction::LoadTypeBuffer("d".into(), TypeOptions::default());
+        assert_eq!(output, vec![expected]);
+
+        let err = compile_code("type foo 3..5").unwrap_err();
+        assert_eq!(err.to_string(), "\"foo\" has 4 lines, there is no line 5");
+    }
+
+    #[test]
+    fn compile_type_autopair() {
+        let output = compile_str("type \"f(a)\" autopair");
+        let options = TypeOptions {
+            autopair: true,
+            ..Default::default()
+        };
+        assert_eq!(output, vec![Instruction::LoadTypeBuffer("f(a)".into(), options)]);
+    }
+
+    #[test]
+    fn compile_type_think() {
+        let output = compile_str("type \"ab\" think 800ms");
+        let expected = vec![
+            Instruction::Wait(Duration::from_millis(800)),
+            Instruction::LoadTypeBuffer("ab".into(), TypeOptions::default()),
+        ];
+        assert_eq!(output, expected);
+
+        // 800ms of thinking, one frame to load the buffer and one for each character,
+        // each with the random delay of 9.5ms on average
+        let duration = estimate(&output, Duration::from_millis(10));
+        assert_eq!(duration, Duration::from_micros(858_500));
+    }
+
+    #[test]
+    fn compile_type_fix() {
+        let output = compile_str("type \"fn mian\" fix \"main\"");
+        let expected = vec![
+            Instruction::LoadTypeBuffer("fn mian".into(), TypeOptions::default()),
+            Instruction::Wait(CORRECTION_DELAY),
+            Instruction::Backspace,
+            Instruction::Backspace,
+            Instruction::Backspace,
+            Instruction::LoadTypeBuffer("ain".into(), TypeOptions::default()),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_insert_each() {
+        let output = compile_str("insert_each \"fn\" \"pub \"");
+        let expected = vec![Instruction::InsertEach {
+            needle: "fn".into(),
+            content: "pub ".into(),
+        }];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_search_next_and_prev() {
+        let output = compile_str("find \"fn\"\nsearch_next 3\nsearch_prev");
+        let expected = vec![
+            Instruction::FindInCurrentLine("fn".into()),
+            Instruction::Search {
+                needle: "fn".into(),
+                count: 3,
+            },
+            Instruction::Search {
+                needle: "fn".into(),
+                count: -1,
+            },
+        ];
+        assert_eq!(output, expected);
+
+        let instructions = parser::parse("search_next").unwrap();
+        let err = compile(instructions).unwrap_err();
+        assert_eq!(err.to_string(), "search_next and search_prev require a previous find");
+    }
+
+    // Play inserts, loops and jumps against a buffer the way the ui does
+    fn play(instructions: &[Instruction]) -> Result<String> {
+        let mut buffer = String::new();
+        let mut loops = Loops::new();
+        let mut index = 0;
+
+        while let Some(inst) = instructions.get(index) {
+            index += 1;
+            match inst {
+                Instruction::Insert(content) => buffer.push_str(content),
+                Instruction::Until { condition, end } if loops.done(*end, condition.holds(&buffer))? => index = *end,
+                Instruction::JumpToInstruction(dest) => index = *dest,
+                _ => {}
+            }
+        }
+
+        Ok(buffer)
+    }
+
+    #[test]
+    fn compile_repeat() {
+        let output = compile_str("repeat 2 {\n    insert \"a\"\n    repeat 2 { wait 1 }\n}");
+        let wait = Instruction::Wait(Duration::from_secs(1));
+        let expected = vec![
+            Instruction::Insert("a".into()),
+            wait.clone(),
+            wait.clone(),
+            Instruction::Insert("a".into()),
+            wait.clone(),
+            wait,
+        ];
+        assert_eq!(output, expected);
+
+        assert!(compile_str("repeat 0 { wait 1 }").is_empty());
+        assert_eq!(compile_str("repeat 1000 { wait 1 }").len(), MAX_ITERATIONS);
+
+        let err = compile(parser::parse("repeat 4294967295 { wait 1 }").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "repeat 4294967295 exceeds 1000 iterations");
+    }
+
+    #[test]
+    fn compile_labels_in_repeat() {
+        // Every iteration jumps to its own label
+        let output = compile_str("label start\nrepeat 2 {\n    jump a\n    label a\n    wait 1\n}\njump start");
+        let expected = vec![
+            Instruction::JumpToInstruction(1),
+            Instruction::Wait(Duration::from_secs(1)),
+            Instruction::JumpToInstruction(3),
+            Instruction::Wait(Duration::from_secs(1)),
+            Instruction::JumpToInstruction(0),
+        ];
+        assert_eq!(output, expected);
+
+        // The label only exists inside the block
+        let err = compile(parser::parse("repeat 2 { label a }\njump a").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "jump to undefined label \"a\"");
+    }
+
+    #[test]
+    fn repeat_until_condition_is_met() {
+        let output = compile_str("repeat until buffer_contains \"aaa\" {\n    insert \"a\"\n}\ninsert \"b\"");
+        let expected = vec![
+            Instruction::Until {
+                condition: Condition::BufferContains("aaa".into()),
+                end: 3,
+            },
+            Instruction::Insert("a".into()),
+            Instruction::JumpToInstruction(0),
+            Instruction::Insert("b".into()),
+        ];
+        assert_eq!(output, expected);
+        assert_eq!(play(&output).unwrap(), "aaab");
+
+        let output = compile_str("repeat until buffer_contains \"b\" { insert \"a\" }");
+        let err = play(&output).unwrap_err();
+        assert_eq!(err.to_string(), "repeat until exceeded 1000 iterations");
+    }
+
+    #[test]
+    fn repeat_until_variable_is_equal() {
+        // The condition is checked again after every copy of the block
+        let code = "set i = \"0\"\nrepeat until var_eq i \"3\" {\n    insert \"a\"\n    set i = \"${i} + 1\"\n}";
+        let output = compile_str(code);
+        assert_eq!(output, vec![Instruction::Insert("a".into()); 3]);
+
+        assert!(compile_str("set i = \"3\"\nrepeat until var_eq i \"3\" { insert \"a\" }").is_empty());
+
+        let instructions = parser::parse("set i = \"0\"\nrepeat until var_eq i \"3\" { insert \"a\" }").unwrap();
+        let err = compile(instructions).unwrap_err();
+        assert_eq!(err.to_string(), "repeat until exceeded 1000 iterations");
+    }
+
+    #[test]
+    fn compile_jumps_to_labels() {
+        let output = compile_str("label start\nwait 1\njump end\nwait 2\njump start\nlabel end\nwait 3");
+        let expected = vec![
+            Instruction::Wait(Duration::from_secs(1)),
+            Instruction::JumpToInstruction(4),
+            Instruction::Wait(Duration::from_secs(2)),
+            Instruction::JumpToInstruction(0),
+            Instruction::Wait(Duration::from_secs(3)),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_jump_to_included_label() {
+        let dir = TempDir::new("parrot-compile-jump-to-included-label");
+        std::fs::write(dir.join("intro.echo"), "wait 1\nlabel outro\nwait 2").unwrap();
+
+        let options = Options {
+            path: Some(dir.join("talk.echo")),
+            ..Default::default()
+        };
+        let instructions = parser::parse("jump outro\ninclude \"intro.echo\"").unwrap();
+        let output = compile_with_options(instructions, &options).unwrap();
+        let expected = vec![
+            Instruction::JumpToInstruction(2),
+            Instruction::Wait(Duration::from_secs(1)),
+            Instruction::Wait(Duration::from_secs(2)),
+        ];
+        assert_eq!(output, expected);
+
+        // The label is defined once, by the included script
+        let instructions = parser::parse("label outro\ninclude \"intro.echo\"").unwrap();
+        let err = compile_with_options(instructions, &options).unwrap_err();
+        assert_eq!(err.to_string(), "label \"outro\" is already defined");
+    }
+
+    #[test]
+    fn typing_replaces_selection() {
+        let output = compile_str("select word\nwait 1\ntype \"b\"\ntype \"c\"");
+        let expected = vec![
+            Instruction::SelectWords(1),
+            Instruction::Wait(Duration::from_secs(1)),
+            Instruction::DeleteSelection,
+            Instruction::LoadTypeBuffer("b".into(), TypeOptions::default()),
+            Instruction::LoadTypeBuffer("c".into(), TypeOptions::default()),
+        ];
+        assert_eq!(output, expected);
+
+        // A copied selection is no longer selected
+        let output = compile_str("select 3 1\ncopy\ninsert \"b\"");
+        let expected = vec![
+            Instruction::Select(Size::new(3, 1)),
+            Instruction::Copy(DEFAULT_REGISTER.into()),
+            Instruction::Insert("b".into()),
+        ];
+        assert_eq!(output, expected);
+
+        let options = Options {
+            replace_selection: false,
+            ..Default::default()
+        };
+        let instructions = parser::parse("select word\ntype \"b\"").unwrap();
+        let output = compile_with_options(instructions, &options).unwrap();
+        let expected = vec![
+            Instruction::SelectWords(1),
+            Instruction::LoadTypeBuffer("b".into(), TypeOptions::default()),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_select_words() {
+        let output = compile_str("select word +2");
+        assert_eq!(output, vec![Instruction::SelectWords(3)]);
+    }
+
+    #[test]
+    fn compile_goto_line() {
+        let output = compile_str("goto line 3");
+        assert_eq!(output, vec![Instruction::JumpToRow(2)]);
+
+        let mut context = Context::new();
+        let mut output = vec![];
+        context.set("pos".into(), "10".into());
+        let inst = parser::Instruction::Goto {
+            dest: Dest::LineVariable("pos".into()),
+            center: false,
+        };
+        compile_instruction(inst.clone(), &mut context, &Options::default(), &mut output).unwrap();
+        assert_eq!(output, vec![Instruction::JumpToRow(9)]);
+
+        context.set("pos".into(), "ten".into());
+        assert!(compile_instruction(inst, &mut context, &Options::default(), &mut output).is_err());
+    }
+
+    #[test]
+    fn compile_goto_offset() {
+        let output = compile_str("goto offset 1234");
+        assert_eq!(output, vec![Instruction::JumpToOffset(1234)]);
+    }
+
+    #[test]
+    fn compile_goto_capture() {
+        let output = compile_str("goto capture /let (\\w+)/ 1");
+        let expected = Instruction::JumpToCapture {
+            pattern: "let (\\w+)".into(),
+            group: 1,
+        };
+        assert_eq!(output, vec![expected]);
+
+        let instructions = parser::parse("goto capture /let (\\w+)/ 2").unwrap();
+        let err = compile(instructions).unwrap_err();
+        assert_eq!(err.to_string(), "/let (\\w+)/ has no group 2");
+
+        let instructions = parser::parse("goto capture /let (\\w+/ 1").unwrap();
+        assert!(compile(instructions).is_err());
+    }
+
+    #[test]
+    fn compile_goto_last_type() {
+        let output = compile_str("type \"fn a() {}\"\ngoto line 1\ngoto last_type");
+        let expected = vec![
+            Instruction::LoadTypeBuffer("fn a() {}".into(), TypeOptions::default()),
+            Instruction::JumpToRow(0),
+            Instruction::JumpToLastType,
+        ];
+        assert_eq!(output, expected);
+
+        let instructions = parser::parse("insert \"a\"\ngoto last_type").unwrap();
+        let err = compile(instructions).unwrap_err();
+        assert_eq!(err.to_string(), "goto last_type requires a previous type");
+    }
+
+    #[test]
+    fn compile_goto_center() {
+        let output = compile_str("goto line 50 center");
+        assert_eq!(output, vec![Instruction::JumpToRow(49), Instruction::CenterCursor]);
+    }
+
+    #[test]
+    fn compile_goto_page() {
+        let output = compile_str("goto page -1");
+        assert_eq!(output, vec![Instruction::JumpPages(-1)]);
+    }
+
+    #[test]
+    fn compile_anchor_and_select_to() {
+        let output = compile_str("anchor\ngoto 2 3\nselect to here\nselect to 1 0\nselect to end");
+        let expected = vec![
+            Instruction::Anchor,
+            Instruction::Jump(Pos::new(3, 2)),
+            Instruction::SelectToAnchor,
+            Instruction::Jump(Pos::new(0, 1)),
+            Instruction::SelectToAnchor,
+            Instruction::JumpToMarker("end".into()),
+            Instruction::SelectToAnchor,
+        ];
+        assert_eq!(output, expected);
+
+        let instructions = parser::parse("goto 2 3\nselect to here").unwrap();
+        assert!(compile(instructions).is_err());
+    }
+
+    #[test]
+    fn compile_select_lines() {
+        let output = compile_str("select lines 10..15");
+        assert_eq!(output, vec![Instruction::SelectLines { start: 9, end: 14 }]);
+    }
+
+    #[test]
+    fn compile_select_region() {
+        // Typing replaces the selected region
+        let output = compile_str("select main\ntype \"a\"");
+        let expected = vec![
+            Instruction::SelectRegion {
+                start: "main".into(),
+                end: "main_end".into(),
+            },
+            Instruction::DeleteSelection,
+            Instruction::LoadTypeBuffer("a".into(), TypeOptions::default()),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_push_and_pop_selection() {
+        // The restored selection is replaced by typing
+        let output = compile_str("select word\npush_selection\nselect lines 1..1\ndelete\npop_selection\ntype \"a\"");
+        let expected = vec![
+            Instruction::SelectWords(1),
+            Instruction::PushSelection,
+            Instruction::SelectLines { start: 0, end: 0 },
+            Instruction::Delete,
+            Instruction::PopSelection,
+            Instruction::DeleteSelection,
+            Instruction::LoadTypeBuffer("a".into(), TypeOptions::default()),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_replace_viewport() {
+        let output = compile_str("replace viewport \"old\" \"new\"");
+        let expected = vec![
+            Instruction::SelectInViewport("old".into()),
+            Instruction::Delete,
+            Instruction::LoadTypeBuffer("new".into(), TypeOptions::default()),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_replace_capture() {
+        let output = compile_str("replace \"old\" \"new\" as prev\ntype prev");
+        let expected = vec![
+            Instruction::SelectInDocument("old".into()),
+            Instruction::Delete,
+            Instruction::LoadTypeBuffer("new".into(), TypeOptions::default()),
+            Instruction::LoadTypeBuffer("old".into(), TypeOptions::default()),
+        ];
+        assert_eq!(output, expected);
+
+        let instructions = parser::parse("replace \"old\" missing as prev\ntype prev").unwrap();
+        assert!(compile(instructions).is_err());
+    }
+
+    #[test]
+    fn compile_wait_file() {
+        let output = compile_str("wait_file \"target/done\"\nwait_file \"target/done\" 5");
+        let expected = vec![
+            Instruction::WaitFile {
+                path: "target/done".into(),
+                timeout: WAIT_FILE_TIMEOUT,
+            },
+            Instruction::WaitFile {
+                path: "target/done".into(),
+                timeout: Duration::from_secs(5),
+            },
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_flush() {
+        let output = compile_str("flush\nwait 1");
+        let expected = vec![Instruction::Flush, Instruction::Wait(Duration::from_secs(1))];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_copy_and_paste() {
+        let output = compile_str("copy\ncopy to a\npaste a\npaste");
+        let expected = vec![
+            Instruction::Copy(DEFAULT_REGISTER.into()),
+            Instruction::Copy("a".into()),
+            Instruction::PasteRegister("a".into()),
+            Instruction::PasteRegister(DEFAULT_REGISTER.into()),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_surround() {
+        let output = compile_str("select word\nsurround \"(\" \")\"");
+        let expected = vec![
+            Instruction::SelectWords(1),
+            Instruction::Surround {
+                left: "(".into(),
+                right: ")".into(),
+            },
+        ];
+        assert_eq!(output, expected);
+
+        let err = compile(parser::parse("select 2 1\nsurround \"{\\n\" \"}\"").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "surround \"{\\n\" contains a newline");
+    }
+
+    #[test]
+    fn compile_include() {
+        let dir = TempDir::new("parrot-compile-include");
+        std::fs::create_dir_all(dir.join("intro")).unwrap();
+        let intro = "# shared intro\nwait 1\ninclude \"outro.echo\"";
+        std::fs::write(dir.join("intro/intro.echo"), intro).unwrap();
+        std::fs::write(dir.join("intro/outro.echo"), "wait 2").unwrap();
+
+        let options = Options {
+            path: Some(dir.join("talk.echo")),
+            comment_prefixes: vec!["#".into()],
+            ..Default::default()
+        };
+        let instructions = parser::parse("include \"intro/intro.echo\"\nwait 3").unwrap();
+        let output = compile_with_options(instructions, &options).unwrap();
+        let expected = vec![
+            Instruction::Wait(Duration::from_secs(1)),
+            Instruction::Wait(Duration::from_secs(2)),
+            Instruction::Wait(Duration::from_secs(3)),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_edit() {
+        let dir = TempDir::new("parrot-compile-edit");
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/main.rs"), "fn main() {\n}\n").unwrap();
+
+        let options = Options {
+            path: Some(dir.join("talk.echo")),
+            ..Default::default()
+        };
+        let instructions = parser::parse("type \"old\"\nedit \"src/main.rs\"").unwrap();
+        let output = compile_with_options(instructions, &options).unwrap();
+        let expected = vec![
+            Instruction::LoadTypeBuffer("old".into(), TypeOptions::default()),
+            Instruction::Clear,
+            Instruction::Insert("fn main() {\n}\n".into()),
+        ];
+        assert_eq!(output, expected);
+
+        let instructions = parser::parse("edit \"src/missing.rs\"").unwrap();
+        assert!(compile_with_options(instructions, &options).is_err());
+    }
+
+    #[test]
+    fn compile_include_cycle() {
+        let dir = TempDir::new("parrot-compile-include-cycle");
+        std::fs::write(dir.join("talk.echo"), "wait 1\ninclude \"talk.echo\"").unwrap();
+
+        let options = Options {
+            path: Some(dir.join("talk.echo")),
+            ..Default::default()
+        };
+        let instructions = parser::parse("wait 1\ninclude \"talk.echo\"").unwrap();
+        let err = compile_with_options(instructions, &options).unwrap_err();
+        assert!(err.to_string().ends_with("talk.echo\" includes itself"));
+    }
+
+    #[test]
+    fn compile_type_recording() {
+        let dir = TempDir::new("parrot-compile-type-recording");
+        let path = dir.join("recording.json");
+        std::fs::write(&path, r#"[["f", 120], ["n", 85], ["\n", 0]]"#).unwrap();
+
+        let output = compile_str(&format!("type_recording \"{}\"", path.display()));
+        let expected = vec![
+            Instruction::Keystroke('f', Duration::from_millis(120)),
+            Instruction::Keystroke('n', Duration::from_millis(85)),
+            Instruction::Keystroke('\n', Duration::ZERO),
+        ];
+        assert_eq!(output, expected);
+
+        // The recorded delays are kept whatever the speed
+        for frame_time in [DEFAULT_SPEED, Duration::from_millis(500)] {
+            assert_eq!(estimate(&output, frame_time), Duration::from_millis(205));
+        }
+
+        std::fs::write(&path, r#"[["fn", 120]]"#).unwrap();
+        let instructions = parser::parse(&format!("type_recording \"{}\"", path.display())).unwrap();
+        let err = compile(instructions).unwrap_err();
+        assert!(err.to_string().ends_with("a key is a single character, found \"fn\""));
+    }
+
+    #[test]
+    fn compile_echo_raw() {
+        let output = compile_str("echo_raw \"\\x1b[2J\"");
+        assert_eq!(output, vec![Instruction::EchoRaw("\x1b[2J".into())]);
+
+        let options = Options {
+            safe: true,
+            ..Default::default()
+        };
+        let instructions = parser::parse("echo_raw \"\\x1b[2J\"").unwrap();
+        let err = compile_with_options(instructions, &options).unwrap_err();
+        assert_eq!(err.to_string(), "echo_raw is not allowed in safe mode");
+    }
+
+    #[test]
+    fn compile_theme_load() {
+        let dir = TempDir::new("parrot-compile-theme-load");
+        let path = dir.join("theme.toml");
+        std::fs::write(&path, "background = \"#282828\"\n[scopes]\ncomment = { italic = true }").unwrap();
+
+        let output = compile_str(&format!("theme load \"{}\"", path.display()));
+        let comment = ScopeStyle {
+            italic: true,
+            ..Default::default()
+        };
+        let theme = Theme {
+            background: Some(Rgb(0x28, 0x28, 0x28)),
+            scopes: [("comment".to_string(), comment)].into(),
+            ..Default::default()
+        };
+        assert_eq!(output, vec![Instruction::LoadTheme(path.clone(), theme)]);
+
+        // A malformed theme fails with the path
+        std::fs::write(&path, "background = \"dark\"").unwrap();
+        let instructions = parser::parse(&format!("theme load \"{}\"", path.display())).unwrap();
+        let err = compile(instructions).unwrap_err().to_string();
+        assert!(err.starts_with(&format!("invalid theme \"{}\"", path.display())));
+
+        // A missing theme keeps the io error as the source
+        std::fs::remove_file(&path).unwrap();
+        let instructions = parser::parse(&format!("theme load \"{}\"", path.display())).unwrap();
+        let err = compile(instructions).unwrap_err();
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn compile_undefined_ident() {
+        let instructions = parser::parse("wait 1\ntype code").unwrap();
+        let err = compile(instructions).unwrap_err();
+        assert_eq!(err.to_string(), "instruction 1 uses \"code\" before it is loaded");
+
+        // Using a variable before loading it is just as undefined
+        let dir = TempDir::new("parrot-compile-undefined-ident");
+        let path = dir.join("main.rs");
+        std::fs::write(&path, "fn main() {}").unwrap();
+
+        let load = format!("load \"{}\" as code", path.display());
+        let instructions = parser::parse(&format!("insert code\n{load}")).unwrap();
+        let err = compile(instructions).unwrap_err();
+        assert_eq!(err.to_string(), "instruction 0 uses \"code\" before it is loaded");
+
+        let output = compile_str(&format!("{load}\ninsert code"));
+        assert_eq!(output, vec![Instruction::Insert("fn main() {}".into())]);
+    }
+
+    #[test]
+    fn compile_assert_var() {
+        let dir = TempDir::new("parrot-compile-assert-var");
+        let path = dir.join("main.rs");
+        std::fs::write(&path, "fn main() {}").unwrap();
+        let load = format!("load \"{}\" as code", path.display());
+
+        let options = Options {
+            validate: true,
+            ..Default::default()
+        };
+        let instructions = parser::parse(&format!("{load}\nassert_var code \"fn main() {{}}\"")).unwrap();
+        assert!(compile_with_options(instructions, &options).unwrap().is_empty());
+
+        let instructions = parser::parse(&format!("{load}\nassert_var code \"fn a() {{}}\"")).unwrap();
+        let err = compile_with_options(instructions, &options).unwrap_err();
+        assert_eq!(err.to_string(), "\"code\" is \"fn main() {}\", expected \"fn a() {}\"");
+
+        // Outside of validate mode nothing is checked
+        let output = compile_str(&format!("{load}\nassert_var code \"fn a() {{}}\""));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn compile_set() {
+        let options = Options {
+            validate: true,
+            ..Default::default()
+        };
+        let code = "
+set i \"0\"
+repeat 3 {
+    set i = \"${i} + 1\"
+}
+assert_var i \"3\"
+set half = \"${i} / 2\"
+assert_var half \"1.5\"
+set total = \"(${i} + ${half}) * 2\"
+assert_var total \"9\"
+set name \"parrot\"
+assert_var name \"parrot\"
+set snippet \"fn main() {}\"
+assert_var snippet \"fn main() {}\"
+set first name
+set name \"echo\"
+assert_var first \"parrot\"
+";
+        let instructions = parser::parse(code).unwrap();
+        assert!(compile_with_options(instructions, &options).unwrap().is_empty());
+
+        let err = compile(parser::parse("set i \"0\"\nset x = \"1 / ${i}\"").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "invalid expression \"1 / ${i}\": division by zero");
+
+        let err = compile(parser::parse("set s \"a\"\nset x = \"${s} + 1\"").unwrap()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid expression \"${s} + 1\": \"s\" is not a number: \"a\""
+        );
+    }
+
+    #[test]
+    fn compile_backspace() {
+        let output = compile_str("backspace 3\nbackspace 0");
+        let expected = vec![Instruction::Backspace, Instruction::Backspace, Instruction::Backspace];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn variables_survive_clear() {
+        let dir = TempDir::new("parrot-variables-survive-clear");
+        let path = dir.join("main.rs");
+        std::fs::write(&path, "fn main() {}").unwrap();
+
+        // Nothing is selected after a clear either
+        let load = format!("load \"{}\" as code", path.display());
+        let output = compile_str(&format!("{load}\nselect word\nclear\ninsert code"));
+        let expected = vec![
+            Instruction::SelectWords(1),
+            Instruction::Clear,
+            Instruction::Insert("fn main() {}".into()),
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_wait_one_of() {
+        let output = compile_str("wait one_of 1 2 5");
+        let expected = vec![Instruction::WaitOneOf(vec![
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+            Duration::from_secs(5),
+        ])];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn compile_breakpoint() {
+        let output = compile_str("breakpoint\nwait 1");
+        let expected = vec![Instruction::Breakpoint, Instruction::Wait(Duration::from_secs(1))];
+        assert_eq!(output, expected);
+
+        let output = compile_str("pause\nwait 1");
+        let expected = vec![Instruction::Pause, Instruction::Wait(Duration::from_secs(1))];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn skip_failing_instruction() {
+        let output = compile_str(
+            "
+on_error skip
+load \"does/not/exist.rs\" as code
+type code
+wait 1
+",
+        );
+        let expected = vec![Instruction::OnError(OnError::Skip), Instruction::Wait(Duration::from_secs(1))];
+        assert_eq!(output, expected);
+
+        // Nothing of a failing block is left, not even the start of the loop
+        let output = compile_str(
+            "
+on_error skip
+repeat until buffer_contains \"a\" {
+    wait 1
+    type code
+}
+wait 2
+",
+        );
+        let expected = vec![Instruction::OnError(OnError::Skip), Instruction::Wait(Duration::from_secs(2))];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn warn_about_skipped_instruction() {
+        let instructions = parser::parse("on_error skip\nload \"does/not/exist.rs\" as code\nwait 1").unwrap();
+        let (output, warnings) = compile_with_warnings(instructions, &Options::default()).unwrap();
+
+        let expected = vec![
+            Instruction::OnError(OnError::Skip),
+            Instruction::Wait(Duration::from_secs(1)),
+        ];
+        assert_eq!(output, expected);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].instruction, 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            "instruction 1: skipped: failed to load \"does/not/exist.rs\""
+        );
+    }
+
+    #[test]
+    fn warn_about_forward_marker() {
+        let instructions = parser::parse("wait 1\ngoto end\ntype \"// @end\\n\"\ngoto end").unwrap();
+        let (_, warnings) = compile_with_warnings(instructions, &Options::default()).unwrap();
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].instruction, 1);
+        assert_eq!(warnings[0].message, "goto \"end\" before the marker is defined");
+    }
+
+    #[test]
+    fn halt_on_failing_instruction() {
+        let instructions = parser::parse("load \"does/not/exist.rs\" as code\nwait 1").unwrap();
+        assert!(compile(instructions).is_err());
+
+        // Only a missing file can be retried, a missing variable won't appear
+        let instructions = parser::parse("on_error retry\ntype code").unwrap();
+        let err = compile(instructions).unwrap_err();
+        assert_eq!(err.to_string(), "instruction 1 uses \"code\" before it is loaded");
+    }
 }
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use crate::error::{Error, Result};
+
+/// The number of times the body of a `repeat until` loop is played
+/// before giving up on the condition
+pub const MAX_ITERATIONS: usize = 1000;
+
+/// Counts the iterations of every `repeat until` loop during playback.
+/// A loop is identified by the index of the instruction after it.
+#[derive(Debug, Default)]
+pub struct Loops {
+    iterations: HashMap<usize, usize>,
+}
+
+impl Loops {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `true` if the loop is done, which resets it for the next time it's reached.
+    /// An error once the body has been played `MAX_ITERATIONS` times
+    /// without the condition being met.
+    pub fn done(&mut self, end: usize, condition_met: bool) -> Result<bool> {
+        if condition_met {
+            self.iterations.remove(&end);
+            return Ok(true);
+        }
+
+        let iterations = self.iterations.entry(end).or_default();
+        if *iterations == MAX_ITERATIONS {
+            self.iterations.remove(&end);
+            return Err(Error::MaxIterations(MAX_ITERATIONS));
+        }
+
+        *iterations += 1;
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn iteration_cap() {
+        let mut loops = Loops::new();
+        for _ in 0..MAX_ITERATIONS {
+            assert!(!loops.done(5, false).unwrap());
+        }
+
+        let err = loops.done(5, false).unwrap_err();
+        assert_eq!(err.to_string(), "repeat until exceeded 1000 iterations");
+
+        // Meeting the condition resets the count
+        assert!(!loops.done(9, false).unwrap());
+        assert!(loops.done(9, true).unwrap());
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+use crate::instructions::Instruction;
+
+/// The name of the marker if the line is a marker comment, e.g. `// @name`
+pub fn marker_name(line: &str) -> Option<&str> {
+    static SYMBOLS: &[&str] = &["//", "#", ";;", ";", "--"];
+
+    // If a string is less than this many bytes
+    // it could not possibly hold a marker
+    const MIN_POSSIBLE_LEN: usize = 3;
+
+    let mut line = line.trim_start();
+
+    if line.len() < MIN_POSSIBLE_LEN {
+        return None;
+    }
+
+    let symbol_len = SYMBOLS
+        .iter()
+        .find(|symbol| line.starts_with(*symbol))
+        .map(|symbol| symbol.len())?;
+
+    line = line[symbol_len..].trim();
+
+    if line.is_empty() || line.as_bytes()[0] != b'@' {
+        return None;
+    }
+
+    // Strip the marker prefix: '@'
+    line = line[1..].trim();
+
+    let end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(line.len());
+
+    Some(&line[..end])
+}
+
+/// Marker names taken by `goto` destinations, so a marker with one of them could never be jumped to
+const RESERVED_NAMES: &[&str] = &["last_type"];
+
+/// The markers defined by the text an instruction puts in the document
+fn defined_markers(instruction: &Instruction) -> impl Iterator<Item = &str> {
+    let content = match instruction {
+        Instruction::LoadTypeBuffer(content, _) | Instruction::Insert(content) | Instruction::Paste(content) => content,
+        _ => "",
+    };
+    content.lines().filter_map(marker_name)
+}
+
+/// The first marker with a reserved name, e.g. `// @last_type`
+pub(crate) fn reserved_marker(instructions: &[Instruction]) -> Option<&str> {
+    instructions
+        .iter()
+        .flat_map(defined_markers)
+        .find(|name| RESERVED_NAMES.contains(name))
+}
+
+/// Every marker that is jumped to or selected before any text defining it is typed,
+/// along with the index of the jump or selection, in the order of the jumps and selections.
+///
+/// Markers are only known once the text holding them is in the buffer,
+/// so jumping to a marker defined further down the script moves nowhere.
+pub(crate) fn forward_markers(instructions: &[Instruction]) -> Vec<(usize, &str)> {
+    let mut defined = HashSet::new();
+    let mut forward = vec![];
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        match instruction {
+            Instruction::LoadTypeBuffer(..) | Instruction::Insert(_) | Instruction::Paste(_) => {
+                defined.extend(defined_markers(instruction));
+            }
+            // A new document has no markers
+            Instruction::Clear => defined.clear(),
+            Instruction::JumpToMarker(name) if !defined.contains(name.as_str()) => forward.push((index, name.as_str())),
+            Instruction::SelectRegion { start, end } => {
+                let names = [start, end].into_iter().map(String::as_str);
+                forward.extend(names.filter(|name| !defined.contains(name)).map(|name| (index, name)));
+            }
+            _ => {}
+        }
+    }
+
+    forward
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compile;
+
+    #[test]
+    fn marker_names() {
+        assert_eq!(marker_name("  // @start"), Some("start"));
+        assert_eq!(marker_name("# @the-end here"), Some("the-end"));
+        assert_eq!(marker_name("// start"), None);
+        assert_eq!(marker_name("fn @start"), None);
+    }
+
+    #[test]
+    fn flag_forward_markers() {
+        let code = "
+type \"// @start\\nfn main() {}\\n\"
+goto start
+goto end
+type \"// @end\\n\"
+goto end
+clear
+goto start
+type \"// @main\\nfn main() {}\\n\"
+select main
+";
+        let instructions = compile(parser::parse(code).unwrap()).unwrap();
+        let expected = vec![(2, "end"), (6, "start"), (8, "main_end")];
+        assert_eq!(forward_markers(&instructions), expected);
+    }
+
+    #[test]
+    fn reserve_goto_destinations() {
+        let instructions = compile(parser::parse("type \"// @start\\n\"").unwrap()).unwrap();
+        assert_eq!(reserved_marker(&instructions), None);
+
+        let instructions = parser::parse("type \"a\"\ninsert \"// @last_type\\n\"").unwrap();
+        let err = compile(instructions).unwrap_err();
+        let expected = "the marker name \"last_type\" is reserved for goto last_type";
+        assert_eq!(err.to_string(), expected);
+    }
+}
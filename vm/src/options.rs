@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+/// Options that change how instructions are compiled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Replace tabs in the indentation of typed and inserted text with spaces
+    pub normalize_whitespace: bool,
+    /// The number of columns a tab is expanded to
+    pub tab_width: u16,
+    /// Print the variables to stderr on `context_dump`
+    pub verbose: bool,
+    /// Delete the selection before typing or inserting, replacing it
+    pub replace_selection: bool,
+    /// Check `assert_var` instructions instead of ignoring them
+    pub validate: bool,
+    /// The path of the script, `include` is relative to its directory
+    pub path: Option<PathBuf>,
+    /// The comment prefixes of included scripts
+    pub comment_prefixes: Vec<String>,
+    /// Variables set before the first instruction, e.g. from an env file
+    pub variables: Vec<(String, String)>,
+    /// Reject instructions that write to the terminal directly, like `echo_raw`
+    pub safe: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            normalize_whitespace: false,
+            tab_width: 4,
+            verbose: false,
+            replace_selection: true,
+            validate: false,
+            path: None,
+            comment_prefixes: vec![parser::DEFAULT_COMMENT_PREFIX.into()],
+            variables: vec![],
+            safe: false,
+        }
+    }
+}
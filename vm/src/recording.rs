@@ -0,0 +1,175 @@
+use std::iter::Peekable;
+use std::str::Chars;
+use std::time::Duration;
+
+/// Read the keystrokes of a recording: a JSON array of `[key, delay]` pairs,
+/// where the key is a single character and the delay is the number of
+/// milliseconds until the next key was pressed.
+///
+/// ```json
+/// [["f", 120], ["n", 85], [" ", 240]]
+/// ```
+pub(crate) fn parse_recording(content: &str) -> Result<Vec<(char, Duration)>, String> {
+    let mut chars = content.chars().peekable();
+    let mut keys = vec![];
+
+    expect(&mut chars, '[')?;
+    if !consume_if(&mut chars, ']') {
+        loop {
+            expect(&mut chars, '[')?;
+            let key = key(&mut chars)?;
+            expect(&mut chars, ',')?;
+            let delay = delay(&mut chars)?;
+            expect(&mut chars, ']')?;
+            keys.push((key, delay));
+
+            if consume_if(&mut chars, ']') {
+                break;
+            }
+            expect(&mut chars, ',')?;
+        }
+    }
+
+    match next(&mut chars) {
+        None => Ok(keys),
+        Some(c) => Err(format!("unexpected `{c}` after the recording")),
+    }
+}
+
+// The next character that isn't whitespace
+fn next(chars: &mut Peekable<Chars<'_>>) -> Option<char> {
+    chars.find(|c| !c.is_whitespace())
+}
+
+fn consume_if(chars: &mut Peekable<Chars<'_>>, expected: char) -> bool {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    chars.next_if_eq(&expected).is_some()
+}
+
+fn expect(chars: &mut Peekable<Chars<'_>>, expected: char) -> Result<(), String> {
+    match next(chars) {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("expected `{expected}`, found `{c}`")),
+        None => Err(format!("expected `{expected}`, found the end of the recording")),
+    }
+}
+
+fn key(chars: &mut Peekable<Chars<'_>>) -> Result<char, String> {
+    expect(chars, '"')?;
+
+    let mut key = String::new();
+    loop {
+        let c = match chars.next() {
+            Some('"') => break,
+            Some('\\') => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some(c @ ('"' | '\\' | '/')) => c,
+                Some('u') => unicode_escape(chars)?,
+                Some(c) => return Err(format!("unknown escape `\\{c}`")),
+                None => return Err("unterminated key".into()),
+            },
+            Some(c) => c,
+            None => return Err("unterminated key".into()),
+        };
+        key.push(c);
+    }
+
+    let mut key_chars = key.chars();
+    match (key_chars.next(), key_chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("a key is a single character, found \"{key}\"")),
+    }
+}
+
+// The character of a `\uXXXX` escape, where a character outside the
+// Basic Multilingual Plane is written as a surrogate pair: `\ud83e\udd9c`
+fn unicode_escape(chars: &mut Peekable<Chars<'_>>) -> Result<char, String> {
+    let high = code_unit(chars)?;
+    if !(0xD800..0xDC00).contains(&high) {
+        return char::from_u32(high).ok_or_else(|| format!("invalid escape `\\u{high:04x}`"));
+    }
+
+    let low = match (chars.next(), chars.next()) {
+        (Some('\\'), Some('u')) => code_unit(chars)?,
+        _ => return Err(format!("`\\u{high:04x}` is not followed by a low surrogate")),
+    };
+    if !(0xDC00..0xE000).contains(&low) {
+        return Err(format!("`\\u{low:04x}` is not a low surrogate"));
+    }
+
+    let code_point = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+    char::from_u32(code_point).ok_or_else(|| format!("invalid escape `\\u{high:04x}\\u{low:04x}`"))
+}
+
+fn code_unit(chars: &mut Peekable<Chars<'_>>) -> Result<u32, String> {
+    let hex = chars.by_ref().take(4).collect::<String>();
+    u32::from_str_radix(&hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 4 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("invalid escape `\\u{hex}`"))
+}
+
+fn delay(chars: &mut Peekable<Chars<'_>>) -> Result<Duration, String> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+
+    match digits.parse() {
+        Ok(millis) => Ok(Duration::from_millis(millis)),
+        Err(_) => Err("a delay is a whole number of milliseconds".into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_keys_and_delays() {
+        let keys = parse_recording(" [ [\"a\", 120], [\"\\n\",0],[\"\\u00e9\", 45] ]\n").unwrap();
+        let expected = vec![
+            ('a', Duration::from_millis(120)),
+            ('\n', Duration::ZERO),
+            ('é', Duration::from_millis(45)),
+        ];
+        assert_eq!(keys, expected);
+
+        assert!(parse_recording("[]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_surrogate_pairs() {
+        let keys = parse_recording("[[\"\\ud83e\\udd9c\", 60]]").unwrap();
+        assert_eq!(keys, vec![('🦜', Duration::from_millis(60))]);
+
+        let err = parse_recording("[[\"\\ud83e\", 60]]").unwrap_err();
+        assert_eq!(err, "`\\ud83e` is not followed by a low surrogate");
+        assert!(parse_recording("[[\"\\ud83e\\u0041\", 60]]").is_err());
+        assert!(parse_recording("[[\"\\udd9c\", 60]]").is_err());
+    }
+
+    #[test]
+    fn parse_malformed_recordings() {
+        let malformed = [
+            "",
+            "[",
+            "[[\"a\", 120]",
+            "[[\"ab\", 120]]",
+            "[[\"\", 120]]",
+            "[[\"a\", -1]]",
+            "[[\"a\", 1.5]]",
+            "[[\"a\"]]",
+            "[[\"a\", 120]] []",
+            "{\"a\": 120}",
+        ];
+
+        for input in malformed {
+            assert!(parse_recording(input).is_err(), "{input:?} should not parse");
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use crate::error::{Error, Result};
+
+/// The register used by `copy` and `paste` unless one is named
+pub const DEFAULT_REGISTER: &str = "\"";
+
+/// Named registers holding copied text
+#[derive(Debug, Default)]
+pub struct Registers {
+    inner: HashMap<String, String>,
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn copy(&mut self, name: impl Into<String>, content: impl Into<String>) {
+        self.inner.insert(name.into(), content.into());
+    }
+
+    pub fn paste(&self, name: &str) -> Result<&str> {
+        match self.inner.get(name) {
+            Some(content) if !content.is_empty() => Ok(content),
+            _ => Err(Error::EmptyRegister(name.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn copy_and_paste_registers() {
+        let mut registers = Registers::new();
+        registers.copy("a", "fn a() {}");
+        registers.copy("b", "fn b() {}");
+
+        assert_eq!(registers.paste("a").unwrap(), "fn a() {}");
+        assert_eq!(registers.paste("b").unwrap(), "fn b() {}");
+
+        registers.copy("a", "");
+        let err = registers.paste("a").unwrap_err();
+        assert_eq!(err.to_string(), "register \"a\" is empty");
+        assert!(registers.paste(DEFAULT_REGISTER).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use anathema::geometry::{Pos, Region, Size};
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::{Error, Result};
+
+/// The region between the anchor and the cursor, including both.
+/// The anchor can be on either side of the cursor.
+pub fn select_between(anchor: Pos, cursor: Pos) -> (Pos, Size) {
+    let from = Pos::new(anchor.x.min(cursor.x), anchor.y.min(cursor.y));
+    let to = Pos::new(anchor.x.max(cursor.x), anchor.y.max(cursor.y));
+    let size = Size::new((to.x - from.x + 1) as u16, (to.y - from.y + 1) as u16);
+    (from, size)
+}
+
+/// Where `surround` inserts the delimiters around the selection from `start` up to and
+/// including `end`, where the line of `end` is `end_width` wide.
+/// The right delimiter goes in first, right after `end` or at the end of its line,
+/// so `start` is still where the left delimiter goes.
+/// Returns where the right delimiter goes, and where the selected text starts and ends
+/// once both are inserted.
+pub fn surround_positions(start: Pos, end: Pos, end_width: usize, left: &str) -> (Pos, Pos, Pos) {
+    let right = Pos::new((end.x + 1).min(end_width as i32), end.y);
+    let shift = left.width() as i32;
+
+    let mut end = Pos::new((right.x - 1).max(0), end.y);
+    if end.y == start.y {
+        end.x += shift;
+    }
+
+    (right, Pos::new(start.x + shift, start.y), end)
+}
+
+/// Selections saved by `push_selection` until restored by `pop_selection`.
+/// Text inserted in front of a saved selection and lines removed above it move it along.
+#[derive(Debug, Default)]
+pub struct Selections {
+    saved: Vec<Region>,
+}
+
+impl Selections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, region: Region) {
+        self.saved.push(region);
+    }
+
+    pub fn pop(&mut self) -> Result<Region> {
+        self.saved.pop().ok_or(Error::EmptySelectionStack)
+    }
+
+    /// Move the saved selections after `content` was inserted at `pos`.
+    /// A selection on the same row moves along with the text after `pos`,
+    /// and a selection that `pos` is inside of grows.
+    pub fn insert(&mut self, pos: Pos, content: &str) {
+        let lines = content.matches('\n').count() as i32;
+        let last_width = content.rsplit('\n').next().unwrap_or_default().width() as i32;
+
+        for region in self.saved.iter_mut() {
+            // The last selected character, as the end of the region is exclusive
+            let last = Pos::new(region.to.x - 1, region.to.y - 1);
+            let last = shift(last, pos, lines, last_width);
+            region.from = shift(region.from, pos, lines, last_width);
+            region.to = Pos::new(last.x + 1, last.y + 1);
+        }
+    }
+
+    /// Move the saved selections after `count` lines were inserted at `pos`,
+    /// or removed if `count` is negative.
+    /// A selection on the same row only moves if the whole line moved.
+    pub fn move_lines(&mut self, pos: Pos, count: i32) {
+        for region in self.saved.iter_mut() {
+            if region.from.y > pos.y || (region.from.y == pos.y && pos.x == 0) {
+                region.from.y += count;
+                region.to.y += count;
+            }
+        }
+    }
+}
+
+// Where the character at `pos` ends up once text of `lines` newlines
+// and a last line `last_width` wide is inserted at `insert`
+fn shift(pos: Pos, insert: Pos, lines: i32, last_width: i32) -> Pos {
+    if pos.y > insert.y {
+        Pos::new(pos.x, pos.y + lines)
+    } else if pos.y == insert.y && pos.x >= insert.x {
+        let x = if lines == 0 { pos.x + last_width } else { pos.x - insert.x + last_width };
+        Pos::new(x, pos.y + lines)
+    } else {
+        pos
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn anchor_before_cursor() {
+        let (pos, size) = select_between(Pos::new(2, 1), Pos::new(5, 3));
+        assert_eq!(pos, Pos::new(2, 1));
+        assert_eq!(size, Size::new(4, 3));
+    }
+
+    #[test]
+    fn push_and_pop_selections() {
+        let mut selections = Selections::new();
+        assert!(selections.pop().is_err());
+
+        let region = Region::from((Pos::new(2, 3), Size::new(4, 2)));
+        selections.push(region);
+
+        // Reselect and edit: two lines typed above, one line joined below
+        // and one line inserted in the middle of the selected row
+        selections.move_lines(Pos::new(5, 1), 2);
+        selections.move_lines(Pos::new(0, 9), -1);
+        selections.move_lines(Pos::new(3, 5), 1);
+
+        let restored = selections.pop().unwrap();
+        assert_eq!(restored.from, Pos::new(2, 5));
+        assert_eq!(restored.to, Pos::new(6, 7));
+        assert!(selections.pop().is_err());
+    }
+
+    #[test]
+    fn move_selections_with_inserted_text() {
+        let mut selections = Selections::new();
+        selections.push(Region::from((Pos::new(4, 1), Size::new(3, 1))));
+
+        // Typed in front of the selection on the same row, then on the row above
+        selections.insert(Pos::new(0, 1), "let ");
+        selections.insert(Pos::new(2, 0), "🦜\n");
+        let restored = selections.pop().unwrap();
+        assert_eq!(restored.from, Pos::new(8, 2));
+        assert_eq!(restored.to, Pos::new(11, 3));
+
+        // Splitting the row in front of the selection moves it to the new row
+        selections.push(restored);
+        selections.insert(Pos::new(6, 2), "\n  ");
+        let restored = selections.pop().unwrap();
+        assert_eq!(restored.from, Pos::new(4, 3));
+        assert_eq!(restored.to, Pos::new(7, 4));
+
+        // Text typed inside the selection grows it, text after it is ignored
+        selections.push(restored);
+        selections.insert(Pos::new(5, 3), "ab");
+        selections.insert(Pos::new(9, 3), "c");
+        let restored = selections.pop().unwrap();
+        assert_eq!(restored.from, Pos::new(4, 3));
+        assert_eq!(restored.to, Pos::new(9, 4));
+    }
+
+    #[test]
+    fn anchor_after_cursor() {
+        let (pos, size) = select_between(Pos::new(5, 3), Pos::new(2, 3));
+        assert_eq!(pos, Pos::new(2, 3));
+        assert_eq!(size, Size::new(4, 1));
+    }
+}
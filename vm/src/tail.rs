@@ -0,0 +1,82 @@
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+/// Read lines as they are appended to a source, like `tail -f`,
+/// until the timeout is reached.
+pub struct Tail<R> {
+    reader: R,
+    // Bytes read after the last complete line, which can end in the middle of a character
+    pending: Vec<u8>,
+    deadline: Instant,
+}
+
+impl<R: Read> Tail<R> {
+    pub fn new(reader: R, timeout: Duration) -> Self {
+        Self {
+            reader,
+            pending: vec![],
+            deadline: Instant::now() + timeout,
+        }
+    }
+
+    /// All complete lines appended since the last poll,
+    /// or `None` once the timeout is reached or the source can no longer be read.
+    pub fn poll(&mut self) -> Option<String> {
+        if Instant::now() >= self.deadline {
+            return None;
+        }
+
+        self.reader.read_to_end(&mut self.pending).ok()?;
+
+        // Hold on to a partially written line until it's complete
+        let end = self.pending.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+        let lines = self.pending.drain(..end).collect::<Vec<_>>();
+        Some(String::from_utf8_lossy(&lines).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    // Every read returns the next chunk, where an empty chunk
+    // is the end of the file at that point in time
+    struct GrowingFile(VecDeque<&'static [u8]>);
+
+    impl Read for GrowingFile {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let chunk = self.0.pop_front().unwrap_or_default();
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn tail_growing_file() {
+        let file = GrowingFile([&b"first\nsec"[..], b"", b"ond\n", b"", b""].into());
+        let mut tail = Tail::new(file, Duration::from_secs(60));
+
+        assert_eq!(tail.poll().unwrap(), "first\n");
+        assert_eq!(tail.poll().unwrap(), "second\n");
+        assert_eq!(tail.poll().unwrap(), "");
+    }
+
+    #[test]
+    fn tail_character_split_across_reads() {
+        // "é" is the two bytes 0xc3 0xa9
+        let file = GrowingFile([&b"caf\xc3"[..], b"", b"\xa9\n", b""].into());
+        let mut tail = Tail::new(file, Duration::from_secs(60));
+
+        assert_eq!(tail.poll().unwrap(), "");
+        assert_eq!(tail.poll().unwrap(), "café\n");
+    }
+
+    #[test]
+    fn tail_stops_after_timeout() {
+        let file = GrowingFile([&b"line\n"[..]].into());
+        let mut tail = Tail::new(file, Duration::ZERO);
+        assert!(tail.poll().is_none());
+    }
+}
@@ -0,0 +1,30 @@
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty directory for the files of a test, removed along with everything in it
+/// when dropped, so nothing is left behind when an assertion fails
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create `name` in the system temp dir, replacing anything a previous run left there
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(name);
+        _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("failed to create the temp dir");
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        _ = std::fs::remove_dir_all(&self.0);
+    }
+}
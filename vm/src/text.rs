@@ -0,0 +1,504 @@
+use std::ops::Range;
+
+use anathema::geometry::Pos;
+use regex::Regex;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Replace every tab with enough spaces to reach the next multiple of `width`.
+/// `col` is the column the first line starts at, every other line starts at zero.
+pub fn align_tabs(content: &str, mut col: usize, width: u16) -> String {
+    // The parser rejects a width of zero, but a deserialized script could still have one
+    let width = usize::from(width.max(1));
+    let mut output = String::with_capacity(content.len());
+
+    for c in content.chars() {
+        match c {
+            '\t' => {
+                let padding = width - col % width;
+                output.extend(std::iter::repeat_n(' ', padding));
+                col += padding;
+            }
+            '\n' => {
+                output.push(c);
+                col = 0;
+            }
+            _ => {
+                output.push(c);
+                col += c.width().unwrap_or(0);
+            }
+        }
+    }
+
+    output
+}
+
+/// Replace the tabs in the leading whitespace of every line with spaces.
+/// Whitespace after the indentation is left as is.
+pub fn normalize_indentation(content: &str, tab_width: u16) -> String {
+    content
+        .split_inclusive('\n')
+        .map(|line| {
+            let text = line.trim_start_matches([' ', '\t']);
+            let indentation = &line[..line.len() - text.len()];
+            align_tabs(indentation, 0, tab_width) + text
+        })
+        .collect()
+}
+
+/// Correct the end of `typed` to `fix`, where `fix` replaces as many characters
+/// at the end of `typed` as it's long.
+/// Returns the end of `typed` to erase, and what to type in its place.
+/// Characters that are already correct are neither erased nor typed again.
+pub fn correction<'a>(typed: &'a str, fix: &'a str) -> (&'a str, &'a str) {
+    let len = fix.chars().count();
+    let start = typed.char_indices().rev().take(len).last();
+    let start = start.map(|(i, _)| i).unwrap_or(typed.len());
+    let wrong = &typed[start..];
+
+    let same: usize = wrong
+        .chars()
+        .zip(fix.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+
+    (&wrong[same..], &fix[same..])
+}
+
+/// Remove the indentation shared by all non-empty lines.
+/// The indentation is counted in characters, as it can hold multi-byte whitespace.
+pub fn dedent(content: &str) -> String {
+    let indent = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.chars().take_while(|c| c.is_whitespace()).count())
+        .min()
+        .unwrap_or(0);
+
+    content
+        .split_inclusive('\n')
+        .map(|line| match line.trim().is_empty() {
+            true => line.trim_start_matches([' ', '\t']),
+            false => line.char_indices().nth(indent).map_or("", |(start, _)| &line[start..]),
+        })
+        .collect()
+}
+
+/// Replace the indentation of every line but the first with `tab_width` spaces
+/// for every `{` that is still open, as an editor would while typing.
+/// A line starting with `}` is indented at the depth of its closing bracket.
+pub fn autoindent(content: &str, tab_width: u16) -> String {
+    let mut depth = 0usize;
+    let mut output = String::new();
+
+    for (i, line) in content.split_inclusive('\n').enumerate() {
+        let line = if i == 0 { line } else { line.trim_start_matches([' ', '\t']) };
+
+        if i > 0 && !line.trim().is_empty() {
+            let closing = line.starts_with('}') as usize;
+            let width = depth.saturating_sub(closing) * tab_width as usize;
+            output.extend(std::iter::repeat_n(' ', width));
+        }
+        output.push_str(line);
+
+        let opened = line.matches('{').count();
+        let closed = line.matches('}').count();
+        depth = (depth + opened).saturating_sub(closed);
+    }
+
+    output
+}
+
+/// Prefix every line but the first with `indent`.
+/// The first line is typed at the cursor, which is already indented.
+pub fn indent(content: &str, indent: &str) -> String {
+    let mut lines = content.split_inclusive('\n');
+    let mut output = lines.next().unwrap_or_default().to_string();
+
+    for line in lines {
+        if !line.trim().is_empty() {
+            output.push_str(indent);
+        }
+        output.push_str(line);
+    }
+
+    output
+}
+
+/// The row after moving a number of pages from `row`, clamped to the rows of the buffer.
+pub fn page_jump(row: usize, pages: i32, page_height: usize, row_count: usize) -> usize {
+    let row = row as i64 + pages as i64 * page_height as i64;
+    row.clamp(0, row_count.saturating_sub(1) as i64) as usize
+}
+
+/// The vertical viewport offset that puts `row` in the middle of the viewport.
+/// The offset is never positive, so the first line doesn't move down the screen.
+pub fn center_offset(row: usize, viewport_height: u16) -> i32 {
+    (viewport_height as i32 / 2 - row as i32).min(0)
+}
+
+/// The rows shown in teleprompter mode: the newest `lines` rows, ending at the cursor row
+pub fn teleprompter_rows(row: usize, lines: usize) -> Range<usize> {
+    let end = row + 1;
+    end.saturating_sub(lines)..end
+}
+
+/// The row and display column where `group` starts in the first match of `pattern`
+/// that includes the group.
+pub fn find_capture(content: &str, pattern: &str, group: usize) -> Option<(usize, usize)> {
+    let regex = Regex::new(pattern).ok()?;
+    let capture = regex.captures_iter(content).find_map(|captures| captures.get(group))?;
+    let start = capture.start();
+
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let row = content[..line_start].matches('\n').count();
+    Some((row, content[line_start..start].width()))
+}
+
+/// The lines from `start` to `end`, counting from 1 and including the end,
+/// or `None` if the range is empty or the content has fewer lines than `end`
+pub fn line_range(content: &str, start: usize, end: usize) -> Option<&str> {
+    let lines = content.split_inclusive('\n').collect::<Vec<_>>();
+    if start == 0 || start > end || end > lines.len() {
+        return None;
+    }
+
+    let from = lines[..start - 1].iter().map(|line| line.len()).sum::<usize>();
+    let to = from + lines[start - 1..end].iter().map(|line| line.len()).sum::<usize>();
+    Some(&content[from..to])
+}
+
+/// The cursor position after `content` has been typed at `cursor`
+pub fn end_of(cursor: Pos, content: &str) -> Pos {
+    match content.rsplit_once('\n') {
+        Some((head, last)) => {
+            let rows = head.matches('\n').count() as i32 + 1;
+            Pos::new(last.width() as i32, cursor.y + rows)
+        }
+        None => Pos::new(cursor.x + content.width() as i32, cursor.y),
+    }
+}
+
+/// The row and column of a byte offset, clamped to the end of the content.
+/// An offset inside a character is snapped to the nearest character boundary,
+/// which is reported by returning `true`.
+pub fn offset_position(content: &str, offset: usize) -> ((usize, usize), bool) {
+    let mut offset = offset.min(content.len());
+    let snapped = !content.is_char_boundary(offset);
+    if snapped {
+        let boundary = |i: &usize| content.is_char_boundary(*i);
+        let before = (0..offset).rev().find(boundary).unwrap_or(0);
+        let after = (offset..).find(boundary).unwrap_or(content.len());
+        offset = if offset - before <= after - offset { before } else { after };
+    }
+
+    let line_start = content[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let row = content[..line_start].matches('\n').count();
+    ((row, content[line_start..offset].width()), snapped)
+}
+
+/// The row and display column of every match of `needle`, in order
+pub fn match_positions(content: &str, needle: &str) -> Vec<(usize, usize)> {
+    content
+        .lines()
+        .enumerate()
+        .flat_map(|(row, line)| {
+            line.match_indices(needle)
+                .map(move |(col, _)| (row, line[..col].width()))
+        })
+        .collect()
+}
+
+/// The row and display column of the first match of `needle` on one of the `rows`
+pub fn find_in_rows(content: &str, needle: &str, rows: Range<usize>) -> Option<(usize, usize)> {
+    match_positions(content, needle)
+        .into_iter()
+        .find(|(row, _)| rows.contains(row))
+}
+
+/// The row and display column of the match of `needle` that is `count` matches away
+/// from the cursor, wrapping around at either end of the content.
+/// A negative count moves backwards.
+pub fn search_step(content: &str, cursor: (usize, usize), needle: &str, count: i32) -> Option<(usize, usize)> {
+    let matches = match_positions(content, needle);
+
+    if matches.is_empty() {
+        return None;
+    }
+
+    let len = matches.len() as i64;
+    let index = if count >= 0 {
+        let next = matches.iter().position(|&m| m > cursor).unwrap_or(0) as i64;
+        next + count.max(1) as i64 - 1
+    } else {
+        let prev = matches.iter().rposition(|&m| m < cursor).map_or(len - 1, |i| i as i64);
+        prev + count as i64 + 1
+    };
+
+    Some(matches[index.rem_euclid(len) as usize])
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The next group of text typed in a single frame when typing by word:
+/// a word followed by any punctuation and spaces.
+/// A newline is always a group of its own.
+pub fn word_group(content: &str) -> &str {
+    if content.starts_with('\n') {
+        return &content[..1];
+    }
+
+    let word_end = content.find(|c| !is_word(c)).unwrap_or(content.len());
+    let rest = &content[word_end..];
+    let punct_end = word_end + rest.find(|c: char| c.is_whitespace() || is_word(c)).unwrap_or(rest.len());
+    let rest = &content[punct_end..];
+    let space_end = punct_end + rest.find(|c: char| !c.is_whitespace() || c == '\n').unwrap_or(rest.len());
+
+    &content[..space_end]
+}
+
+/// The start and end column of `count` words, starting with the word at `col`.
+/// If `col` is not on a word the selection starts with the next word.
+pub fn word_extent(line: &str, col: usize, count: usize) -> Option<(usize, usize)> {
+    let mut words = vec![];
+    let mut start = None;
+    let mut x = 0;
+
+    for c in line.chars() {
+        match (is_word(c), start) {
+            (true, None) => start = Some(x),
+            (false, Some(from)) => {
+                words.push((from, x));
+                start = None;
+            }
+            _ => {}
+        }
+        x += c.width().unwrap_or(0);
+    }
+
+    if let Some(from) = start {
+        words.push((from, x));
+    }
+
+    let first = words.iter().position(|&(_, end)| end > col)?;
+    let last = (first + count.max(1) - 1).min(words.len() - 1);
+    Some((words[first].0, words[last].1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lines_in_range() {
+        let content = "a\nb\nc";
+        assert_eq!(line_range(content, 1, 1), Some("a\n"));
+        assert_eq!(line_range(content, 2, 3), Some("b\nc"));
+        assert_eq!(line_range(content, 3, 4), None);
+        assert_eq!(line_range(content, 0, 2), None);
+        assert_eq!(line_range(content, 3, 2), None);
+    }
+
+    #[test]
+    fn autoindent_nested_block() {
+        let content = "fn main() {\nif a {\n  b();\n    }\n\nc();\n}\n";
+        let expected = "fn main() {\n    if a {\n        b();\n    }\n\n    c();\n}\n";
+        assert_eq!(autoindent(content, 4), expected);
+
+        // Opening and closing on the same line doesn't change the depth
+        let content = "let a = A {};\nb {\n}";
+        assert_eq!(autoindent(content, 2), "let a = A {};\nb {\n}");
+    }
+
+    #[test]
+    fn land_on_capture() {
+        let content = "fn main() {\n    let value = 1;\n    let (a, b) = pair;\n}";
+        assert_eq!(find_capture(content, r"let (\w+)", 1), Some((1, 8)));
+        assert_eq!(find_capture(content, r"let (\w+)", 0), Some((1, 4)));
+
+        // The first match that includes the group
+        assert_eq!(find_capture(content, r"let (?:\w+|(\())", 1), Some((2, 8)));
+
+        assert_eq!(find_capture(content, r"struct (\w+)", 1), None);
+
+        // Wide characters before the group take two columns each
+        assert_eq!(find_capture("// 日本\nlet 語 = 1;", r"= (\d)", 1), Some((1, 9)));
+    }
+
+    #[test]
+    fn cursor_after_typing() {
+        // Where `goto last_type` returns to
+        assert_eq!(end_of(Pos::new(4, 2), "fn a()"), Pos::new(10, 2));
+        assert_eq!(end_of(Pos::new(4, 2), "{\n    b\n}"), Pos::new(1, 4));
+        assert_eq!(end_of(Pos::new(4, 2), "a\n"), Pos::new(0, 3));
+        assert_eq!(end_of(Pos::new(0, 0), "日本"), Pos::new(4, 0));
+    }
+
+    #[test]
+    fn offset_to_row_and_col() {
+        let content = "fn main() {\n    let 🐇 = 1;\n}";
+        assert_eq!(offset_position(content, 0), ((0, 0), false));
+        assert_eq!(offset_position(content, 16), ((1, 4), false));
+        assert_eq!(offset_position(content, 20), ((1, 8), false));
+        assert_eq!(offset_position(content, 30), ((2, 0), false));
+
+        // Inside the rabbit, snapping to the closest side of it
+        assert_eq!(offset_position(content, 21), ((1, 8), true));
+        assert_eq!(offset_position(content, 23), ((1, 10), true));
+
+        assert_eq!(offset_position(content, 1000), ((2, 1), false));
+    }
+
+    #[test]
+    fn every_match() {
+        let matches = match_positions("fn a() {}\n\nfn b() { fn c() {} }", "fn");
+        assert_eq!(matches, [(0, 0), (2, 0), (2, 9)]);
+
+        // Overlapping matches only count once
+        assert_eq!(match_positions("xxx\naxxa", "xx"), [(0, 0), (1, 1)]);
+        assert!(match_positions("fn a() {}", "struct").is_empty());
+
+        // Columns are display columns, a wide character taking two
+        assert_eq!(match_positions("「fn」 é fn", "fn"), [(0, 2), (0, 9)]);
+    }
+
+    #[test]
+    fn find_only_on_visible_rows() {
+        let content = "let old = 1;\nlet a = 2;\nlet b = old;\nlet c = old;";
+
+        // The match above the viewport is skipped
+        assert_eq!(find_in_rows(content, "old", 1..3), Some((2, 8)));
+        assert_eq!(find_in_rows(content, "old", 0..3), Some((0, 4)));
+        assert_eq!(find_in_rows(content, "old", 1..2), None);
+        assert_eq!(find_in_rows("a\n🐇 old", "old", 1..2), Some((1, 3)));
+    }
+
+    #[test]
+    fn search_by_count_with_wraparound() {
+        let content = "fn a() {}\nfn b() {}\n\nfn c() {}";
+
+        assert_eq!(search_step(content, (0, 0), "fn", 1), Some((1, 0)));
+        assert_eq!(search_step(content, (0, 0), "fn", 2), Some((3, 0)));
+        assert_eq!(search_step(content, (0, 0), "fn", 3), Some((0, 0)));
+        assert_eq!(search_step(content, (3, 0), "fn", 2), Some((1, 0)));
+
+        // Between two matches
+        assert_eq!(search_step(content, (1, 4), "fn", 1), Some((3, 0)));
+        assert_eq!(search_step(content, (1, 4), "fn", -1), Some((1, 0)));
+
+        assert_eq!(search_step(content, (1, 0), "fn", -1), Some((0, 0)));
+        assert_eq!(search_step(content, (1, 0), "fn", -2), Some((3, 0)));
+        assert_eq!(search_step(content, (0, 0), "fn", -4), Some((3, 0)));
+
+        assert_eq!(search_step(content, (0, 0), "struct", 1), None);
+
+        // The cursor is in display columns, like the matches
+        assert_eq!(search_step("「fn」 fn", (0, 2), "fn", 1), Some((0, 7)));
+    }
+
+    #[test]
+    fn extent_of_words() {
+        let line = "let value = foo.bar(baz);";
+        assert_eq!(word_extent(line, 5, 1), Some((4, 9)));
+        assert_eq!(word_extent(line, 5, 2), Some((4, 15)));
+
+        // Punctuation separates words
+        assert_eq!(word_extent(line, 12, 3), Some((12, 23)));
+
+        // Starting on whitespace selects the next word
+        assert_eq!(word_extent(line, 3, 1), Some((4, 9)));
+
+        // Extending past the last word stops at the end of the line
+        assert_eq!(word_extent(line, 20, 5), Some((20, 23)));
+        assert_eq!(word_extent(line, 24, 1), None);
+    }
+
+    #[test]
+    fn normalize_tab_indentation() {
+        let output = normalize_indentation("fn a() {\n\tb(\"\t\");\n  \tc();\n}", 4);
+        let expected = "fn a() {\n    b(\"\t\");\n    c();\n}";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn page_jump_with_viewport() {
+        // 100 rows with a viewport of 20 rows
+        assert_eq!(page_jump(5, 1, 20, 100), 25);
+        assert_eq!(page_jump(25, -1, 20, 100), 5);
+        assert_eq!(page_jump(90, 1, 20, 100), 99);
+        assert_eq!(page_jump(5, -1, 20, 100), 0);
+        assert_eq!(page_jump(0, 2, 20, 0), 0);
+    }
+
+    #[test]
+    fn word_groups() {
+        let mut content = "let a = foo(bar);\n    b";
+        let mut groups = vec![];
+        while !content.is_empty() {
+            let group = word_group(content);
+            content = &content[group.len()..];
+            groups.push(group);
+        }
+
+        assert_eq!(groups, vec!["let ", "a ", "= ", "foo(", "bar);", "\n", "    ", "b"]);
+    }
+
+    #[test]
+    fn correct_typed_text() {
+        assert_eq!(correction("fn mian", "main"), ("ian", "ain"));
+        assert_eq!(correction("fn main", "main"), ("", ""));
+        assert_eq!(correction("ab", "xyz"), ("ab", "xyz"));
+        assert_eq!(correction("a", ""), ("", ""));
+    }
+
+    #[test]
+    fn teleprompter_window() {
+        // Typed more lines than are shown, only the newest are visible
+        assert_eq!(teleprompter_rows(9, 3), 7..10);
+        assert_eq!(teleprompter_rows(2, 3), 0..3);
+        assert_eq!(teleprompter_rows(1, 3), 0..2);
+    }
+
+    #[test]
+    fn center_in_viewport() {
+        // Row 50 in a viewport of 20 rows is shown on row 10 of the screen
+        assert_eq!(center_offset(50, 20), -40);
+        assert_eq!(center_offset(50, 20) + 50, 10);
+        assert_eq!(center_offset(3, 20), 0);
+    }
+
+    #[test]
+    fn dedent_snippet() {
+        let output = dedent("        fn a() {\n            b();\n\n        }\n");
+        let expected = "fn a() {\n    b();\n\n}\n";
+        assert_eq!(output, expected);
+
+        // A no-break space is one character of indentation, but two bytes
+        let output = dedent("\u{a0}\u{a0}a\n \u{a0}b\n");
+        assert_eq!(output, "a\nb\n");
+    }
+
+    #[test]
+    fn reindent_snippet() {
+        let output = indent(&dedent("        fn a() {\n            b();\n        }"), "    ");
+        let expected = "fn a() {\n        b();\n    }";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn align_columns() {
+        let output = align_tabs("a\tbb\tc\nddddddddd\te", 0, 8);
+        let expected = "a       bb      c\nddddddddd       e";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn align_from_cursor_column() {
+        let output = align_tabs("a\tb", 5, 4);
+        assert_eq!(output, "a  b");
+
+        assert_eq!(align_tabs("a\tb", 5, 0), "a b");
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer};
+
+/// A color scheme for the syntax highlighting, read from a TOML file.
+/// Styles are keyed by the scope they apply to:
+///
+/// ```toml
+/// foreground = "#ebdbb2"
+/// background = "#282828"
+///
+/// [scopes]
+/// comment = { foreground = "#928374", italic = true }
+/// "keyword.control" = { foreground = "#fb4934", bold = true }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    pub foreground: Option<Rgb>,
+    pub background: Option<Rgb>,
+    #[serde(default)]
+    pub scopes: BTreeMap<String, ScopeStyle>,
+}
+
+/// The style of the text matching a scope
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScopeStyle {
+    pub foreground: Option<Rgb>,
+    pub background: Option<Rgb>,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub italic: bool,
+    #[serde(default)]
+    pub underline: bool,
+}
+
+/// A color written as `#rrggbb`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        parse_rgb(&hex).ok_or_else(|| serde::de::Error::custom(format!("\"{hex}\" is not a color like \"#rrggbb\"")))
+    }
+}
+
+fn parse_rgb(hex: &str) -> Option<Rgb> {
+    let hex = hex.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+pub(crate) fn parse_theme(content: &str) -> Result<Theme, String> {
+    toml::from_str(content).map_err(|err| err.message().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_scopes() {
+        let content = r##"
+foreground = "#ebdbb2"
+
+[scopes]
+comment = { foreground = "#928374", italic = true }
+"keyword.control" = { background = "#FB4934", bold = true }
+"##;
+        let theme = parse_theme(content).unwrap();
+        assert_eq!(theme.foreground, Some(Rgb(0xeb, 0xdb, 0xb2)));
+        assert_eq!(theme.background, None);
+
+        let comment = ScopeStyle {
+            foreground: Some(Rgb(0x92, 0x83, 0x74)),
+            italic: true,
+            ..Default::default()
+        };
+        assert_eq!(theme.scopes["comment"], comment);
+
+        let keyword = ScopeStyle {
+            background: Some(Rgb(0xfb, 0x49, 0x34)),
+            bold: true,
+            ..Default::default()
+        };
+        assert_eq!(theme.scopes["keyword.control"], keyword);
+    }
+
+    #[test]
+    fn reject_invalid_themes() {
+        assert!(parse_theme("foreground = \"ebdbb2\"").is_err());
+        assert!(parse_theme("foreground = \"#ebdbzz\"").is_err());
+        assert!(parse_theme("[scopes]\ncomment = { colour = \"#928374\" }").is_err());
+        assert!(parse_theme("[scopes").is_err());
+    }
+}
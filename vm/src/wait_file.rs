@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::instructions::Instruction;
+
+/// Wait for a file to exist until the timeout is reached.
+/// The existence check is given so it can be replaced in tests.
+pub struct WaitFile<F> {
+    path: PathBuf,
+    exists: F,
+    timeout: Duration,
+    deadline: Instant,
+}
+
+impl WaitFile<fn(&Path) -> bool> {
+    pub fn on_disk(path: PathBuf, timeout: Duration) -> Self {
+        Self::new(path, timeout, Path::exists)
+    }
+}
+
+impl<F: Fn(&Path) -> bool> WaitFile<F> {
+    pub fn new(path: PathBuf, timeout: Duration, exists: F) -> Self {
+        Self {
+            path,
+            exists,
+            timeout,
+            deadline: Instant::now() + timeout,
+        }
+    }
+
+    /// The instruction that started the wait
+    pub fn instruction(&self) -> Instruction {
+        Instruction::WaitFile {
+            path: self.path.clone(),
+            timeout: self.timeout,
+        }
+    }
+
+    /// `true` once the file exists, or an error if it still doesn't exist
+    /// once the timeout is reached.
+    pub fn poll(&self) -> Result<bool> {
+        if (self.exists)(&self.path) {
+            return Ok(true);
+        }
+
+        if Instant::now() >= self.deadline {
+            return Err(Error::Timeout(self.path.clone()));
+        }
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn wait_for_file() {
+        let checks = Cell::new(0);
+        let exists = |path: &Path| {
+            checks.set(checks.get() + 1);
+            path == Path::new("target/done") && checks.get() > 2
+        };
+
+        let wait = WaitFile::new("target/done".into(), Duration::from_secs(60), exists);
+        assert!(!wait.poll().unwrap());
+        assert!(!wait.poll().unwrap());
+        assert!(wait.poll().unwrap());
+    }
+
+    #[test]
+    fn wait_for_file_timeout() {
+        let wait = WaitFile::new("target/done".into(), Duration::ZERO, |_: &Path| false);
+        let err = wait.poll().unwrap_err();
+        assert_eq!(err.to_string(), "timed out waiting for \"target/done\"");
+    }
+}